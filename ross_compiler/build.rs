@@ -1,16 +1,21 @@
 use std::process::Command;
 
 fn main() {
-    // Client/Core.js
+    println!("cargo:rerun-if-changed=build.rs");
+
+    // Client/Core.js is only needed by the JavaScript backends.
+    if std::env::var_os("CARGO_FEATURE_JS").is_none() {
+        return;
+    }
+
     println!("cargo:rerun-if-changed=src/gen/client/core/src/*/**");
     println!("cargo:rerun-if-changed=src/gen/client/core/package.json");
-    Command::new("yarn")
-        .current_dir("src/gen/client/core")
-        .status()
-        .unwrap();
-    Command::new("yarn")
-        .arg("build")
-        .current_dir("src/gen/client/core")
-        .status()
-        .unwrap();
+    for args in &[&[][..], &["build"][..]] {
+        let status = Command::new("yarn")
+            .args(*args)
+            .current_dir("src/gen/client/core")
+            .status()
+            .expect("yarn is required to build the JavaScript backend.");
+        assert!(status.success(), "yarn {:?} failed.", args);
+    }
 }
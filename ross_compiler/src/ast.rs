@@ -1,10 +1,14 @@
 use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug)]
 pub struct Mod {
     pub structs: IndexMap<String, Struct>,
     pub actions: IndexMap<String, Action>,
     pub mods: IndexMap<String, Mod>,
+    /// Path of the file that was imported as this module, relative to the
+    /// directory of the root file.
+    pub source: Option<String>,
 }
 
 #[derive(Debug)]
@@ -14,6 +18,13 @@ pub struct Struct {
     pub fields: IndexMap<String, Type>,
     pub members: IndexMap<String, String>,
     pub type_vec: Vec<PrimitiveType>,
+    /// Content of the `///` comments before the declaration.
+    pub doc: Option<String>,
+    /// Documentation of the fields, only the documented fields are present.
+    pub field_docs: IndexMap<String, String>,
+    /// The `@` attributes of the fields, only the fields that have at least
+    /// one attribute are present.
+    pub attributes: IndexMap<String, Vec<Attribute>>,
 }
 
 #[derive(Debug)]
@@ -21,6 +32,7 @@ pub struct Action {
     pub id: u32,
     pub parameters: IndexMap<String, Type>,
     pub actions: Vec<ActionAtom>,
+    pub doc: Option<String>,
 }
 
 #[derive(Debug)]
@@ -35,6 +47,88 @@ pub enum ActionAtom {
         parameter: String,
         ty: String,
     },
+    /// Compare-And-Set a field of a referenced object.
+    Set {
+        parameter_index: usize,
+        parameter: String,
+        ty: String,
+        /// Path to the field, it has more than one item when the field belongs to
+        /// an inlined struct.
+        path: Vec<String>,
+        /// Index of the field in the flattened `type_vec` of the struct.
+        field_index: usize,
+        field_type: PrimitiveType,
+        value: ActionValue,
+    },
+    Touch {
+        parameter_index: usize,
+        parameter: String,
+        ty: String,
+    },
+    /// Fail the action if the field does not have the given value.
+    Require {
+        parameter_index: usize,
+        parameter: String,
+        ty: String,
+        path: Vec<String>,
+        field_index: usize,
+        field_type: PrimitiveType,
+        value: ActionValue,
+    },
+}
+
+/// Right-hand side of a `set` or `require` statement.
+#[derive(Debug)]
+pub enum ActionValue {
+    Parameter {
+        parameter_index: usize,
+        parameter: String,
+    },
+    Literal(Literal),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Null,
+    Bool(bool),
+    /// The number as it was written in the source, so no precision is lost
+    /// for decimals and 64-bit integers.
+    Number(String),
+    String(String),
+}
+
+/// An annotation of a struct field, it is written before the name of the field.
+/// (e.g `@min(0) @max(255) r: u32`)
+#[derive(Debug, Clone, PartialEq)]
+pub enum Attribute {
+    /// Value of the field when an inserted object does not have one.
+    Default(Literal),
+    /// Inclusive bounds of a numeric field, the numbers are kept as they were
+    /// written in the source.
+    Min(String),
+    Max(String),
+    /// Maximum number of characters of a string field.
+    MaxLen(u32),
+    Merge(MergePolicy),
+    /// Ask the server to keep an index of the objects by this field.
+    Index,
+}
+
+/// How concurrent writes to the same field are resolved.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MergePolicy {
+    /// The writes conflict with each other, this is the default.
+    Cas,
+    /// The last write wins.
+    Lww,
+}
+
+/// The argument of an attribute as it was written in the source, a literal or
+/// a bare word. (e.g `lww`)
+#[derive(Debug, Clone)]
+pub enum AttributeArgument {
+    Literal(Literal),
+    Word(String),
 }
 
 #[derive(Debug)]
@@ -44,24 +138,57 @@ pub enum Type {
     Primitive(PrimitiveType),
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum PrimitiveType {
     Null,
     Bool,
     Str,
+    /// The loosely typed number, it is stored either as an `u32` or a `f64`.
     Num,
     Hash,
+    U32,
+    I64,
+    F64,
+    /// A fixed-point decimal number with the given number of fractional digits.
+    Decimal(u8),
+}
+
+/// Maximum number of fractional digits a `decimal(n)` can have, the value is
+/// stored in an `i64` so anything above this will not fit.
+pub const MAX_DECIMAL_SCALE: u8 = 18;
+
+impl Attribute {
+    /// Name of the attribute without the `@`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Attribute::Default(_) => "default",
+            Attribute::Min(_) => "min",
+            Attribute::Max(_) => "max",
+            Attribute::MaxLen(_) => "maxlen",
+            Attribute::Merge(_) => "merge",
+            Attribute::Index => "index",
+        }
+    }
+}
+
+impl MergePolicy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MergePolicy::Cas => "cas",
+            MergePolicy::Lww => "lww",
+        }
+    }
 }
 
 pub mod builder {
     use super::*;
     use indexmap::IndexMap;
+    use std::collections::HashSet;
     use std::fmt;
 
     pub struct ASTBuilder {
         frames: Vec<State>,
         state: State,
-        path: Vec<usize>,
     }
 
     #[derive(Debug)]
@@ -74,9 +201,20 @@ pub mod builder {
         FieldNotComplete(String),
         CanNotResolveName(String),
         UnexpectedEnd,
-        IDOutOfBound(Vec<usize>, usize),
         InsertTypeError,
         DeleteTypeError,
+        InvalidDecimalScale(String),
+        Syntax(String),
+        CanNotImport(String, String),
+        ImportCycle(Vec<String>),
+        TouchTypeError,
+        FieldAccessTypeError,
+        ValueTypeError(String),
+        UnknownAttribute(String),
+        InvalidAttributeArgument(String),
+        DuplicateAttribute(String),
+        AttributeTypeError(String, String),
+        ConflictingAttributes(String),
     }
 
     impl std::error::Error for BuilderError {}
@@ -103,17 +241,57 @@ pub mod builder {
                 BuilderError::UnexpectedEnd => {
                     write!(f, "The AST-builder was terminated before completion.")
                 }
-                BuilderError::IDOutOfBound(v, i) => write!(
-                    f,
-                    "Could not pack the ID into an u32. (path={:?}, id={}.)",
-                    v, i
-                ),
                 BuilderError::InsertTypeError => {
                     write!(f, "Insert statement only accepts owned objects (not ref.)")
                 }
                 BuilderError::DeleteTypeError => {
                     write!(f, "Delete statement only accepts referenced objects.")
                 }
+                BuilderError::TouchTypeError => {
+                    write!(f, "Touch statement only accepts referenced objects.")
+                }
+                BuilderError::FieldAccessTypeError => write!(
+                    f,
+                    "Fields can only be accessed on referenced objects and must be primitives."
+                ),
+                BuilderError::ValueTypeError(field) => {
+                    write!(f, "Value does not match the type of the field '{}'.", field)
+                }
+                BuilderError::CanNotImport(path, e) => {
+                    write!(f, "Cannot import '{}': {}", path, e)
+                }
+                BuilderError::ImportCycle(files) => {
+                    write!(f, "Import cycle detected: {}", files.join(" -> "))
+                }
+                BuilderError::Syntax(e) => write!(f, "Syntax error, {}.", e),
+                BuilderError::UnknownAttribute(name) => {
+                    write!(f, "Unknown attribute '@{}'.", name)
+                }
+                BuilderError::InvalidAttributeArgument(name) => {
+                    write!(f, "Invalid argument for the attribute '@{}'.", name)
+                }
+                BuilderError::DuplicateAttribute(name) => {
+                    write!(
+                        f,
+                        "Attribute '@{}' is used more than once on a field.",
+                        name
+                    )
+                }
+                BuilderError::AttributeTypeError(name, field) => write!(
+                    f,
+                    "Attribute '@{}' does not match the type of the field '{}'.",
+                    name, field
+                ),
+                BuilderError::ConflictingAttributes(field) => write!(
+                    f,
+                    "The attributes of the field '{}' can not be satisfied together.",
+                    field
+                ),
+                BuilderError::InvalidDecimalScale(scale) => write!(
+                    f,
+                    "Invalid decimal scale '{}', it should be in range 0..={}.",
+                    scale, MAX_DECIMAL_SCALE
+                ),
             }
         }
     }
@@ -124,35 +302,48 @@ pub mod builder {
             structs: IndexMap<String, Struct>,
             actions: IndexMap<String, Action>,
             mods: IndexMap<String, Mod>,
+            source: Option<String>,
         },
         Struct {
             name: Option<String>,
-            id: u32,
             owner: Option<(String, String)>,
             fields: IndexMap<String, Type>,
             field_name: Option<String>,
             field_type: Option<Type>,
+            doc: Option<String>,
+            field_docs: IndexMap<String, String>,
+            /// Documentation of the next field.
+            field_doc: Option<String>,
+            attributes: IndexMap<String, Vec<Attribute>>,
+            /// Attributes of the next field.
+            field_attributes: Vec<Attribute>,
         },
         Action {
             name: Option<String>,
-            id: u32,
             parameters: IndexMap<String, Type>,
             actions: Vec<ActionAtom>,
             parameter_name: Option<String>,
             parameter_type: Option<Type>,
+            doc: Option<String>,
         },
     }
 
+    impl Default for ASTBuilder {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
     impl ASTBuilder {
         pub fn new() -> Self {
             ASTBuilder {
                 frames: Vec::with_capacity(5),
-                path: Vec::with_capacity(5),
                 state: State::Mod {
                     name: None,
                     structs: IndexMap::new(),
                     actions: IndexMap::new(),
                     mods: IndexMap::new(),
+                    source: None,
                 },
             }
         }
@@ -189,24 +380,34 @@ pub mod builder {
             Ok(())
         }
 
+        /// Set the file that the current module was imported from.
+        pub fn source(&mut self, path: String) -> Result<(), BuilderError> {
+            match &mut self.state {
+                State::Mod { source, .. } => {
+                    *source = Some(path);
+                    Ok(())
+                }
+                _ => Err(BuilderError::OperationOnInvalidState),
+            }
+        }
+
         pub fn enter_mod(&mut self) -> Result<(), BuilderError> {
-            let index = match &self.state {
-                State::Mod { mods, .. } => mods.len(),
-                _ => return Err(BuilderError::OperationOnInvalidState),
-            };
+            if !matches!(self.state, State::Mod { .. }) {
+                return Err(BuilderError::OperationOnInvalidState);
+            }
 
             let mut next_state = State::Mod {
                 name: None,
                 structs: IndexMap::new(),
                 actions: IndexMap::new(),
                 mods: IndexMap::new(),
+                source: None,
             };
 
             std::mem::swap(&mut next_state, &mut self.state);
             // state = self.state
             // self.state = next state
             self.frames.push(next_state);
-            self.path.push(index);
 
             Ok(())
         }
@@ -221,13 +422,14 @@ pub mod builder {
                     mods,
                     structs,
                     actions,
-                    ..
+                    source,
                 } => (
                     name.ok_or(BuilderError::MissingModName)?,
                     Mod {
                         mods,
                         structs,
                         actions,
+                        source,
                     },
                 ),
                 mut state => {
@@ -240,7 +442,6 @@ pub mod builder {
             match &mut self.state {
                 State::Mod { mods, .. } => {
                     mods.insert(name, declaration);
-                    self.path.pop().unwrap();
                     Ok(())
                 }
                 _ => unreachable!(),
@@ -248,20 +449,21 @@ pub mod builder {
         }
 
         pub fn enter_struct(&mut self) -> Result<(), BuilderError> {
-            let index = match &self.state {
-                State::Mod { structs, .. } => structs.len(),
-                _ => return Err(BuilderError::OperationOnInvalidState),
-            };
+            if !matches!(self.state, State::Mod { .. }) {
+                return Err(BuilderError::OperationOnInvalidState);
+            }
 
-            let id = pack_id(&self.path, index)
-                .ok_or_else(|| BuilderError::IDOutOfBound(self.path.clone(), index))?;
             let mut next_state = State::Struct {
                 name: None,
-                id,
                 owner: None,
                 fields: IndexMap::new(),
                 field_name: None,
                 field_type: None,
+                doc: None,
+                field_docs: IndexMap::new(),
+                field_doc: None,
+                attributes: IndexMap::new(),
+                field_attributes: Vec::new(),
             };
             std::mem::swap(&mut next_state, &mut self.state);
             self.frames.push(next_state);
@@ -276,18 +478,24 @@ pub mod builder {
             let (name, mut declaration) = match state {
                 State::Struct {
                     name,
-                    id,
                     owner,
                     fields,
+                    doc,
+                    field_docs,
+                    attributes,
                     ..
                 } => (
                     name.ok_or(BuilderError::MissingStructName)?,
                     Struct {
                         owner,
-                        id,
+                        // Assigned in `finalize`.
+                        id: 0,
                         fields,
                         members: IndexMap::new(),
                         type_vec: Vec::new(),
+                        doc,
+                        field_docs,
+                        attributes,
                     },
                 ),
                 mut state => {
@@ -298,8 +506,12 @@ pub mod builder {
             };
 
             match &mut self.state {
-                State::Mod { structs, .. } => {
-                    collect_type_vec(&structs, &declaration.fields, &mut declaration.type_vec);
+                State::Mod { structs, mods, .. } => {
+                    let scope = Scope {
+                        structs: &*structs,
+                        mods: &*mods,
+                    };
+                    collect_type_vec(scope, &declaration.fields, &mut declaration.type_vec);
                     structs.insert(name, declaration);
                     Ok(())
                 }
@@ -307,17 +519,26 @@ pub mod builder {
             }
         }
 
-        fn field_finalize(&mut self) {
+        fn field_finalize(&mut self) -> Result<(), BuilderError> {
             match &mut self.state {
                 State::Struct {
                     fields,
                     field_name,
                     field_type,
+                    attributes,
+                    field_attributes,
                     ..
                 } if field_name.is_some() && field_type.is_some() => {
-                    fields.insert(field_name.take().unwrap(), field_type.take().unwrap());
+                    let name = field_name.take().unwrap();
+                    let ty = field_type.take().unwrap();
+                    if !field_attributes.is_empty() {
+                        check_attributes(&name, &ty, field_attributes)?;
+                        attributes.insert(name.clone(), std::mem::take(field_attributes));
+                    }
+                    fields.insert(name, ty);
+                    Ok(())
                 }
-                State::Struct { .. } => {}
+                State::Struct { .. } => Ok(()),
                 _ => unreachable!(),
             }
         }
@@ -328,14 +549,20 @@ pub mod builder {
                     Err(BuilderError::FieldNotComplete(field_name.take().unwrap()))
                 }
                 State::Struct {
-                    field_name, fields, ..
+                    field_name,
+                    fields,
+                    field_docs,
+                    field_doc,
+                    ..
                 } => {
                     if fields.contains_key(&name) {
                         Err(BuilderError::NameAlreadyInUse(name))
                     } else {
+                        if let Some(doc) = field_doc.take() {
+                            field_docs.insert(name.clone(), doc);
+                        }
                         field_name.replace(name);
-                        self.field_finalize();
-                        Ok(())
+                        self.field_finalize()
                     }
                 }
                 _ => Err(BuilderError::OperationOnInvalidState),
@@ -346,7 +573,81 @@ pub mod builder {
             match &mut self.state {
                 State::Struct { field_type, .. } => {
                     field_type.replace(ty);
-                    self.field_finalize();
+                    self.field_finalize()
+                }
+                _ => Err(BuilderError::OperationOnInvalidState),
+            }
+        }
+
+        /// Add a line of documentation, the lines before the name of a struct
+        /// or an action belong to the declaration, and the rest belong to the
+        /// next field.
+        pub fn doc(&mut self, line: String) -> Result<(), BuilderError> {
+            let doc = match &mut self.state {
+                State::Struct {
+                    name: None, doc, ..
+                }
+                | State::Action {
+                    name: None, doc, ..
+                } => doc,
+                State::Struct { field_doc, .. } => field_doc,
+                _ => return Err(BuilderError::OperationOnInvalidState),
+            };
+
+            match doc {
+                Some(doc) => {
+                    doc.push('\n');
+                    doc.push_str(&line);
+                }
+                None => *doc = Some(line),
+            }
+
+            Ok(())
+        }
+
+        /// Add an attribute to the next field, the attribute is checked against
+        /// the type of the field once the field is complete.
+        pub fn attribute(
+            &mut self,
+            name: &str,
+            argument: Option<AttributeArgument>,
+        ) -> Result<(), BuilderError> {
+            let invalid = || BuilderError::InvalidAttributeArgument(name.into());
+            let attribute = match (name, argument) {
+                ("default", Some(AttributeArgument::Literal(literal))) => {
+                    Attribute::Default(literal)
+                }
+                ("min", Some(AttributeArgument::Literal(Literal::Number(n)))) => Attribute::Min(n),
+                ("max", Some(AttributeArgument::Literal(Literal::Number(n)))) => Attribute::Max(n),
+                ("maxlen", Some(AttributeArgument::Literal(Literal::Number(n)))) => {
+                    Attribute::MaxLen(n.parse().map_err(|_| invalid())?)
+                }
+                ("merge", Some(AttributeArgument::Word(policy))) => match &policy[..] {
+                    "cas" => Attribute::Merge(MergePolicy::Cas),
+                    "lww" => Attribute::Merge(MergePolicy::Lww),
+                    _ => return Err(invalid()),
+                },
+                ("index", None) => Attribute::Index,
+                ("default", _)
+                | ("min", _)
+                | ("max", _)
+                | ("maxlen", _)
+                | ("merge", _)
+                | ("index", _) => return Err(invalid()),
+                _ => return Err(BuilderError::UnknownAttribute(name.into())),
+            };
+
+            match &mut self.state {
+                State::Struct {
+                    field_attributes, ..
+                } => {
+                    if field_attributes
+                        .iter()
+                        .any(|a| a.name() == attribute.name())
+                    {
+                        return Err(BuilderError::DuplicateAttribute(name.into()));
+                    }
+                    field_attributes.push(attribute);
                     Ok(())
                 }
                 _ => Err(BuilderError::OperationOnInvalidState),
@@ -378,20 +679,17 @@ pub mod builder {
         }
 
         pub fn enter_action(&mut self) -> Result<(), BuilderError> {
-            let index = match &self.state {
-                State::Mod { actions, .. } => actions.len(),
-                _ => return Err(BuilderError::OperationOnInvalidState),
-            };
+            if !matches!(self.state, State::Mod { .. }) {
+                return Err(BuilderError::OperationOnInvalidState);
+            }
 
-            let id = pack_id(&self.path, index)
-                .ok_or_else(|| BuilderError::IDOutOfBound(self.path.clone(), index))?;
             let mut next_state = State::Action {
                 name: None,
-                id,
                 parameters: IndexMap::new(),
                 actions: vec![],
                 parameter_name: None,
                 parameter_type: None,
+                doc: None,
             };
             std::mem::swap(&mut next_state, &mut self.state);
             self.frames.push(next_state);
@@ -406,16 +704,17 @@ pub mod builder {
             let (name, declaration) = match state {
                 State::Action {
                     name,
-                    id,
                     parameters,
                     actions,
+                    doc,
                     ..
                 } => (
                     name.ok_or(BuilderError::MissingActionName)?,
                     Action {
-                        id,
+                        id: 0,
                         parameters,
                         actions,
+                        doc,
                     },
                 ),
                 mut state => {
@@ -527,6 +826,150 @@ pub mod builder {
             }
         }
 
+        pub fn touch(&mut self, parameter_name: &str) -> Result<(), BuilderError> {
+            let (parameter, ty) = self.resolve_parameter(parameter_name)?;
+
+            let action = match ty {
+                Type::ObjectRef(o) => ActionAtom::Touch {
+                    parameter_index: parameter,
+                    parameter: parameter_name.into(),
+                    ty: o.clone(),
+                },
+                _ => return Err(BuilderError::TouchTypeError),
+            };
+
+            match &mut self.state {
+                State::Action { actions, .. } => {
+                    actions.push(action);
+                    Ok(())
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        pub fn set(
+            &mut self,
+            parameter_name: &str,
+            path: Vec<String>,
+            value: ActionValue,
+        ) -> Result<(), BuilderError> {
+            let (parameter_index, ty, field_index, field_type) =
+                self.resolve_field_access(parameter_name, &path, &value)?;
+
+            let action = ActionAtom::Set {
+                parameter_index,
+                parameter: parameter_name.into(),
+                ty,
+                path,
+                field_index,
+                field_type,
+                value,
+            };
+
+            match &mut self.state {
+                State::Action { actions, .. } => {
+                    actions.push(action);
+                    Ok(())
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        pub fn require(
+            &mut self,
+            parameter_name: &str,
+            path: Vec<String>,
+            value: ActionValue,
+        ) -> Result<(), BuilderError> {
+            let (parameter_index, ty, field_index, field_type) =
+                self.resolve_field_access(parameter_name, &path, &value)?;
+
+            let action = ActionAtom::Require {
+                parameter_index,
+                parameter: parameter_name.into(),
+                ty,
+                path,
+                field_index,
+                field_type,
+                value,
+            };
+
+            match &mut self.state {
+                State::Action { actions, .. } => {
+                    actions.push(action);
+                    Ok(())
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        /// Resolve `parameter.path` to the index of the field in the flattened
+        /// data-vector and type-check the value against it.
+        fn resolve_field_access(
+            &self,
+            parameter_name: &str,
+            path: &[String],
+            value: &ActionValue,
+        ) -> Result<(usize, String, usize, PrimitiveType), BuilderError> {
+            let (parameter_index, ty) = self.resolve_parameter(parameter_name)?;
+            let struct_name = match ty {
+                Type::ObjectRef(o) => o.clone(),
+                _ => return Err(BuilderError::FieldAccessTypeError),
+            };
+
+            let (mut st, mut scope) = self.scope().find(&struct_name).unwrap();
+            let mut field_index = 0;
+            let mut field_type = None;
+            for (i, name) in path.iter().enumerate() {
+                let (index, _, ty) = st
+                    .fields
+                    .get_full(name)
+                    .ok_or_else(|| BuilderError::CanNotResolveName(name.clone()))?;
+                field_index += st
+                    .fields
+                    .values()
+                    .take(index)
+                    .map(|t| type_len(scope, t))
+                    .sum::<usize>();
+                match ty {
+                    Type::Object(o) if i + 1 < path.len() => {
+                        let (next, next_scope) = scope.find(o).unwrap();
+                        st = next;
+                        scope = next_scope;
+                    }
+                    Type::Primitive(_) | Type::ObjectRef(_) if i + 1 == path.len() => {
+                        field_type = Some(ty);
+                    }
+                    _ => return Err(BuilderError::FieldAccessTypeError),
+                }
+            }
+
+            let field_type = field_type.unwrap();
+            let matches = match (field_type, value) {
+                (_, ActionValue::Literal(Literal::Null)) => true,
+                (Type::Primitive(p), ActionValue::Literal(literal)) => literal_is_of(literal, *p),
+                (expected, ActionValue::Parameter { parameter, .. }) => {
+                    match (expected, self.resolve_parameter(parameter)?.1) {
+                        (Type::Primitive(a), Type::Primitive(b)) => a == b,
+                        (Type::ObjectRef(a), Type::ObjectRef(b)) => a == b,
+                        _ => false,
+                    }
+                }
+                _ => false,
+            };
+
+            if !matches {
+                return Err(BuilderError::ValueTypeError(path.join(".")));
+            }
+
+            let primitive = match field_type {
+                Type::Primitive(p) => *p,
+                _ => PrimitiveType::Hash,
+            };
+
+            Ok((parameter_index, struct_name, field_index, primitive))
+        }
+
         pub fn resolve_parameter(&self, n: &str) -> Result<(usize, &Type), BuilderError> {
             match &self.state {
                 State::Action { parameters, .. } => parameters
@@ -547,77 +990,142 @@ pub mod builder {
         }
 
         fn last_mod_mut(&mut self) -> &mut State {
-            match &self.state {
-                State::Mod { .. } => return &mut self.state,
-                _ => {}
-            };
+            if let State::Mod { .. } = &self.state {
+                return &mut self.state;
+            }
 
             for s in self.frames.iter_mut().rev() {
-                match &s {
-                    State::Mod { .. } => return s,
-                    _ => {}
-                };
+                if let State::Mod { .. } = &s {
+                    return s;
+                }
             }
 
             unreachable!()
         }
 
         fn last_mod(&self) -> &State {
-            match &self.state {
-                State::Mod { .. } => return &self.state,
-                _ => {}
-            };
+            if let State::Mod { .. } = &self.state {
+                return &self.state;
+            }
 
             for s in self.frames.iter().rev() {
-                match &s {
-                    State::Mod { .. } => return &s,
-                    _ => {}
-                };
+                if let State::Mod { .. } = &s {
+                    return s;
+                }
             }
 
             unreachable!()
         }
 
+        pub fn decimal(&self, scale: &str) -> Result<Type, BuilderError> {
+            match scale.parse::<u8>() {
+                Ok(n) if n <= MAX_DECIMAL_SCALE => Ok(Type::Primitive(PrimitiveType::Decimal(n))),
+                _ => Err(BuilderError::InvalidDecimalScale(scale.into())),
+            }
+        }
+
+        /// Resolve the name of an struct, names of structs in the child modules are
+        /// separated by dots. (e.g `shapes.Circle`)
         pub fn resolve_obj(&self, name: &str, is_ref: bool) -> Result<Type, BuilderError> {
+            if self.scope().find(name).is_some() {
+                Ok(if is_ref {
+                    Type::ObjectRef(name.into())
+                } else {
+                    Type::Object(name.into())
+                })
+            } else {
+                Err(BuilderError::CanNotResolveName(name.into()))
+            }
+        }
+
+        fn scope(&self) -> Scope<'_> {
             match self.last_mod() {
-                State::Mod { structs, .. } => {
-                    if structs.contains_key(name) {
-                        Ok(if is_ref {
-                            Type::ObjectRef(name.into())
-                        } else {
-                            Type::Object(name.into())
-                        })
-                    } else {
-                        Err(BuilderError::CanNotResolveName(name.into()))
-                    }
-                }
+                State::Mod { structs, mods, .. } => Scope { structs, mods },
                 _ => unreachable!(),
             }
         }
 
         pub fn finalize(self) -> Result<Mod, BuilderError> {
-            if self.frames.len() > 0 {
+            if !self.frames.is_empty() {
                 return Err(BuilderError::UnexpectedEnd);
             }
 
-            match self.state {
+            let mut root = match self.state {
                 State::Mod {
                     actions,
                     structs,
                     mods,
                     ..
-                } => Ok(Mod {
+                } => Mod {
                     actions,
                     structs,
                     mods,
-                }),
+                    source: None,
+                },
                 _ => unreachable!(),
-            }
+            };
+
+            assign_ids(&mut root);
+            Ok(root)
         }
     }
 
+    /// Assign an unique id to every struct and action in the tree.
+    ///
+    /// Declarations that fit in the old packed layout keep their packed id so
+    /// the objects which are already stored can still be decoded, the rest get
+    /// the smallest ids that are not used by anything else. The lock file can
+    /// later replace any of these.
+    fn assign_ids(root: &mut Mod) {
+        let mut structs = Vec::new();
+        let mut actions = Vec::new();
+        collect_ids(root, &mut Vec::new(), &mut structs, &mut actions);
+        allocate_ids(structs);
+        allocate_ids(actions);
+    }
+
+    fn collect_ids<'a>(
+        module: &'a mut Mod,
+        path: &mut Vec<usize>,
+        structs: &mut Vec<(&'a mut u32, Option<u32>)>,
+        actions: &mut Vec<(&'a mut u32, Option<u32>)>,
+    ) {
+        for (index, st) in module.structs.values_mut().enumerate() {
+            structs.push((&mut st.id, pack_id(path, index)));
+        }
+
+        for (index, action) in module.actions.values_mut().enumerate() {
+            actions.push((&mut action.id, pack_id(path, index)));
+        }
+
+        for (index, m) in module.mods.values_mut().enumerate() {
+            path.push(index);
+            collect_ids(m, path, structs, actions);
+            path.pop();
+        }
+    }
+
+    fn allocate_ids(ids: Vec<(&mut u32, Option<u32>)>) {
+        let mut used: HashSet<u32> = ids.iter().filter_map(|(_, packed)| *packed).collect();
+        let mut next = 0;
+        for (id, packed) in ids {
+            *id = match packed {
+                Some(packed) => packed,
+                None => {
+                    while used.contains(&next) {
+                        next += 1;
+                    }
+                    used.insert(next);
+                    next
+                }
+            };
+        }
+    }
+
+    /// The id layout used before the lock file existed, which packs the module
+    /// path and the index of the declaration into an u32.
     #[inline]
-    fn pack_id(path: &Vec<usize>, id: usize) -> Option<u32> {
+    fn pack_id(path: &[usize], id: usize) -> Option<u32> {
         if path.len() > 4 {
             return None;
         }
@@ -644,33 +1152,162 @@ pub mod builder {
         Some(ret)
     }
 
+    /// The declarations visible from a module.
+    #[derive(Clone, Copy)]
+    struct Scope<'s> {
+        structs: &'s IndexMap<String, Struct>,
+        mods: &'s IndexMap<String, Mod>,
+    }
+
+    impl<'s> Scope<'s> {
+        /// Find an struct by its (possibly dotted) name, returns the struct along
+        /// with the scope of the module that contains it.
+        fn find(self, name: &str) -> Option<(&'s Struct, Scope<'s>)> {
+            match name.find('.') {
+                None => self.structs.get(name).map(|st| (st, self)),
+                Some(i) => {
+                    let m = self.mods.get(&name[..i])?;
+                    let scope = Scope {
+                        structs: &m.structs,
+                        mods: &m.mods,
+                    };
+                    scope.find(&name[i + 1..])
+                }
+            }
+        }
+    }
+
+    /// Number of primitive values a field of the given type occupies in the
+    /// flattened data-vector.
     #[inline]
-    fn collect_type_vec(
-        structs: &IndexMap<String, Struct>,
-        fields: &IndexMap<String, Type>,
-        mut type_vec: &mut Vec<PrimitiveType>,
-    ) {
-        fn visit(
-            structs: &IndexMap<String, Struct>,
-            mut vec: &mut Vec<PrimitiveType>,
-            fields: &IndexMap<String, Type>,
-        ) {
-            for (_, ty) in fields {
-                match ty {
-                    Type::Primitive(t) => {
-                        vec.push(*t);
-                    }
-                    Type::ObjectRef(_) => {
-                        vec.push(PrimitiveType::Hash);
+    fn type_len(scope: Scope, ty: &Type) -> usize {
+        match ty {
+            Type::Object(name) => scope.find(name).unwrap().0.type_vec.len(),
+            _ => 1,
+        }
+    }
+
+    #[inline]
+    fn literal_is_of(literal: &Literal, ty: PrimitiveType) -> bool {
+        match (literal, ty) {
+            (Literal::Null, _) => true,
+            (Literal::Bool(_), PrimitiveType::Bool) => true,
+            (Literal::String(_), PrimitiveType::Str) => true,
+            (Literal::String(_), PrimitiveType::Hash) => true,
+            (Literal::Number(_), PrimitiveType::Num) => true,
+            (Literal::Number(_), PrimitiveType::F64) => true,
+            (Literal::Number(n), PrimitiveType::U32) => n.parse::<u32>().is_ok(),
+            (Literal::Number(n), PrimitiveType::I64) => n.parse::<i64>().is_ok(),
+            (Literal::Number(n), PrimitiveType::Decimal(scale)) => {
+                n.find('.').map(|i| n.len() - i - 1).unwrap_or(0) <= scale as usize
+            }
+            _ => false,
+        }
+    }
+
+    /// Check that the attributes can be used on a field of the given type, and
+    /// that the default value is in the bounds.
+    fn check_attributes(
+        field: &str,
+        ty: &Type,
+        attributes: &[Attribute],
+    ) -> Result<(), BuilderError> {
+        let primitive = match ty {
+            Type::Primitive(p) => Some(*p),
+            Type::ObjectRef(_) => None,
+            // Attributes of an inlined struct belong to its own fields.
+            Type::Object(_) => {
+                let name = attributes[0].name();
+                return Err(BuilderError::AttributeTypeError(name.into(), field.into()));
+            }
+        };
+
+        let mut min = None;
+        let mut max = None;
+        let mut max_len = None;
+        let mut default = None;
+        for attribute in attributes {
+            let valid = match (attribute, primitive) {
+                (Attribute::Merge(_), _) | (Attribute::Index, _) => true,
+                (Attribute::Default(literal), _) => {
+                    default = Some(literal);
+                    match primitive {
+                        Some(p) => literal_is_of(literal, p),
+                        None => *literal == Literal::Null,
                     }
-                    Type::Object(name) => {
-                        let st = structs.get(name).unwrap();
-                        visit(structs, &mut vec, &st.fields);
+                }
+                (Attribute::Min(n), Some(p)) | (Attribute::Max(n), Some(p)) => {
+                    let bound = n.parse::<f64>().ok();
+                    match attribute {
+                        Attribute::Min(_) => min = bound,
+                        _ => max = bound,
                     }
+                    is_numeric(p) && literal_is_of(&Literal::Number(n.clone()), p)
                 }
+                (Attribute::MaxLen(n), Some(PrimitiveType::Str)) => {
+                    max_len = Some(*n as usize);
+                    true
+                }
+                _ => false,
+            };
+
+            if !valid {
+                let name = attribute.name();
+                return Err(BuilderError::AttributeTypeError(name.into(), field.into()));
+            }
+        }
+
+        let satisfied = match (default, min, max) {
+            (_, Some(min), Some(max)) if min > max => false,
+            (Some(Literal::Number(n)), min, max) => {
+                let n = n.parse::<f64>().unwrap_or(0.0);
+                !matches!(min, Some(min) if n < min) && !matches!(max, Some(max) if n > max)
+            }
+            (Some(Literal::String(s)), _, _) => {
+                !matches!(max_len, Some(len) if s.chars().count() > len)
             }
+            _ => true,
+        };
+
+        if !satisfied {
+            return Err(BuilderError::ConflictingAttributes(field.into()));
         }
 
-        visit(structs, &mut type_vec, fields);
+        Ok(())
+    }
+
+    #[inline]
+    fn is_numeric(ty: PrimitiveType) -> bool {
+        matches!(
+            ty,
+            PrimitiveType::Num
+                | PrimitiveType::U32
+                | PrimitiveType::I64
+                | PrimitiveType::F64
+                | PrimitiveType::Decimal(_)
+        )
+    }
+
+    #[inline]
+    fn collect_type_vec(
+        scope: Scope,
+        fields: &IndexMap<String, Type>,
+        type_vec: &mut Vec<PrimitiveType>,
+    ) {
+        for (_, ty) in fields {
+            match ty {
+                Type::Primitive(t) => {
+                    type_vec.push(*t);
+                }
+                Type::ObjectRef(_) => {
+                    type_vec.push(PrimitiveType::Hash);
+                }
+                Type::Object(name) => {
+                    // Inlined structs are already flattened.
+                    let (st, _) = scope.find(name).unwrap();
+                    type_vec.extend_from_slice(&st.type_vec);
+                }
+            }
+        }
     }
 }
@@ -0,0 +1,14 @@
+use std::io;
+
+fn main() {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    std::process::exit(match ross_compiler::lsp::run(stdin.lock(), stdout.lock()) {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    });
+}
@@ -1,19 +1,16 @@
 use crate::ast;
+use crate::config::{Config, Target};
+use crate::fmt;
 use crate::gen::{self, Backend};
+use crate::lint;
+use crate::lock::Lock;
 use crate::parser;
 use clap::{App, Arg, ArgMatches, SubCommand};
-use std::fs::{self, File};
-use std::path::Path;
-use std::io::prelude::*;
+use std::path::{Path, PathBuf};
 
+#[derive(Default)]
 pub struct Cli {}
 
-impl Default for Cli {
-    fn default() -> Self {
-        Cli {}
-    }
-}
-
 impl Cli {
     fn build_app() -> clap::App<'static, 'static> {
         App::new("Ross Compiler")
@@ -39,6 +36,60 @@ impl Cli {
                         Arg::with_name("OUTDIR")
                             .help("Output directory to write the generated codes.")
                             .required(true),
+                    )
+                    .arg(
+                        Arg::with_name("target")
+                            .long("target")
+                            .help("The language of the generated client, can be repeated.")
+                            .takes_value(true)
+                            .multiple(true)
+                            .number_of_values(1)
+                            .possible_values(&["js", "dart", "schema"]),
+                    )
+                    .arg(
+                        Arg::with_name("out-name")
+                            .long("out-name")
+                            .help("Name of the generated files without the extension.")
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("indent")
+                            .long("indent")
+                            .help("Number of spaces to indent the generated code with, or 'tab'.")
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("module")
+                            .long("module")
+                            .help("Module format of the generated JavaScript.")
+                            .takes_value(true)
+                            .possible_values(&["cjs", "esm"]),
+                    )
+                    .arg(
+                        Arg::with_name("core-package")
+                            .long("core-package")
+                            .help("Import the core runtime from this package instead of embedding it.")
+                            .takes_value(true),
+                    ),
+                SubCommand::with_name("fmt")
+                    .about("Format the schema files in place.")
+                    .arg(
+                        Arg::with_name("INPUT")
+                            .help("Sets the input files to format.")
+                            .required(true)
+                            .multiple(true),
+                    )
+                    .arg(
+                        Arg::with_name("check")
+                            .long("check")
+                            .help("Only check if the files are formatted, do not write them."),
+                    ),
+                SubCommand::with_name("lint")
+                    .about("Warn about the common mistakes in the schema.")
+                    .arg(
+                        Arg::with_name("INPUT")
+                            .help("Sets the input file to use.")
+                            .required(true),
                     ),
                 SubCommand::with_name("ast")
                     .about("Prints the AST of the source file.")
@@ -55,19 +106,27 @@ impl Cli {
         let app_matches = Self::build_app().get_matches();
         match app_matches.subcommand() {
             ("ast", Some(sub)) => {
-                let ast = Cli::open(sub)?;
+                let (ast, _) = Cli::open(sub)?;
                 println!("{:#?}", ast);
                 Ok(())
             }
             ("check", Some(sub)) => {
                 Cli::open(sub)?;
-                // TODO(qti3e) Check lock.
+                Ok(())
+            }
+            ("fmt", Some(sub)) => Cli::fmt(sub),
+            ("lint", Some(sub)) => {
+                let (ast, _) = Cli::open(sub)?;
+                for warning in lint::lint(&ast) {
+                    eprintln!("warning: {}", warning);
+                }
                 Ok(())
             }
             ("gen", Some(sub)) => {
-                let ast = Cli::open(sub)?;
-                // TODO(qti3e) Check lock.
+                let (ast, lock) = Cli::open(sub)?;
                 Cli::write(ast, sub)?;
+                lock.save(&Cli::lock_path(sub))
+                    .map_err(|e| format!("{}", e))?;
                 Ok(())
             }
             _ => Err(format!(
@@ -77,15 +136,76 @@ impl Cli {
         }
     }
 
-    fn open(sub: &ArgMatches) -> Result<ast::Mod, String> {
+    fn fmt(sub: &ArgMatches) -> Result<(), String> {
+        let mut unformatted = Vec::new();
+        for input in sub.values_of("INPUT").unwrap() {
+            let source = std::fs::read_to_string(input).map_err(|e| format!("{}", e))?;
+            let formatted =
+                fmt::format(&source).map_err(|e| format!("Parse error in '{}': {}", input, e))?;
+            if formatted == source {
+                continue;
+            }
+
+            if sub.is_present("check") {
+                unformatted.push(input);
+            } else {
+                std::fs::write(input, formatted).map_err(|e| format!("{}", e))?;
+            }
+        }
+
+        if unformatted.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("Not formatted: {}", unformatted.join(", ")))
+        }
+    }
+
+    /// Parse the input and assign the ids from the lock file, the lock is
+    /// returned so it can be saved with the new declarations.
+    fn open(sub: &ArgMatches) -> Result<(ast::Mod, Lock), String> {
         let input = sub.value_of("INPUT").unwrap().to_string();
         let path = Path::new(&input);
-        let source =
-            fs::read_to_string(path).map_err(|e| format!("Cannot read the input: {}", e))?;
-        parser::parse(&source).map_err(|e| format!("Parse error: {}", e))
+        let mut ast = parser::parse_file(path).map_err(|e| format!("Parse error: {}", e))?;
+        let mut lock = Lock::open(&Cli::lock_path(sub)).map_err(|e| format!("{}", e))?;
+        lock.apply(&mut ast).map_err(|e| format!("{}", e))?;
+        Ok((ast, lock))
+    }
+
+    /// The lock file is stored next to the input. (`schema.ross` -> `schema.lock`)
+    fn lock_path(sub: &ArgMatches) -> PathBuf {
+        Path::new(sub.value_of("INPUT").unwrap()).with_extension("lock")
+    }
+
+    /// The config file is stored next to the input. (`schema.ross` -> `ross.yaml`)
+    fn config_path(sub: &ArgMatches) -> PathBuf {
+        Path::new(sub.value_of("INPUT").unwrap()).with_file_name("ross.yaml")
+    }
+
+    /// Read the config file and override it with the command line arguments.
+    fn config(sub: &ArgMatches) -> Result<Config, String> {
+        let mut config = Config::open(&Cli::config_path(sub)).map_err(|e| format!("{}", e))?;
+        if let Some(targets) = sub.values_of("target") {
+            config.targets = targets
+                .map(|t| t.parse())
+                .collect::<Result<_, _>>()?;
+        }
+        if let Some(name) = sub.value_of("out-name") {
+            config.out_name = Some(name.into());
+        }
+        if let Some(indent) = sub.value_of("indent") {
+            config.indent = Some(indent.parse()?);
+        }
+        if let Some(module) = sub.value_of("module") {
+            config.module = module.parse()?;
+        }
+        if let Some(package) = sub.value_of("core-package") {
+            config.core_package = Some(package.into());
+        }
+        Ok(config)
     }
 
     fn write(ast: ast::Mod, sub: &ArgMatches) -> Result<(), String> {
+        let config = Cli::config(sub)?;
         let dir = sub.value_of("OUTDIR").unwrap().to_string();
         let path = Path::new(&dir);
 
@@ -97,15 +217,41 @@ impl Cli {
             return Err(format!("'{}' is not a directory.", path.display()));
         }
 
-        let js_path = path.join("client.js");
-        let tsd_path = path.join("client.d.ts");
-        let mut js_file = File::create(js_path).map_err(|e| format!("{}", e))?;
-        let mut tsd_file = File::create(tsd_path).map_err(|e| format!("{}", e))?;
+        for target in config.targets() {
+            let indention = config.indention(target);
+            match target {
+                #[cfg(feature = "js")]
+                Target::Js => {
+                    let options = config.js_options();
+                    let js_path = path.join(format!("{}.js", config.out_name()));
+                    let tsd_path = path.join(format!("{}.d.ts", config.out_name()));
 
-        let js = gen::client::js::JavaScriptClientBackend::new("    ").gen(&ast);
-        let tsd = gen::client::tsd::TypeScriptClientBackend::new("    ").gen(&ast);
-        js_file.write_all(js.as_bytes()).map_err(|e| format!("{}", e))?;
-        tsd_file.write_all(tsd.as_bytes()).map_err(|e| format!("{}", e))?;
+                    let js = gen::client::js::JavaScriptClientBackend::with_options(&indention, &options)
+                        .gen(&ast);
+                    let tsd = gen::client::tsd::TypeScriptClientBackend::with_options(&indention, &options)
+                        .gen(&ast);
+                    std::fs::write(js_path, js).map_err(|e| format!("{}", e))?;
+                    std::fs::write(tsd_path, tsd).map_err(|e| format!("{}", e))?;
+                }
+                #[cfg(not(feature = "js"))]
+                Target::Js => {
+                    return Err(
+                        "The JavaScript backend is not enabled, build the compiler with `--features js`."
+                            .into(),
+                    );
+                }
+                Target::Dart => {
+                    let dart_path = path.join(format!("{}.dart", config.out_name()));
+                    let dart = gen::client::dart::DartClientBackend::new(&indention).gen(&ast);
+                    std::fs::write(dart_path, dart).map_err(|e| format!("{}", e))?;
+                }
+                Target::Schema => {
+                    let schema_path = path.join(format!("{}.schema.json", config.out_name()));
+                    let schema = gen::schema::generate(&ast);
+                    std::fs::write(schema_path, schema).map_err(|e| format!("{}", e))?;
+                }
+            }
+        }
 
         Ok(())
     }
@@ -0,0 +1,188 @@
+use crate::gen::client::{JsOptions, ModuleFormat};
+use serde::Deserialize;
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// The project config is an optional `ross.yaml` file next to the schema, it
+/// holds the default options of the `gen` command, the command line arguments
+/// take precedence over it.
+///
+/// ```yaml
+/// targets: [js, dart]
+/// out_name: client
+/// indent: 2
+/// module: esm
+/// core_package: "@ross/core"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub targets: Vec<Target>,
+    /// Name of the generated files without the extension.
+    pub out_name: Option<String>,
+    pub indent: Option<Indent>,
+    pub module: ModuleFormat,
+    pub core_package: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Target {
+    /// The JavaScript client and its TypeScript declaration file.
+    Js,
+    Dart,
+    /// The JSON description of the schema for the server-side tools.
+    Schema,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "RawIndent")]
+pub enum Indent {
+    Spaces(usize),
+    Tab,
+}
+
+/// An indent is written as the number of spaces or `tab` in the config.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawIndent {
+    Width(usize),
+    Name(String),
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    IO(String),
+    InvalidFormat(String),
+}
+
+impl std::error::Error for ConfigError {}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::IO(e) => write!(f, "Cannot access the config file: {}", e),
+            ConfigError::InvalidFormat(e) => write!(f, "Invalid config file: {}", e),
+        }
+    }
+}
+
+impl Config {
+    /// Read the config file, returns the default config if the file does not
+    /// exist.
+    pub fn open(path: &Path) -> Result<Self, ConfigError> {
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+
+        let source = fs::read_to_string(path).map_err(|e| ConfigError::IO(e.to_string()))?;
+        serde_yaml::from_str(&source).map_err(|e| ConfigError::InvalidFormat(e.to_string()))
+    }
+
+    /// The targets to generate, only JavaScript is generated by default.
+    pub fn targets(&self) -> Vec<Target> {
+        if self.targets.is_empty() {
+            vec![Target::Js]
+        } else {
+            self.targets.clone()
+        }
+    }
+
+    pub fn out_name(&self) -> &str {
+        self.out_name.as_deref().unwrap_or("client")
+    }
+
+    /// The indention of the target, each target defaults to the conventional
+    /// indention of its language.
+    pub fn indention(&self, target: Target) -> String {
+        match (self.indent, target) {
+            (Some(Indent::Tab), _) => "\t".into(),
+            (Some(Indent::Spaces(n)), _) => " ".repeat(n),
+            (None, Target::Js) => " ".repeat(4),
+            (None, _) => " ".repeat(2),
+        }
+    }
+
+    pub fn js_options(&self) -> JsOptions {
+        JsOptions {
+            module: self.module,
+            core_package: self.core_package.clone(),
+        }
+    }
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "js" => Ok(Target::Js),
+            "dart" => Ok(Target::Dart),
+            "schema" => Ok(Target::Schema),
+            _ => Err(format!("Unknown target '{}'.", s)),
+        }
+    }
+}
+
+impl FromStr for Indent {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse::<usize>() {
+            Ok(n) => Indent::try_from(RawIndent::Width(n)),
+            Err(_) => Indent::try_from(RawIndent::Name(s.into())),
+        }
+    }
+}
+
+impl TryFrom<RawIndent> for Indent {
+    type Error = String;
+
+    fn try_from(raw: RawIndent) -> Result<Self, Self::Error> {
+        match raw {
+            RawIndent::Width(n) if n > 0 && n <= 8 => Ok(Indent::Spaces(n)),
+            RawIndent::Name(name) if name == "tab" => Ok(Indent::Tab),
+            RawIndent::Width(n) => Err(format!(
+                "Indent must be between 1 and 8 spaces, found {}.",
+                n
+            )),
+            RawIndent::Name(name) => Err(format!(
+                "Indent must be a number or 'tab', found '{}'.",
+                name
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let config: Config = serde_yaml::from_str(
+            "targets: [js, dart]\nout_name: schema\nindent: tab\nmodule: esm\ncore_package: \"@ross/core\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.targets(), vec![Target::Js, Target::Dart]);
+        assert_eq!(config.out_name(), "schema");
+        assert_eq!(config.indention(Target::Dart), "\t");
+        assert_eq!(config.module, ModuleFormat::Esm);
+        assert_eq!(config.core_package.as_deref(), Some("@ross/core"));
+
+        let config: Config = serde_yaml::from_str("indent: 2\n").unwrap();
+        assert_eq!(config.targets(), vec![Target::Js]);
+        assert_eq!(config.out_name(), "client");
+        assert_eq!(config.indention(Target::Js), "  ");
+        assert_eq!(config.module, ModuleFormat::Cjs);
+
+        assert!(serde_yaml::from_str::<Config>("indent: 0\n").is_err());
+        assert!(serde_yaml::from_str::<Config>("target: js\n").is_err());
+        assert_eq!("tab".parse::<Indent>(), Ok(Indent::Tab));
+        assert_eq!("3".parse::<Indent>(), Ok(Indent::Spaces(3)));
+        assert!("x".parse::<Indent>().is_err());
+    }
+}
@@ -0,0 +1,503 @@
+use crate::gen::writer::Writer;
+use crate::parser::{self, Rule};
+use pest::iterators::Pair;
+use std::fmt::Write;
+
+/// Pretty-print the source of a schema file, the comments are kept where they
+/// were (before or after the item they belong to.)
+pub fn format(source: &str) -> Result<String, String> {
+    let pairs = parser::parse_program(source).map_err(|e| format!("{}", e))?;
+    let mut f = Formatter {
+        source,
+        w: Writer::new("    "),
+        pos: 0,
+        first: true,
+    };
+
+    for pair in pairs {
+        match pair.as_rule() {
+            Rule::EOI => f.leading(source.len(), false),
+            _ => f.declaration(pair),
+        }
+    }
+
+    let result = f.w.result();
+    Ok(format!("{}\n", result.trim_end()))
+}
+
+struct Formatter<'i> {
+    source: &'i str,
+    w: Writer,
+    /// End of the last item that was written, everything before this position
+    /// is already in the output.
+    pos: usize,
+    /// Whether nothing is written in the current block yet.
+    first: bool,
+}
+
+impl<'i> Formatter<'i> {
+    fn declaration(&mut self, pair: Pair<'i, Rule>) {
+        let span = pair.as_span();
+        self.leading(span.start(), true);
+
+        match pair.as_rule() {
+            Rule::import_declaration => {
+                let mut inner = pair.into_inner();
+                let path = inner.next().unwrap().as_str();
+                let name = inner.next().unwrap().as_str();
+                write!(self.w, "import {} as {};", path, name).unwrap();
+                self.inline(span.start(), span.end());
+            }
+            Rule::mod_declaration => {
+                let mut inner = pair.into_inner();
+                let name = inner.next().unwrap();
+                write!(self.w, "mod {} {{", name.as_str()).unwrap();
+                self.header_comments(span.start(), name.as_span().end());
+                self.enter_block();
+                for pair in inner {
+                    self.declaration(pair);
+                }
+                self.exit_block(span.end() - 1);
+            }
+            Rule::struct_declaration => {
+                let mut fields = Vec::new();
+                let mut header_end = 0;
+                let mut owner = None;
+                let mut field_name = None;
+                let mut field_docs = Vec::new();
+                let mut attributes = Vec::new();
+                for pair in pair.into_inner() {
+                    match pair.as_rule() {
+                        Rule::doc_comment if header_end == 0 => self.doc(pair.as_str()),
+                        Rule::doc_comment => field_docs.push(pair),
+                        Rule::struct_name => {
+                            header_end = pair.as_span().end();
+                            write!(self.w, "struct {}", pair.as_str()).unwrap();
+                        }
+                        Rule::owner_name => owner = Some(pair.as_str()),
+                        Rule::owner_field_name => {
+                            header_end = pair.as_span().end();
+                            write!(self.w, " in {} as .{}", owner.unwrap(), pair.as_str()).unwrap();
+                        }
+                        Rule::field_attribute => attributes.push(pair),
+                        Rule::struct_field_name => field_name = Some(pair),
+                        Rule::struct_field_type => {
+                            let name: Pair<Rule> = field_name.take().unwrap();
+                            let start = field_docs
+                                .first()
+                                .or_else(|| attributes.first())
+                                .unwrap_or(&name)
+                                .as_span()
+                                .start();
+                            let end = pair.as_span().end();
+                            let mut text = String::new();
+                            for doc in field_docs.drain(..) {
+                                text.push_str(doc.as_str().trim_end());
+                                text.push('\n');
+                            }
+                            for pair in attributes.drain(..) {
+                                text.push_str(&attribute(pair));
+                                text.push(' ');
+                            }
+                            write!(text, "{}: {},", name.as_str(), ty(pair)).unwrap();
+                            fields.push((start, end, text));
+                        }
+                        _ => unreachable!(),
+                    }
+                }
+                self.block(span.start(), header_end, span.end() - 1, fields);
+            }
+            Rule::action_declaration => {
+                let mut parameters = Vec::new();
+                let mut statements = Vec::new();
+                let mut header_end = 0;
+                let mut parameter_name = None;
+                for pair in pair.into_inner() {
+                    match pair.as_rule() {
+                        Rule::doc_comment => self.doc(pair.as_str()),
+                        Rule::action_name => {
+                            header_end = pair.as_span().end();
+                            write!(self.w, "action {}(", pair.as_str()).unwrap();
+                        }
+                        Rule::parameter_name => parameter_name = Some(pair.as_str()),
+                        Rule::parameter_type => {
+                            header_end = pair.as_span().end();
+                            parameters.push(format!("{}: {}", parameter_name.unwrap(), ty(pair)));
+                        }
+                        _ => {
+                            let start = pair.as_span().start();
+                            let end = pair.as_span().end();
+                            statements.push((start, end, format!("{};", statement(pair))));
+                        }
+                    }
+                }
+                write!(self.w, "{})", parameters.join(", ")).unwrap();
+                self.block(span.start(), header_end, span.end() - 1, statements);
+            }
+            _ => unreachable!(),
+        }
+
+        self.pos = span.end();
+        self.trailing();
+        self.w.write("\n");
+        self.first = false;
+    }
+
+    /// Write the body of a struct or an action, the header (everything before
+    /// the `{`) must already be written.
+    fn block(&mut self, start: usize, header_end: usize, close: usize, items: Vec<Item>) {
+        let open = self.open_brace(header_end);
+        if items.is_empty() && comments(self.source, open, close).is_empty() {
+            self.w.write(" {}");
+            self.inline(start, open);
+            return;
+        }
+
+        self.w.write(" {");
+        self.header_comments(start, header_end);
+        self.enter_block();
+        for (start, end, text) in items {
+            self.leading(start, false);
+            self.w.write(&text[..]);
+            self.inline(start, end);
+            self.pos = end;
+            self.trailing();
+            self.w.write("\n");
+            self.first = false;
+        }
+        self.exit_block(close);
+    }
+
+    /// Write the comments before the `{` of a declaration next to it, along
+    /// with the comments that are on the same line as the `{`.
+    fn header_comments(&mut self, start: usize, header_end: usize) {
+        let open = self.open_brace(header_end);
+        self.inline(start, open);
+        self.pos = open + 1;
+        self.trailing();
+    }
+
+    fn enter_block(&mut self) {
+        self.w.write("\n");
+        self.w.indent();
+        self.first = true;
+    }
+
+    fn exit_block(&mut self, close: usize) {
+        self.leading(close, false);
+        self.w.dedent();
+        self.w.write("}");
+        self.first = false;
+    }
+
+    /// Position of the `{` that comes after the given position.
+    fn open_brace(&self, from: usize) -> usize {
+        let mut pos = from;
+        loop {
+            let rest = &self.source[pos..];
+            if rest.starts_with("/*") {
+                pos += comment_len(rest);
+            } else if rest.starts_with('{') {
+                return pos;
+            } else {
+                pos += rest.chars().next().unwrap().len_utf8();
+            }
+        }
+    }
+
+    /// Write the comments that are between the last written item and the next
+    /// one each on its own line, blank lines from the source are preserved and
+    /// when `separate` is set the next item is always preceded by a blank line.
+    fn leading(&mut self, to: usize, mut separate: bool) {
+        for (start, end) in comments(self.source, self.pos, to) {
+            if !self.first && (separate || blank_line(&self.source[self.pos..start])) {
+                self.w.write("\n");
+            }
+            self.comment(start, end);
+            self.w.write("\n");
+            self.pos = end;
+            self.first = false;
+            separate = false;
+        }
+
+        if !self.first && (separate || blank_line(&self.source[self.pos..to])) {
+            self.w.write("\n");
+        }
+        self.pos = to;
+    }
+
+    /// Write the comments that directly follow the last written item on the
+    /// same line, the comments after the next item on the line belong to it.
+    fn trailing(&mut self) {
+        loop {
+            let rest = &self.source[self.pos..];
+            let gap = rest
+                .find(|c: char| c == '\n' || !(c.is_whitespace() || c == ',' || c == ';'))
+                .unwrap_or(rest.len());
+            if !rest[gap..].starts_with("/*") {
+                break;
+            }
+            let start = self.pos + gap;
+            let end = start + comment_len(&rest[gap..]);
+            self.w.write(" ");
+            self.comment(start, end);
+            self.pos = end;
+        }
+    }
+
+    /// Write all of the comments in the given range on the current line.
+    fn inline(&mut self, from: usize, to: usize) {
+        for (start, end) in comments(self.source, from, to) {
+            self.w.write(" ");
+            self.comment(start, end);
+        }
+    }
+
+    fn doc(&mut self, comment: &str) {
+        self.w.write(comment.trim_end());
+        self.w.write("\n");
+    }
+
+    fn comment(&mut self, start: usize, end: usize) {
+        for (i, line) in self.source[start..end].lines().enumerate() {
+            if i > 0 {
+                self.w.write("\n ");
+            }
+            self.w.write(line.trim());
+        }
+    }
+}
+
+/// Start, end and the formatted text of an item in a block.
+type Item = (usize, usize, String);
+
+/// Find the comments in the given range, doc comments are skipped since they
+/// are part of the items, and so are the string literals.
+fn comments(source: &str, from: usize, to: usize) -> Vec<(usize, usize)> {
+    let mut result = Vec::new();
+    let mut pos = from;
+    while pos < to {
+        let rest = &source[pos..];
+        if rest.starts_with('"') {
+            pos += string_len(rest);
+        } else if rest.starts_with("///") {
+            pos += rest.find('\n').unwrap_or(rest.len());
+        } else if rest.starts_with("/*") {
+            let end = pos + comment_len(rest);
+            result.push((pos, end));
+            pos = end;
+        } else {
+            pos += rest.chars().next().unwrap().len_utf8();
+        }
+    }
+    result
+}
+
+/// Length of the block comment at the start of the text.
+fn comment_len(text: &str) -> usize {
+    text.find("*/").map_or(text.len(), |n| n + 2)
+}
+
+/// Length of the string literal at the start of the text, including the quotes.
+fn string_len(text: &str) -> usize {
+    let mut escaped = false;
+    for (i, c) in text.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => return i + 1,
+            _ => {}
+        }
+    }
+    text.len()
+}
+
+#[inline]
+fn blank_line(gap: &str) -> bool {
+    gap.matches('\n').count() > 1
+}
+
+fn ty(pair: Pair<Rule>) -> String {
+    let pair = pair.into_inner().next().unwrap();
+    match pair.as_rule() {
+        Rule::ref_type => format!("ref {}", pair.into_inner().next().unwrap().as_str()),
+        Rule::primitive_type => match pair.clone().into_inner().next() {
+            Some(decimal) => {
+                let scale = decimal.into_inner().next().unwrap();
+                format!("decimal({})", scale.as_str())
+            }
+            None => pair.as_str().into(),
+        },
+        _ => pair.as_str().into(),
+    }
+}
+
+fn attribute(pair: Pair<Rule>) -> String {
+    let mut inner = pair.into_inner();
+    let name = inner.next().unwrap().as_str();
+    match inner.next() {
+        Some(argument) => format!("@{}({})", name, argument.as_str()),
+        None => format!("@{}", name),
+    }
+}
+
+fn statement(pair: Pair<Rule>) -> String {
+    let keyword = match pair.as_rule() {
+        Rule::insert_action => "insert",
+        Rule::delete_action => "delete",
+        Rule::touch_action => "touch",
+        Rule::set_action => "set",
+        Rule::require_action => "require",
+        _ => unreachable!(),
+    };
+
+    let mut inner = pair.into_inner();
+    let target = inner.next().unwrap().as_str();
+    match inner.next() {
+        Some(value) => {
+            let operator = if keyword == "set" { "=" } else { "==" };
+            format!("{} {} {} {}", keyword, target, operator, value.as_str())
+        }
+        None => format!("{} {}", keyword, target),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::format;
+
+    #[test]
+    fn layout() {
+        let source = r#"/* Shapes. */
+import   "shapes.ross" as shapes ;
+mod m{struct P{x:num,y:decimal( 2 )}}
+struct Scene { title: str, }
+struct RGB { r: num, g: num, b: num }
+struct Empty {}
+struct Box in Scene as .boxes { color: RGB, p: ref m.P }
+action add ( s : ref Scene , b : Box ) { insert b ; set s.title = "x" ; }
+"#;
+        let expected = r#"/* Shapes. */
+import "shapes.ross" as shapes;
+
+mod m {
+    struct P {
+        x: num,
+        y: decimal(2),
+    }
+}
+
+struct Scene {
+    title: str,
+}
+
+struct RGB {
+    r: num,
+    g: num,
+    b: num,
+}
+
+struct Empty {}
+
+struct Box in Scene as .boxes {
+    color: RGB,
+    p: ref m.P,
+}
+
+action add(s: ref Scene, b: Box) {
+    insert b;
+    set s.title = "x";
+}
+"#;
+        assert_eq!(format(source).unwrap(), expected);
+        assert_eq!(format(expected).unwrap(), expected);
+    }
+
+    #[test]
+    fn comments() {
+        let source = r#"/* Header. */
+
+/*
+ * A point.
+ */
+struct Point { /* Inline. */
+  x: num, /* Trailing. */
+
+  /* Leading. */
+  y: /* Inside. */ num
+  /* Last. */
+} /* After. */
+action a() { /* Empty. */ }
+"#;
+        let expected = r#"/* Header. */
+
+/*
+ * A point.
+ */
+struct Point { /* Inline. */
+    x: num, /* Trailing. */
+
+    /* Leading. */
+    y: num, /* Inside. */
+    /* Last. */
+} /* After. */
+
+action a() { /* Empty. */
+}
+"#;
+        assert_eq!(format(source).unwrap(), expected);
+        assert_eq!(format(expected).unwrap(), expected);
+    }
+
+    #[test]
+    fn docs() {
+        let source = "/// A point.   \n  /// See `Line`.\nstruct Point { /// X.\n x: num, y: num, /// Z, not /* a comment */.\n z: num }\n/// Move.\naction m() {}\n";
+        let expected = r#"/// A point.
+/// See `Line`.
+struct Point {
+    /// X.
+    x: num,
+    y: num,
+    /// Z, not /* a comment */.
+    z: num,
+}
+
+/// Move.
+action m() {}
+"#;
+        assert_eq!(format(source).unwrap(), expected);
+        assert_eq!(format(expected).unwrap(), expected);
+    }
+
+    #[test]
+    fn attributes() {
+        let source = "struct Item { /* Title. */ @ default( \"x\" )\n@index title: str, /// Price.\n@min(0)@merge( lww ) price: decimal(2) }\n";
+        let expected = r#"struct Item { /* Title. */
+    @default("x") @index title: str,
+    /// Price.
+    @min(0) @merge(lww) price: decimal(2),
+}
+"#;
+        assert_eq!(format(source).unwrap(), expected);
+        assert_eq!(format(expected).unwrap(), expected);
+    }
+
+    #[test]
+    fn string_literals() {
+        let source = r#"struct S { @default("/*") t: str, @default("a \" /* b") u: str }
+action a(s: ref S) { set s.t = "/*"; require s.u == "/* x */"; /* Real. */ }
+"#;
+        let expected = r#"struct S {
+    @default("/*") t: str,
+    @default("a \" /* b") u: str,
+}
+
+action a(s: ref S) {
+    set s.t = "/*";
+    require s.u == "/* x */"; /* Real. */
+}
+"#;
+        assert_eq!(format(source).unwrap(), expected);
+        assert_eq!(format(expected).unwrap(), expected);
+    }
+}
@@ -3,7 +3,10 @@ import ts from "@wessberg/rollup-plugin-ts";
 const config = [
   {
     input: "./src/lib.ts",
-    output: [{ file: "dist/bundle.js", format: "commonjs" }],
+    output: [
+      { file: "dist/bundle.js", format: "commonjs" },
+      { file: "dist/bundle.mjs", format: "es" }
+    ],
     plugins: [ts({})]
   }
 ];
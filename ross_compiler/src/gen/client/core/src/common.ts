@@ -5,6 +5,19 @@ import type { Snapshot } from "./snapshot";
  */
 export type Hash16 = string;
 
+/**
+ * A fixed-point decimal number, it is kept as a string in the client so that
+ * no precision is lost, e.g `"12.50"`.
+ */
+export type Decimal = string;
+
+/**
+ * The primitives that JSON numbers can not hold without rounding are sent as
+ * tagged strings, e.g `{ "i64": "-1" }` and `{ "decimal": "12.50" }`, in the
+ * client they are a `bigint` and a `Decimal`.
+ */
+export type TaggedValue = { i64: string } | { decimal: Decimal };
+
 /**
  * A pointer to an object that is stored on the server.
  */
@@ -30,7 +43,12 @@ export interface Ref<T extends RossStruct> {
 /**
  * Any primitive value in ROSS.
  */
-export type PrimitiveValue = boolean | string | number | Hash16;
+export type PrimitiveValue =
+  | boolean
+  | string
+  | number
+  | Hash16
+  | TaggedValue;
 
 /**
  * In ROSS (core), fields do not actually exists and all of the objects are
@@ -59,7 +77,11 @@ export type Field =
   // Inline struct
   | [string, StructConstructor]
   // Ref<T>
-  | [string];
+  | [string]
+  // A primitive that is sent as a tagged string.
+  | [string, TaggedKind];
+
+export type TaggedKind = "i64" | "decimal";
 
 /**
  * Common methods on every struct.
@@ -92,6 +114,12 @@ export abstract class RossStruct {
    * @internal
    */
   abstract getPathFor(fieldId: number): string[];
+  /**
+   * @param fieldId Index of the field when the data is flattened.
+   * @returns The kind of the field if it is sent as a tagged string.
+   * @internal
+   */
+  abstract getKindFor(fieldId: number): TaggedKind | undefined;
   /**
    * Encode this object as an array of primitive values.
    * @param ownerId Provided to contained objects that their owner is not
@@ -6,28 +6,40 @@ import {
   PrimitiveValue,
   Ref,
   Hash16,
+  TaggedKind,
 } from "./common";
 
 // This file contains the functions used to generate the classes and other
 // stuff dynamically at the runtime.
 
+interface FlatFields {
+  paths: string[][];
+  kinds: (TaggedKind | undefined)[];
+}
+
 /**
- * Get a list of fields and return an array of the path to each field.
+ * Get a list of fields and return an array of the path to each field, along
+ * with the kind of the fields that are sent as tagged strings.
  * # Example
  * ```js
  * // struct Point2D {x: num, y: num}
- * // struct X {pos: Point2D, size: num}
- * flattenFields([['pos', ['x', 'y']], 'size']);
- * // -> [['pos', 'x'], ['pos', 'y'], ['size']]
+ * // struct X {pos: Point2D, size: i64}
+ * flattenFields([['pos', ['x', 'y']], ['size', 'i64']]);
+ * // -> paths: [['pos', 'x'], ['pos', 'y'], ['size']]
+ * // -> kinds: [undefined, undefined, 'i64']
  * ```
  * @param fields List of the fields of an struct.
  */
-function flattenFields(fields: Field[]): string[][] {
-  const result = [];
+function flattenFields(fields: Field[]): FlatFields {
+  const result: FlatFields = { paths: [], kinds: [] };
 
   function write(path: string[], field: Field) {
-    if (typeof field === "string" || field[1] === undefined) {
-      result.push([...path, field]);
+    if (typeof field === "string") {
+      result.paths.push([...path, field]);
+      result.kinds.push(undefined);
+    } else if (field[1] === undefined || typeof field[1] === "string") {
+      result.paths.push([...path, field[0]]);
+      result.kinds.push(field[1]);
     } else {
       const newPath = [...path, field[0]];
       const fields = field[1].$;
@@ -57,7 +69,7 @@ export function c(
   ownerField?: string
 ) {
   class Struct extends RossStruct {
-    static flattenCache: string[][] | undefined;
+    static flattenCache: FlatFields | undefined;
     static readonly $ = fields;
     private _alreadyInOwner: boolean;
 
@@ -135,7 +147,12 @@ export function c(
 
     getPathFor(fieldId: number): string[] {
       if (!Struct.flattenCache) Struct.flattenCache = flattenFields(fields);
-      return Struct.flattenCache[fieldId];
+      return Struct.flattenCache.paths[fieldId];
+    }
+
+    getKindFor(fieldId: number): TaggedKind | undefined {
+      if (!Struct.flattenCache) Struct.flattenCache = flattenFields(fields);
+      return Struct.flattenCache.kinds[fieldId];
     }
 
     encode(ownerId?: Hash16, buffer?: ObjectRawData): ObjectRawData {
@@ -145,6 +162,8 @@ export function c(
         const field = fields[i];
         if (typeof field === "string") {
           buffer.push(this[field]);
+        } else if (typeof field[1] === "string") {
+          buffer.push(encodeValue(field[1], this[field[0]]));
         } else if (field[1] === undefined) {
           if (ownerId && i === 0) {
             buffer.push(ownerId);
@@ -165,6 +184,8 @@ export function c(
         const field = fields[i];
         if (typeof field === "string") {
           values.push(iter.next().value);
+        } else if (typeof field[1] === "string") {
+          values.push(decodeValue(field[1], iter.next().value));
         } else if (field[1] === undefined) {
           const id = iter.next().value;
           if (typeof id !== "string") throw new TypeError("Expected Hash16.");
@@ -181,6 +202,30 @@ export function c(
   ns._[id] = ns[name] = Struct;
 }
 
+/**
+ * Convert a runtime value to the tagged form that is sent to the server, an
+ * i64 is a `bigint` and a decimal is a string, `null` is kept as is.
+ * @param kind Kind of the field.
+ * @param value The runtime value.
+ */
+function encodeValue(kind: TaggedKind, value: any): PrimitiveValue {
+  if (value === null || value === undefined) return value;
+  if (kind === "i64") return { i64: BigInt(value).toString() };
+  return { decimal: String(value) };
+}
+
+/**
+ * The inverse of `encodeValue`, plain numbers are accepted too.
+ * @param kind Kind of the field.
+ * @param value The value as it was sent by the server.
+ */
+function decodeValue(kind: TaggedKind, value: any): any {
+  if (value === null || value === undefined) return value;
+  const raw = typeof value === "object" ? value[kind] : value;
+  if (kind === "i64") return BigInt(raw);
+  return String(raw);
+}
+
 /**
  * Create an insert patch.
  * @param obj The object to insert.
@@ -253,3 +298,84 @@ export function d(ref: Ref<RossStruct>): Patch[] {
 
   return patches;
 }
+
+/**
+ * Read the current primitive value of a field of the referenced object.
+ * @param ref Reference to the object.
+ * @param fieldId Index of the field when the data is flattened.
+ */
+function readField(ref: Ref<RossStruct>, fieldId: number): PrimitiveValue {
+  let value: any = ref.data;
+  const path = ref.data.getPathFor(fieldId);
+  for (let i = 0, n = path.length; i < n; ++i) value = value[path[i]];
+  return toPrimitive(ref, fieldId, value);
+}
+
+/**
+ * Convert a value of a field to the form that is sent to the server, refs are
+ * stored as the id of the object they point to.
+ * @param ref Reference to the object.
+ * @param fieldId Index of the field when the data is flattened.
+ * @param value The runtime value.
+ */
+function toPrimitive(
+  ref: Ref<RossStruct>,
+  fieldId: number,
+  value: any
+): PrimitiveValue {
+  const kind = ref.data.getKindFor(fieldId);
+  if (kind) return encodeValue(kind, value);
+  if (value !== null && typeof value === "object") return value.id;
+  return value;
+}
+
+/**
+ * Create a CAS patch that sets a field of the referenced object.
+ * @param ref Reference to the object.
+ * @param fieldId Index of the field when the data is flattened.
+ * @param value The new value.
+ */
+export function s(ref: Ref<RossStruct>, fieldId: number, value: any): Patch[] {
+  return [
+    {
+      type: "cas",
+      id: ref.id,
+      field: fieldId + 1,
+      base: readField(ref, fieldId),
+      target: toPrimitive(ref, fieldId, value),
+    },
+  ];
+}
+
+/**
+ * Create a touch patch, used to ensure the object still exists.
+ * @param ref Reference to the object.
+ */
+export function t(ref: Ref<RossStruct>): Patch[] {
+  return [
+    {
+      type: "touch",
+      id: ref.id,
+    },
+  ];
+}
+
+/**
+ * Create a precondition, it is a CAS patch where the base and the target are
+ * the same, so it only fails if the field has a different value.
+ * @param ref Reference to the object.
+ * @param fieldId Index of the field when the data is flattened.
+ * @param value The expected value.
+ */
+export function r(ref: Ref<RossStruct>, fieldId: number, value: any): Patch[] {
+  const expected = toPrimitive(ref, fieldId, value);
+  return [
+    {
+      type: "cas",
+      id: ref.id,
+      field: fieldId + 1,
+      base: expected,
+      target: expected,
+    },
+  ];
+}
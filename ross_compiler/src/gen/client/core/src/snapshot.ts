@@ -20,6 +20,11 @@ export interface DeletePatch {
 export interface CASPatch {
   type: "cas";
   id: Hash16;
+  /**
+   * Index of the field in the object's raw data, since the first item of the
+   * raw data is the tag, this is the flattened field id plus one.
+   */
+  field: number;
   base: PrimitiveValue;
   target: PrimitiveValue;
 }
@@ -0,0 +1,552 @@
+//! The Dart code generator.
+//! This module contains `DartClientBackend`, which generates a single Dart
+//! library containing a class for each struct and a static method for each
+//! action, the runtime in `dart/core.dart` is embedded at the top of the file.
+//!
+//! # Internal Notes
+//! Dart has no namespaces, so the structs of nested modules are named by their
+//! path joined with a `$`, e.g `shapes.Circle` becomes `shapes$Circle`, the `$`
+//! can not appear in a Ross identifier so the names never collide. The actions
+//! of each module are static methods of the `Actions` class of that module.
+//! (e.g `shapes$Actions`)
+//!
+//! Action parameters are prefixed with a `$` so that they never shadow any of
+//! the runtime functions.
+
+pub use crate::ast;
+pub use crate::gen::{writer::Writer, Backend};
+use std::fmt::Write;
+
+const CORE_DART: &str = include_str!("./dart/core.dart");
+
+pub struct DartClientBackend {
+    w: Writer,
+    /// Path of the current module, not including the root.
+    path: Vec<String>,
+    mod_level: u8,
+    /// Whether the `Actions` class of the current module is opened.
+    in_actions: bool,
+    /// Id and the class name of all of the structs, used to generate the
+    /// decoders map.
+    decoders: Vec<(u32, String)>,
+}
+
+impl DartClientBackend {
+    pub fn new(indention: &str) -> Self {
+        let mut w = Writer::new(indention);
+        w.write("// Generated by the Ross compiler, do not edit.\n\n");
+        w.write(CORE_DART);
+        w.write("\n");
+        Self {
+            w,
+            path: Vec::new(),
+            mod_level: 0,
+            in_actions: false,
+            decoders: Vec::new(),
+        }
+    }
+
+    /// The Dart class name of a struct, the name is relative to the current
+    /// module.
+    fn class_name(&self, name: &str) -> String {
+        let mut parts = self.path.clone();
+        parts.extend(name.split('.').map(String::from));
+        parts.join("$")
+    }
+
+    fn dart_type(&self, ty: &ast::Type) -> String {
+        match ty {
+            ast::Type::Object(obj) => self.class_name(obj),
+            ast::Type::ObjectRef(obj) => format!("Ref<{}>", self.class_name(obj)),
+            ast::Type::Primitive(p) => primitive_type(*p).into(),
+        }
+    }
+
+    fn doc(&mut self, doc: Option<&String>) {
+        for line in doc.iter().flat_map(|doc| doc.lines()) {
+            match line {
+                "" => self.w.write("///\n"),
+                _ => writeln!(&mut self.w, "/// {}", line).unwrap(),
+            }
+        }
+    }
+
+    fn encode_field(&mut self, name: &str, ty: &ast::Type) {
+        match ty {
+            ast::Type::Object(_) => writeln!(&mut self.w, "{}.$encodeInto(data);", name),
+            ast::Type::ObjectRef(_) if name == "owner" => {
+                writeln!(&mut self.w, "data.add(ownerId ?? owner?.id);")
+            }
+            ast::Type::ObjectRef(_) => writeln!(&mut self.w, "data.add({}.id);", name),
+            ast::Type::Primitive(p) => writeln!(&mut self.w, "data.add({});", encode(name, *p)),
+        }
+        .unwrap();
+    }
+
+    fn decode_field(&mut self, name: &str, ty: &ast::Type) {
+        let value = match ty {
+            ast::Type::Object(obj) => format!("{}.$decode(snapshot, iter)", self.class_name(obj)),
+            ast::Type::ObjectRef(obj) if name == "owner" => {
+                format!("$readOwner<{}>(snapshot, iter)", self.class_name(obj))
+            }
+            ast::Type::ObjectRef(obj) => {
+                format!("$readRef<{}>(snapshot, iter)", self.class_name(obj))
+            }
+            ast::Type::Primitive(p) => format!("{}(iter)", primitive_reader(*p)),
+        };
+        writeln!(&mut self.w, "{}: {},", name, value).unwrap();
+    }
+}
+
+impl Backend for DartClientBackend {
+    fn compile_source(mut self) -> String {
+        self.w
+            .write("final Map<int, Decoder> $decoders = <int, Decoder>{\n");
+        self.w.indent();
+        for (id, class) in &self.decoders {
+            writeln!(&mut self.w, "{}: {}.$decode,", id, class).unwrap();
+        }
+        self.w.dedent();
+        self.w.write("};\n");
+        self.w.result()
+    }
+
+    fn enter_mod(&mut self, name: &String, _: &ast::Mod) {
+        if self.mod_level > 0 {
+            self.path.push(name.clone());
+        }
+        self.mod_level += 1;
+    }
+
+    fn exit_mod(&mut self, _: &String, _: &ast::Mod) {
+        self.mod_level -= 1;
+        if self.mod_level > 0 {
+            self.path.pop();
+        }
+    }
+
+    fn enter_struct(&mut self, name: &String, node: &ast::Struct) {
+        let class = self.class_name(name);
+        self.decoders.push((node.id, class.clone()));
+
+        self.doc(node.doc.as_ref());
+        writeln!(&mut self.w, "class {} extends RossStruct {{", class).unwrap();
+        self.w.indent();
+
+        // Fields.
+        for (name, ty) in &node.fields {
+            self.doc(node.field_docs.get(name));
+            let dart_type = self.dart_type(ty);
+            match ty {
+                ast::Type::ObjectRef(_) if name == "owner" => {
+                    writeln!(&mut self.w, "{}? owner;", dart_type)
+                }
+                _ => writeln!(&mut self.w, "{} {};", dart_type, name),
+            }
+            .unwrap();
+        }
+        for (name, object) in &node.members {
+            let object = self.class_name(object);
+            writeln!(&mut self.w, "final List<{}> {};", object, name).unwrap();
+        }
+
+        // Constructor.
+        if node.fields.is_empty() && node.members.is_empty() {
+            writeln!(&mut self.w, "\n{}();", class).unwrap();
+        } else {
+            writeln!(&mut self.w, "\n{}({{", class).unwrap();
+            self.w.indent();
+            for (name, _) in &node.fields {
+                if name == "owner" && node.owner.is_some() {
+                    self.w.write("this.owner,\n");
+                } else {
+                    writeln!(&mut self.w, "required this.{},", name).unwrap();
+                }
+            }
+            for (name, object) in &node.members {
+                let object = self.class_name(object);
+                writeln!(&mut self.w, "List<{}>? {},", object, name).unwrap();
+            }
+            self.w.dedent();
+            if node.members.is_empty() {
+                self.w.write("});\n");
+            } else {
+                let members: Vec<String> = node
+                    .members
+                    .keys()
+                    .map(|m| format!("{m} = {m} ?? []", m = m))
+                    .collect();
+                writeln!(&mut self.w, "}}) : {};", members.join(", ")).unwrap();
+            }
+        }
+
+        // RossStruct members.
+        writeln!(&mut self.w, "\n@override\nint get $tag => {};", node.id).unwrap();
+        if node.owner.is_some() {
+            self.w
+                .write("\n@override\nRef<RossStruct>? get $owner => owner;\n");
+        }
+
+        self.w
+            .write("\n@override\nList<RossStruct> get $children => ");
+        if node.members.is_empty() {
+            self.w.write("const [];\n");
+        } else {
+            let members: Vec<String> = node.members.keys().map(|m| format!("...{}", m)).collect();
+            writeln!(&mut self.w, "[{}];", members.join(", ")).unwrap();
+        }
+
+        writeln!(
+            &mut self.w,
+            "\n@override\nRef<{c}> $makeRef(String id, int version) => Ref<{c}>(id, version, this);",
+            c = class
+        )
+        .unwrap();
+
+        self.w
+            .write("\n@override\nvoid $encodeInto(List<Object?> data, [String? ownerId]) {\n");
+        self.w.indent();
+        for (name, ty) in &node.fields {
+            self.encode_field(name, ty);
+        }
+        self.w.dedent();
+        self.w.write("}\n");
+
+        writeln!(
+            &mut self.w,
+            "\nstatic {c} $decode(Snapshot snapshot, Iterator<Object?> iter) => {c}(",
+            c = class
+        )
+        .unwrap();
+        self.w.indent();
+        for (name, ty) in &node.fields {
+            self.decode_field(name, ty);
+        }
+        self.w.dedent();
+        self.w.write(");\n");
+    }
+
+    fn exit_struct(&mut self, _name: &String, _node: &ast::Struct) {
+        self.w.dedent();
+        self.w.write("}\n\n");
+    }
+
+    fn enter_action(&mut self, name: &String, node: &ast::Action) {
+        if !self.in_actions {
+            self.in_actions = true;
+            let class = self.class_name("Actions");
+            writeln!(&mut self.w, "abstract class {} {{", class).unwrap();
+            self.w.indent();
+        }
+
+        self.doc(node.doc.as_ref());
+        write!(&mut self.w, "static Action {}(", name).unwrap();
+    }
+
+    fn action_parameter(&mut self, name: &String, ty: &ast::Type, index: usize) {
+        if index > 0 {
+            self.w.write(", ");
+        }
+        let dart_type = self.dart_type(ty);
+        write!(&mut self.w, "{} ${}", dart_type, name).unwrap();
+    }
+
+    fn exit_parameters(&mut self, _: &String, node: &ast::Action) {
+        writeln!(&mut self.w, ") =>").unwrap();
+        self.w.indent();
+        writeln!(&mut self.w, "Action({}, (newId) => [", node.id).unwrap();
+        self.w.indent();
+    }
+
+    fn action_atom(&mut self, atom: &ast::ActionAtom) {
+        match atom {
+            ast::ActionAtom::Insert { parameter, .. } => {
+                writeln!(&mut self.w, "...$insert(${}, newId),", parameter)
+            }
+            ast::ActionAtom::Delete { parameter, .. } => {
+                writeln!(&mut self.w, "...$delete(${}),", parameter)
+            }
+            ast::ActionAtom::Touch { parameter, .. } => {
+                writeln!(&mut self.w, "...$touch(${}),", parameter)
+            }
+            ast::ActionAtom::Set {
+                parameter,
+                path,
+                field_index,
+                field_type,
+                value,
+                ..
+            } => {
+                let current = format!("${}.data.{}", parameter, path.join("."));
+                writeln!(
+                    &mut self.w,
+                    "...$set(${}, {}, {}, {}),",
+                    parameter,
+                    field_index,
+                    patch_value(&current, *field_type),
+                    dart_value(value, *field_type)
+                )
+            }
+            ast::ActionAtom::Require {
+                parameter,
+                field_index,
+                field_type,
+                value,
+                ..
+            } => writeln!(
+                &mut self.w,
+                "...$require(${}, {}, {}),",
+                parameter,
+                field_index,
+                dart_value(value, *field_type)
+            ),
+        }
+        .unwrap();
+    }
+
+    fn exit_action(&mut self, _name: &String, _node: &ast::Action) {
+        self.w.dedent();
+        self.w.write("]);\n");
+        self.w.dedent();
+    }
+
+    fn exit_actions(&mut self) {
+        if self.in_actions {
+            self.in_actions = false;
+            self.w.dedent();
+            self.w.write("}\n\n");
+        }
+    }
+}
+
+fn primitive_type(ty: ast::PrimitiveType) -> &'static str {
+    match ty {
+        ast::PrimitiveType::Null => "Null",
+        ast::PrimitiveType::Bool => "bool",
+        ast::PrimitiveType::Str => "String",
+        ast::PrimitiveType::Num => "num",
+        ast::PrimitiveType::Hash => "String",
+        ast::PrimitiveType::U32 => "int",
+        ast::PrimitiveType::I64 => "int",
+        ast::PrimitiveType::F64 => "double",
+        ast::PrimitiveType::Decimal(_) => "String",
+    }
+}
+
+fn primitive_reader(ty: ast::PrimitiveType) -> &'static str {
+    match ty {
+        ast::PrimitiveType::Null => "$readNull",
+        ast::PrimitiveType::Bool => "$readBool",
+        ast::PrimitiveType::Str => "$readStr",
+        ast::PrimitiveType::Num => "$readNum",
+        ast::PrimitiveType::Hash => "$readStr",
+        ast::PrimitiveType::U32 => "$readInt",
+        ast::PrimitiveType::I64 => "$readI64",
+        ast::PrimitiveType::F64 => "$readDouble",
+        ast::PrimitiveType::Decimal(_) => "$readDecimal",
+    }
+}
+
+/// Wrap the expression so it is in the same form that is sent to the server.
+fn encode(expr: &str, ty: ast::PrimitiveType) -> String {
+    match ty {
+        ast::PrimitiveType::I64 => format!("$i64({})", expr),
+        ast::PrimitiveType::Decimal(_) => format!("$decimal({})", expr),
+        _ => expr.into(),
+    }
+}
+
+/// Wrap a field value or a parameter of an action so it can be used in a patch,
+/// a `hash` may be a ref which is sent as its id.
+fn patch_value(expr: &str, ty: ast::PrimitiveType) -> String {
+    match ty {
+        ast::PrimitiveType::Hash => format!("$id({})", expr),
+        _ => encode(expr, ty),
+    }
+}
+
+/// Returns the Dart expression for the right-hand side of a statement.
+fn dart_value(value: &ast::ActionValue, ty: ast::PrimitiveType) -> String {
+    match value {
+        ast::ActionValue::Parameter { parameter, .. } => {
+            patch_value(&format!("${}", parameter), ty)
+        }
+        ast::ActionValue::Literal(literal) => match (literal, ty) {
+            (ast::Literal::Null, _) => "null".into(),
+            (ast::Literal::Bool(b), _) => format!("{}", b),
+            (ast::Literal::Number(n), ast::PrimitiveType::Decimal(_)) => {
+                encode(&format!("'{}'", n), ty)
+            }
+            (ast::Literal::Number(n), _) => encode(n, ty),
+            (ast::Literal::String(s), _) => dart_string(s),
+        },
+    }
+}
+
+fn dart_string(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() + 2);
+    result.push('\'');
+    for c in s.chars() {
+        match c {
+            '\\' => result.push_str("\\\\"),
+            '\'' => result.push_str("\\'"),
+            '$' => result.push_str("\\$"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if c.is_control() => write!(&mut result, "\\u{{{:x}}}", c as u32).unwrap(),
+            c => result.push(c),
+        }
+    }
+    result.push('\'');
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::parse;
+
+    #[test]
+    fn generate() {
+        let ast = parse(
+            r#"
+            mod shapes { struct Point { x: num, y: i64 } }
+            /// The root.
+            struct Scene {
+                /// Shown in the title bar.
+                title: str,
+            }
+            struct Box in Scene as .boxes { p: shapes.Point, size: decimal(2) }
+            action add(s: ref Scene, b: Box) { insert b; set s.title = "$x"; }
+            action grow(b: ref Box, size: decimal(2)) { require b.p.y == 5; set b.size = size; }
+            action move(b: ref Box, s: ref Scene) { require b.owner == s; set b.owner = s; }
+            "#,
+        )
+        .unwrap();
+        let code = DartClientBackend::new("  ").gen(&ast);
+        let generated = &code[code.find("class shapes$Point").unwrap()..];
+        let expected = r#"class shapes$Point extends RossStruct {
+  num x;
+  int y;
+
+  shapes$Point({
+    required this.x,
+    required this.y,
+  });
+
+  @override
+  int get $tag => 256;
+
+  @override
+  List<RossStruct> get $children => const [];
+
+  @override
+  Ref<shapes$Point> $makeRef(String id, int version) => Ref<shapes$Point>(id, version, this);
+
+  @override
+  void $encodeInto(List<Object?> data, [String? ownerId]) {
+    data.add(x);
+    data.add($i64(y));
+  }
+
+  static shapes$Point $decode(Snapshot snapshot, Iterator<Object?> iter) => shapes$Point(
+    x: $readNum(iter),
+    y: $readI64(iter),
+  );
+}
+
+/// The root.
+class Scene extends RossStruct {
+  /// Shown in the title bar.
+  String title;
+  final List<Box> boxes;
+
+  Scene({
+    required this.title,
+    List<Box>? boxes,
+  }) : boxes = boxes ?? [];
+
+  @override
+  int get $tag => 0;
+
+  @override
+  List<RossStruct> get $children => [...boxes];
+
+  @override
+  Ref<Scene> $makeRef(String id, int version) => Ref<Scene>(id, version, this);
+
+  @override
+  void $encodeInto(List<Object?> data, [String? ownerId]) {
+    data.add(title);
+  }
+
+  static Scene $decode(Snapshot snapshot, Iterator<Object?> iter) => Scene(
+    title: $readStr(iter),
+  );
+}
+
+class Box extends RossStruct {
+  Ref<Scene>? owner;
+  shapes$Point p;
+  String size;
+
+  Box({
+    this.owner,
+    required this.p,
+    required this.size,
+  });
+
+  @override
+  int get $tag => 1;
+
+  @override
+  Ref<RossStruct>? get $owner => owner;
+
+  @override
+  List<RossStruct> get $children => const [];
+
+  @override
+  Ref<Box> $makeRef(String id, int version) => Ref<Box>(id, version, this);
+
+  @override
+  void $encodeInto(List<Object?> data, [String? ownerId]) {
+    data.add(ownerId ?? owner?.id);
+    p.$encodeInto(data);
+    data.add($decimal(size));
+  }
+
+  static Box $decode(Snapshot snapshot, Iterator<Object?> iter) => Box(
+    owner: $readOwner<Scene>(snapshot, iter),
+    p: shapes$Point.$decode(snapshot, iter),
+    size: $readDecimal(iter),
+  );
+}
+
+abstract class Actions {
+  static Action add(Ref<Scene> $s, Box $b) =>
+    Action(0, (newId) => [
+      ...$insert($b, newId),
+      ...$set($s, 0, $s.data.title, '\$x'),
+    ]);
+  static Action grow(Ref<Box> $b, String $size) =>
+    Action(1, (newId) => [
+      ...$require($b, 2, $i64(5)),
+      ...$set($b, 3, $decimal($b.data.size), $decimal($size)),
+    ]);
+  static Action move(Ref<Box> $b, Ref<Scene> $s) =>
+    Action(2, (newId) => [
+      ...$require($b, 0, $id($s)),
+      ...$set($b, 0, $id($b.data.owner), $id($s)),
+    ]);
+}
+
+final Map<int, Decoder> $decoders = <int, Decoder>{
+  256: shapes$Point.$decode,
+  0: Scene.$decode,
+  1: Box.$decode,
+};
+"#;
+        assert_eq!(generated, expected);
+    }
+}
@@ -0,0 +1,234 @@
+// Runtime of the generated Dart client, the generated code is appended to this
+// file so everything here is visible to it.
+//
+// Values are kept in the same form that they are sent to the server: refs and
+// hashes are 32-char strings, `i64` and `decimal` values are tagged strings so
+// that no precision is lost. (`{"i64": "-1"}`, `{"decimal": "12.50"}`)
+
+/// A pointer to an object that is stored on the server.
+class Ref<T extends RossStruct> {
+  /// The unique id which is assigned to the object upon insertion.
+  final String id;
+
+  /// Version of the object on the client side.
+  final int version;
+
+  /// The actual data that we're pointing towards, all changes to the data
+  /// must take place using a patch.
+  final T data;
+
+  Ref(this.id, this.version, this.data);
+}
+
+/// Common members of every struct.
+abstract class RossStruct {
+  /// Reference to this object, it is set when the object is added to a
+  /// snapshot.
+  Ref<RossStruct>? $ref;
+
+  /// Id of the struct in the schema, it is the first item of the raw data.
+  int get $tag;
+
+  /// The owner of this object, if the struct is owned by another struct.
+  Ref<RossStruct>? get $owner => null;
+
+  /// Objects that are owned by this object.
+  List<RossStruct> get $children;
+
+  /// Create a reference to this object, generics are reified in Dart so the
+  /// reference must be created with the concrete type.
+  Ref<RossStruct> $makeRef(String id, int version);
+
+  /// Write the flattened fields of this object, `ownerId` is provided to the
+  /// children whose owner is not inserted yet.
+  void $encodeInto(List<Object?> data, [String? ownerId]);
+
+  /// Encode this object as the tagged data-vector which is stored on the
+  /// server.
+  List<Object?> $encode([String? ownerId]) {
+    final data = <Object?>[$tag];
+    $encodeInto(data, ownerId);
+    return data;
+  }
+}
+
+/// The latest version of the objects that the client knows about.
+class Snapshot {
+  final Map<String, Ref<RossStruct>> objects = {};
+
+  /// Decode the raw data of an object and add it to the snapshot, the objects
+  /// it refers to must already be in the snapshot.
+  Ref<RossStruct> add(String id, int version, List<Object?> raw) {
+    final data = decodeObject(this, raw);
+    final ref = data.$makeRef(id, version);
+    data.$ref = ref;
+    return objects[id] = ref;
+  }
+}
+
+/// Decode a tagged data-vector into an instance of the struct it belongs to.
+RossStruct decodeObject(Snapshot snapshot, List<Object?> raw) {
+  final iter = raw.iterator;
+  if (!iter.moveNext()) throw FormatException('Empty object data.');
+  final decoder = $decoders[iter.current];
+  if (decoder == null) throw FormatException('Unknown tag ${iter.current}.');
+  return decoder(snapshot, iter);
+}
+
+typedef Decoder = RossStruct Function(Snapshot, Iterator<Object?>);
+
+Object? $read(Iterator<Object?> iter) {
+  if (!iter.moveNext()) throw FormatException('Object data is too short.');
+  return iter.current;
+}
+
+Null $readNull(Iterator<Object?> iter) => $read(iter) as Null;
+bool $readBool(Iterator<Object?> iter) => $read(iter) as bool;
+String $readStr(Iterator<Object?> iter) => $read(iter) as String;
+num $readNum(Iterator<Object?> iter) => $read(iter) as num;
+int $readInt(Iterator<Object?> iter) => $read(iter) as int;
+double $readDouble(Iterator<Object?> iter) => ($read(iter) as num).toDouble();
+
+int $readI64(Iterator<Object?> iter) {
+  final value = $read(iter);
+  if (value is int) return value;
+  return int.parse((value as Map)['i64'] as String);
+}
+
+String $readDecimal(Iterator<Object?> iter) {
+  final value = $read(iter);
+  if (value is String) return value;
+  if (value is num) return value.toString();
+  return (value as Map)['decimal'] as String;
+}
+
+Ref<T> $readRef<T extends RossStruct>(Snapshot snapshot, Iterator<Object?> iter) {
+  final id = $readStr(iter);
+  final ref = snapshot.objects[id];
+  if (ref == null) throw StateError('Object $id is not in the snapshot.');
+  return ref as Ref<T>;
+}
+
+Ref<T>? $readOwner<T extends RossStruct>(Snapshot snapshot, Iterator<Object?> iter) {
+  final id = $read(iter) as String?;
+  if (id == null) return null;
+  return snapshot.objects[id] as Ref<T>?;
+}
+
+Map<String, String> $i64(int value) => {'i64': value.toString()};
+Map<String, String> $decimal(String value) => {'decimal': value};
+
+/// Refs are sent as the id of the object they point to.
+Object? $id(Object? value) => value is Ref ? value.id : value;
+
+/// A change to a single object.
+abstract class Patch {
+  Map<String, Object?> toJson();
+}
+
+class TouchPatch extends Patch {
+  final String id;
+
+  TouchPatch(this.id);
+
+  @override
+  Map<String, Object?> toJson() => {'type': 'touch', 'id': id};
+}
+
+class CreatePatch extends Patch {
+  final String id;
+  final List<Object?> data;
+
+  CreatePatch(this.id, this.data);
+
+  @override
+  Map<String, Object?> toJson() => {'type': 'create', 'id': id, 'data': data};
+}
+
+class DeletePatch extends Patch {
+  final String id;
+  final int version;
+
+  DeletePatch(this.id, this.version);
+
+  @override
+  Map<String, Object?> toJson() =>
+      {'type': 'delete', 'id': id, 'version': version};
+}
+
+/// Compare-And-Set a field, `field` is the index in the raw data so it is the
+/// flattened field index plus one.
+class CASPatch extends Patch {
+  final String id;
+  final int field;
+  final Object? base;
+  final Object? target;
+
+  CASPatch(this.id, this.field, this.base, this.target);
+
+  @override
+  Map<String, Object?> toJson() => {
+        'type': 'cas',
+        'id': id,
+        'field': field,
+        'base': base,
+        'target': target,
+      };
+}
+
+/// An instance of an action, the patches are only built when the ids for the
+/// new objects can be generated.
+class Action {
+  final int id;
+  final List<Patch> Function(String Function() newId) _build;
+
+  Action(this.id, this._build);
+
+  List<Patch> patches(String Function() newId) => _build(newId);
+
+  /// The batch patch that is sent to the server.
+  Map<String, Object?> toJson(String Function() newId,
+          {required int time, String? author}) =>
+      {
+        'patches': patches(newId).map((p) => p.toJson()).toList(),
+        if (author != null) 'author': author,
+        'action': id,
+        'time': time,
+      };
+}
+
+List<Patch> $insert(RossStruct obj, String Function() newId,
+    [String? ownerId]) {
+  final id = newId();
+  final patches = <Patch>[CreatePatch(id, obj.$encode(ownerId))];
+  final owner = obj.$owner;
+  if (ownerId == null && owner != null) patches.add(TouchPatch(owner.id));
+  for (final child in obj.$children) {
+    patches.addAll($insert(child, newId, id));
+  }
+  return patches;
+}
+
+List<Patch> $delete(Ref<RossStruct> ref) {
+  final patches = <Patch>[];
+  final owner = ref.data.$owner;
+  if (owner != null) patches.add(TouchPatch(owner.id));
+
+  final queue = <Ref<RossStruct>>[ref];
+  for (var i = 0; i < queue.length; ++i) {
+    patches.add(DeletePatch(queue[i].id, queue[i].version));
+    for (final child in queue[i].data.$children) {
+      final childRef = child.$ref;
+      if (childRef != null) queue.add(childRef);
+    }
+  }
+  return patches;
+}
+
+List<Patch> $touch(Ref<RossStruct> ref) => [TouchPatch(ref.id)];
+
+List<Patch> $set(Ref<RossStruct> ref, int field, Object? base, Object? target) =>
+    [CASPatch(ref.id, field + 1, base, target)];
+
+List<Patch> $require(Ref<RossStruct> ref, int field, Object? value) =>
+    [CASPatch(ref.id, field + 1, value, value)];
@@ -14,25 +14,62 @@
 //! - i(Struct): Generate the required patches to insert the given struct.
 //! - d(ref): Delete the reference.
 //! - s(ref, field_id, new_value): Generate a CAS action.
+//! - t(ref): Touch the reference.
+//! - r(ref, field_id, value): Generate a precondition (CAS with base = target).
 //! - root._ -> is a map from each struct id to the constructor, the assignment
 //!   is donne inn the `c` function and is used for decoding the raw data.
+//!
+//! Action parameters are prefixed with a `$` so that they never shadow any of
+//! the above functions.
+//!
+//! When the core is imported as a package, it is re-exported from the generated
+//! module so the users can still access the runtime from the client.
 
 pub use crate::ast;
+use crate::gen::client::{JsOptions, ModuleFormat};
 pub use crate::gen::{writer::Writer, Backend};
 use std::fmt::Write;
 
-const CORE_JS: &'static str = include_str!("./core/dist/bundle.js");
+const CORE_JS: &str = include_str!("./core/dist/bundle.js");
+const CORE_ESM: &str = include_str!("./core/dist/bundle.mjs");
 
 pub struct JavaScriptClientBackend {
     w: Writer,
     mod_level: u8,
+    module: ModuleFormat,
 }
 
 impl JavaScriptClientBackend {
     pub fn new(indention: &str) -> Self {
+        Self::with_options(indention, &JsOptions::default())
+    }
+
+    pub fn with_options(indention: &str, options: &JsOptions) -> Self {
         let mut w = Writer::new(indention);
-        w.write(CORE_JS);
-        Self { w, mod_level: 0 }
+        match (&options.core_package, options.module) {
+            (None, ModuleFormat::Cjs) => w.write(CORE_JS),
+            (None, ModuleFormat::Esm) => w.write(CORE_ESM),
+            (Some(package), ModuleFormat::Cjs) => write!(
+                &mut w,
+                "const $core = require({p:?});\nObject.assign(exports, $core);\n",
+                p = package
+            )
+            .unwrap(),
+            (Some(package), ModuleFormat::Esm) => write!(
+                &mut w,
+                "import * as $core from {p:?};\nexport * from {p:?};\n",
+                p = package
+            )
+            .unwrap(),
+        }
+        if options.core_package.is_some() {
+            w.write("const { c, p, i, d, s, t, r } = $core;\n");
+        }
+        Self {
+            w,
+            mod_level: 0,
+            module: options.module,
+        }
     }
 }
 
@@ -43,11 +80,15 @@ impl Backend for JavaScriptClientBackend {
 
     fn enter_mod(&mut self, name: &String, _: &ast::Mod) {
         if self.mod_level == 0 {
-            write!(&mut self.w, "exports.{n} = ($ => {{\n", n = name).unwrap();
+            match self.module {
+                ModuleFormat::Cjs => writeln!(&mut self.w, "exports.{n} = ($ => {{", n = name),
+                ModuleFormat::Esm => writeln!(&mut self.w, "export const {n} = ($ => {{", n = name),
+            }
+            .unwrap();
             self.w.indent();
             self.w.write("$._ = {};\n"); // Instance ID Map: Map<ID, Constructor>
         } else {
-            write!(&mut self.w, "$.{n} = ($ => {{\n", n = name).unwrap();
+            writeln!(&mut self.w, "$.{n} = ($ => {{", n = name).unwrap();
             self.w.indent();
         }
         self.mod_level += 1;
@@ -75,6 +116,13 @@ impl Backend for JavaScriptClientBackend {
         match ty {
             ast::Type::Object(obj) => write!(&mut self.w, "['{n}', $.{o}], ", n = name, o = obj),
             ast::Type::ObjectRef(_) => write!(&mut self.w, "['{n}'], ", n = name),
+            // The fields that are sent as tagged strings, see `TaggedValue` in the core.
+            ast::Type::Primitive(ast::PrimitiveType::I64) => {
+                write!(&mut self.w, "['{n}', 'i64'], ", n = name)
+            }
+            ast::Type::Primitive(ast::PrimitiveType::Decimal(_)) => {
+                write!(&mut self.w, "['{n}', 'decimal'], ", n = name)
+            }
             _ => write!(&mut self.w, "'{n}', ", n = name),
         }
         .unwrap();
@@ -90,7 +138,7 @@ impl Backend for JavaScriptClientBackend {
 
     fn exit_struct(&mut self, _name: &String, node: &ast::Struct) {
         if let Some((_, field)) = &node.owner {
-            write!(&mut self.w, "], '{n}');\n", n = field).unwrap()
+            writeln!(&mut self.w, "], '{n}');", n = field).unwrap()
         } else {
             self.w.write("]);\n");
         }
@@ -107,14 +155,14 @@ impl Backend for JavaScriptClientBackend {
 
     fn action_parameter(&mut self, name: &String, _: &ast::Type, index: usize) {
         if index > 0 {
-            write!(&mut self.w, ", {}", name).unwrap();
+            write!(&mut self.w, ", ${}", name).unwrap();
         } else {
-            self.w.write(name.as_str());
+            write!(&mut self.w, "${}", name).unwrap();
         }
     }
 
     fn exit_parameters(&mut self, _: &String, node: &ast::Action) {
-        write!(&mut self.w, ") => p({},\n", node.id).unwrap();
+        writeln!(&mut self.w, ") => p({},", node.id).unwrap();
         self.w.indent();
     }
 
@@ -126,11 +174,102 @@ impl Backend for JavaScriptClientBackend {
     fn action_atom(&mut self, atom: &ast::ActionAtom) {
         match atom {
             ast::ActionAtom::Insert { parameter, .. } => {
-                write!(&mut self.w, "i({}),\n", parameter).unwrap();
+                writeln!(&mut self.w, "i(${}),", parameter).unwrap();
             }
             ast::ActionAtom::Delete { parameter, .. } => {
-                write!(&mut self.w, "d({}),\n", parameter).unwrap();
+                writeln!(&mut self.w, "d(${}),", parameter).unwrap();
+            }
+            ast::ActionAtom::Touch { parameter, .. } => {
+                writeln!(&mut self.w, "t(${}),", parameter).unwrap();
+            }
+            ast::ActionAtom::Set {
+                parameter,
+                field_index,
+                field_type,
+                value,
+                ..
+            } => {
+                let v = js_value(value, *field_type);
+                writeln!(&mut self.w, "s(${}, {}, {}),", parameter, field_index, v).unwrap();
+            }
+            ast::ActionAtom::Require {
+                parameter,
+                field_index,
+                field_type,
+                value,
+                ..
+            } => {
+                let v = js_value(value, *field_type);
+                writeln!(&mut self.w, "r(${}, {}, {}),", parameter, field_index, v).unwrap();
             }
         }
     }
 }
+
+/// Returns the JavaScript expression for the right-hand side of a statement.
+fn js_value(value: &ast::ActionValue, ty: ast::PrimitiveType) -> String {
+    match value {
+        ast::ActionValue::Parameter { parameter, .. } => format!("${}", parameter),
+        ast::ActionValue::Literal(literal) => match (literal, ty) {
+            (ast::Literal::Null, _) => "null".into(),
+            (ast::Literal::Bool(b), _) => format!("{}", b),
+            (ast::Literal::Number(n), ast::PrimitiveType::I64) => format!("{}n", n),
+            (ast::Literal::Number(n), ast::PrimitiveType::Decimal(_)) => format!("'{}'", n),
+            (ast::Literal::Number(n), _) => n.clone(),
+            (ast::Literal::String(s), _) => format!("{:?}", s),
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::parse;
+
+    #[test]
+    fn core_package() {
+        let ast = parse("struct Point { x: num }").unwrap();
+        let mut options = JsOptions {
+            module: ModuleFormat::Esm,
+            core_package: Some("@ross/core".into()),
+        };
+        let code = JavaScriptClientBackend::with_options("  ", &options).gen(&ast);
+        assert!(code.starts_with(
+            "import * as $core from \"@ross/core\";\nexport * from \"@ross/core\";\n\
+             const { c, p, i, d, s, t, r } = $core;\nexport const root = ($ => {\n"
+        ));
+
+        options.module = ModuleFormat::Cjs;
+        let code = JavaScriptClientBackend::with_options("  ", &options).gen(&ast);
+        assert!(code.starts_with(
+            "const $core = require(\"@ross/core\");\nObject.assign(exports, $core);\n\
+             const { c, p, i, d, s, t, r } = $core;\nexports.root = ($ => {\n"
+        ));
+    }
+
+    #[test]
+    fn tagged_fields() {
+        let ast = parse("struct Account { balance: i64, rate: decimal(2), name: str }").unwrap();
+        let options = JsOptions {
+            module: ModuleFormat::Cjs,
+            core_package: Some("@ross/core".into()),
+        };
+        let code = JavaScriptClientBackend::with_options("  ", &options).gen(&ast);
+        assert!(code.contains(
+            "c($, 0, 'Account', [['balance', 'i64'], ['rate', 'decimal'], 'name', ], []);"
+        ));
+    }
+
+    #[test]
+    fn tagged_literals() {
+        let ast = parse(
+            "struct Account { balance: i64, rate: decimal(2) }
+             action deposit(a: ref Account) { set a.balance = 7; require a.rate == 1.5; }",
+        )
+        .unwrap();
+        let code = JavaScriptClientBackend::new("  ").gen(&ast);
+        // The core encodes them by the kind of the field.
+        assert!(code
+            .contains("$$.deposit = ($a) => p(0,\n    s($a, 0, 7n),\n    r($a, 1, '1.5'),\n  );"));
+    }
+}
@@ -1,2 +1,48 @@
+use serde::Deserialize;
+
+pub mod dart;
+/// The JavaScript backends embed the core bundle which is built with yarn, so
+/// they are only available with the `js` feature.
+#[cfg(feature = "js")]
 pub mod js;
+#[cfg(feature = "js")]
 pub mod tsd;
+
+/// Options shared by the JavaScript and the TypeScript declaration backends.
+#[derive(Debug, Clone, Default)]
+pub struct JsOptions {
+    pub module: ModuleFormat,
+    /// Import the core runtime from this package instead of embedding the
+    /// bundle in the generated file.
+    pub core_package: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModuleFormat {
+    /// CommonJS, the generated module is assigned to `exports.root`.
+    Cjs,
+    /// ES module, the generated module is exported as `root`.
+    Esm,
+}
+
+// The `#[default]` variant attribute needs Rust 1.62, the crate builds on older
+// toolchains.
+#[allow(clippy::derivable_impls)]
+impl Default for ModuleFormat {
+    fn default() -> Self {
+        ModuleFormat::Cjs
+    }
+}
+
+impl std::str::FromStr for ModuleFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cjs" => Ok(ModuleFormat::Cjs),
+            "esm" => Ok(ModuleFormat::Esm),
+            _ => Err(format!("Unknown module format '{}'.", s)),
+        }
+    }
+}
@@ -1,23 +1,54 @@
 pub use crate::ast;
+use crate::gen::client::JsOptions;
 pub use crate::gen::{writer::Writer, Backend};
+use indexmap::IndexMap;
 use std::fmt::Write;
 
-const CORE_TS: &'static str = include_str!("./core/dist/bundle.d.ts");
+const CORE_TS: &str = include_str!("./core/dist/bundle.d.ts");
 
 pub struct TypeScriptClientBackend {
     w: Writer,
     mod_level: u8,
     in_constructor: bool,
+    /// Documentation of the fields of the current struct.
+    field_docs: IndexMap<String, String>,
 }
 
 impl TypeScriptClientBackend {
     pub fn new(indention: &str) -> Self {
+        Self::with_options(indention, &JsOptions::default())
+    }
+
+    /// The declarations are the same for both module formats, only the way
+    /// the core is included changes.
+    pub fn with_options(indention: &str, options: &JsOptions) -> Self {
         let mut w = Writer::new(indention);
-        w.write(CORE_TS);
+        match &options.core_package {
+            None => w.write(CORE_TS),
+            Some(package) => write!(
+                &mut w,
+                "import {{ BatchPatch, Decimal, Hash16, Ref, RossStruct, StructConstructor }} from {p:?};\nexport * from {p:?};\n",
+                p = package
+            )
+            .unwrap(),
+        }
         Self {
             w,
             mod_level: 0,
             in_constructor: false,
+            field_docs: IndexMap::new(),
+        }
+    }
+
+    /// Write the documentation of a declaration as a JSDoc comment.
+    fn jsdoc(&mut self, doc: Option<&String>) {
+        if let Some(doc) = doc {
+            self.w.write("/**\n");
+            for line in doc.lines() {
+                let line = line.replace("*/", "*\\/");
+                writeln!(&mut self.w, " *{}{}", if line.is_empty() { "" } else { " " }, line).unwrap();
+            }
+            self.w.write(" */\n");
         }
     }
 }
@@ -29,11 +60,11 @@ impl Backend for TypeScriptClientBackend {
 
     fn enter_mod(&mut self, name: &String, _: &ast::Mod) {
         if self.mod_level == 0 {
-            write!(&mut self.w, "export declare namespace {n} {{\n", n = name).unwrap();
+            writeln!(&mut self.w, "export declare namespace {n} {{", n = name).unwrap();
             self.w.indent();
             self.w.write("export const _: Record<number, StructConstructor>;\n");
         } else {
-            write!(&mut self.w, "export namespace {n} {{\n", n = name).unwrap();
+            writeln!(&mut self.w, "export namespace {n} {{", n = name).unwrap();
             self.w.indent();
         }
         self.mod_level += 1;
@@ -45,9 +76,11 @@ impl Backend for TypeScriptClientBackend {
         self.w.write("}\n");
     }
 
-    fn enter_struct(&mut self, name: &String, _node: &ast::Struct) {
+    fn enter_struct(&mut self, name: &String, node: &ast::Struct) {
         self.in_constructor = false;
-        write!(&mut self.w, "export class {n} extends RossStruct {{\n", n = name).unwrap();
+        self.field_docs = node.field_docs.clone();
+        self.jsdoc(node.doc.as_ref());
+        writeln!(&mut self.w, "export class {n} extends RossStruct {{", n = name).unwrap();
         self.w.indent();
     }
 
@@ -62,28 +95,34 @@ impl Backend for TypeScriptClientBackend {
                 ast::PrimitiveType::Hash => format!("{n}: Hash16", n = name),
                 ast::PrimitiveType::Num => format!("{n}: number", n = name),
                 ast::PrimitiveType::Str => format!("{n}: string", n = name),
+                ast::PrimitiveType::U32 => format!("{n}: number", n = name),
+                ast::PrimitiveType::I64 => format!("{n}: bigint", n = name),
+                ast::PrimitiveType::F64 => format!("{n}: number", n = name),
+                ast::PrimitiveType::Decimal(_) => format!("{n}: Decimal", n = name),
             },
         };
 
         if self.in_constructor {
-            write!(&mut self.w, "{},\n", ty).unwrap();
+            writeln!(&mut self.w, "{},", ty).unwrap();
         } else {
-            write!(&mut self.w, "readonly {};\n", ty).unwrap();
+            let doc = self.field_docs.get(name).cloned();
+            self.jsdoc(doc.as_ref());
+            writeln!(&mut self.w, "readonly {};", ty).unwrap();
         }
     }
 
     fn struct_member(&mut self, field: &String, object: &String) {
         if self.in_constructor {
-            write!(
+            writeln!(
                 &mut self.w,
-                "{n}: ArrayLike<{o}> | undefined,\n",
+                "{n}: ArrayLike<{o}> | undefined,",
                 n = field,
                 o = object
             )
         } else {
-            write!(
+            writeln!(
                 &mut self.w,
-                "readonly {n}: ReadonlyArray<{o}>;\n",
+                "readonly {n}: ReadonlyArray<{o}>;",
                 n = field,
                 o = object
             )
@@ -113,7 +152,8 @@ impl Backend for TypeScriptClientBackend {
         self.w.write("}\n");
     }
 
-    fn enter_action(&mut self, name: &String, _node: &ast::Action) {
+    fn enter_action(&mut self, name: &String, node: &ast::Action) {
+        self.jsdoc(node.doc.as_ref());
         write!(&mut self.w, "export function {n}(", n = name).unwrap();
     }
 
@@ -127,6 +167,10 @@ impl Backend for TypeScriptClientBackend {
                 ast::PrimitiveType::Hash => "Hash16",
                 ast::PrimitiveType::Num => "number",
                 ast::PrimitiveType::Str => "string",
+                ast::PrimitiveType::U32 => "number",
+                ast::PrimitiveType::I64 => "bigint",
+                ast::PrimitiveType::F64 => "number",
+                ast::PrimitiveType::Decimal(_) => "Decimal",
             }
             .to_string(),
         };
@@ -138,6 +182,6 @@ impl Backend for TypeScriptClientBackend {
     }
 
     fn exit_parameters(&mut self, _: &String, _: &ast::Action) {
-        write!(&mut self.w, "): BatchPatch;\n").unwrap();
+        writeln!(&mut self.w, "): BatchPatch;").unwrap();
     }
 }
@@ -1,7 +1,8 @@
 use crate::ast;
-mod writer;
+pub(crate) mod writer;
 
 pub mod client;
+pub mod schema;
 
 pub trait Backend: Sized {
     fn gen(mut self, root: &ast::Mod) -> String {
@@ -12,7 +13,20 @@ pub trait Backend: Sized {
         self.compile_source()
     }
 
+    /// Visit the declarations of a module in this order: the child modules,
+    /// the structs and then the actions.
+    /// The modules are visited first because the structs can refer to the
+    /// structs in the child modules (e.g `shapes.Circle`), so the generated
+    /// code of a module always comes after the code of its children.
     fn visit(&mut self, root: &ast::Mod) {
+        self.enter_modules();
+        for (name, node) in &root.mods {
+            self.enter_mod(name, node);
+            self.visit(node);
+            self.exit_mod(name, node);
+        }
+        self.exit_modules();
+
         self.enter_structs();
         for (name, node) in &root.structs {
             self.enter_struct(name, node);
@@ -30,10 +44,8 @@ pub trait Backend: Sized {
         for (name, node) in &root.actions {
             self.enter_action(name, node);
             self.enter_parameters(name, node);
-            let mut i = 0;
-            for (name, ty) in &node.parameters {
+            for (i, (name, ty)) in node.parameters.iter().enumerate() {
                 self.action_parameter(name, ty, i);
-                i += 1;
             }
             self.exit_parameters(name, node);
             for atom in &node.actions {
@@ -42,14 +54,6 @@ pub trait Backend: Sized {
             self.exit_action(name, node);
         }
         self.exit_actions();
-
-        self.enter_modules();
-        for (name, node) in &root.mods {
-            self.enter_mod(name, node);
-            self.visit(node);
-            self.exit_mod(name, node);
-        }
-        self.exit_modules();
     }
 
     #[inline]
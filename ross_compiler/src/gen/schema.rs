@@ -0,0 +1,240 @@
+//! The schema artifact.
+//! A JSON description of every struct and action in the schema, it is meant
+//! for the server-side tools that need to know about the layout of the objects
+//! without parsing the schema. (e.g. to show the field names and descriptions)
+//!
+//! The names are qualified from the root module (e.g `shapes.Circle`) and the
+//! types are written the same way they are in the source. (e.g `ref Scene`)
+//!
+//! The `layout` of a struct has an item for every value in its flattened data
+//! vector (the same order as `types`), along with the attributes that apply to
+//! that value. The numbers in the attributes are written as strings so decimals
+//! and 64-bit integers do not lose precision.
+
+use crate::ast;
+use serde_json::{json, Map, Value};
+
+/// Version of the artifact format, it is increased on breaking changes.
+pub const VERSION: u32 = 1;
+
+pub fn generate(root: &ast::Mod) -> String {
+    let mut structs = Vec::new();
+    let mut actions = Vec::new();
+    visit(root, root, "", &mut structs, &mut actions);
+    let artifact = json!({
+        "version": VERSION,
+        "structs": structs,
+        "actions": actions,
+    });
+    format!("{:#}\n", artifact)
+}
+
+fn visit(
+    root: &ast::Mod,
+    module: &ast::Mod,
+    prefix: &str,
+    structs: &mut Vec<Value>,
+    actions: &mut Vec<Value>,
+) {
+    for (name, node) in &module.mods {
+        visit(
+            root,
+            node,
+            &format!("{}{}.", prefix, name),
+            structs,
+            actions,
+        );
+    }
+
+    for (name, node) in &module.structs {
+        let fields: Vec<Value> = node
+            .fields
+            .iter()
+            .map(|(name, ty)| {
+                let mut field = Map::new();
+                field.insert("name".into(), json!(name));
+                field.insert("type".into(), json!(type_name(ty, prefix)));
+                if let Some(doc) = node.field_docs.get(name) {
+                    field.insert("doc".into(), json!(doc));
+                }
+                if let Some(attributes) = node.attributes.get(name) {
+                    field.insert("attributes".into(), attributes_object(attributes));
+                }
+                Value::Object(field)
+            })
+            .collect();
+        let members: Vec<Value> = node
+            .members
+            .iter()
+            .map(|(name, object)| json!({ "name": name, "type": format!("{}{}", prefix, object) }))
+            .collect();
+
+        let mut object = Map::new();
+        object.insert("name".into(), json!(format!("{}{}", prefix, name)));
+        object.insert("id".into(), json!(node.id));
+        if let Some(doc) = &node.doc {
+            object.insert("doc".into(), json!(doc));
+        }
+        if let Some((owner, field)) = &node.owner {
+            let owner = format!("{}{}", prefix, owner);
+            object.insert("owner".into(), json!({ "type": owner, "field": field }));
+        }
+        object.insert("fields".into(), Value::Array(fields));
+        object.insert("members".into(), Value::Array(members));
+        object.insert("types".into(), json!(node.type_vec));
+        let mut layout = Vec::new();
+        collect_layout(root, node, prefix, "", &mut layout);
+        object.insert("layout".into(), Value::Array(layout));
+        structs.push(Value::Object(object));
+    }
+
+    for (name, node) in &module.actions {
+        let parameters: Vec<Value> = node
+            .parameters
+            .iter()
+            .map(|(name, ty)| json!({ "name": name, "type": type_name(ty, prefix) }))
+            .collect();
+
+        let mut action = Map::new();
+        action.insert("name".into(), json!(format!("{}{}", prefix, name)));
+        action.insert("id".into(), json!(node.id));
+        if let Some(doc) = &node.doc {
+            action.insert("doc".into(), json!(doc));
+        }
+        action.insert("parameters".into(), Value::Array(parameters));
+        actions.push(Value::Object(action));
+    }
+}
+
+/// Collect the fields of the flattened data vector of a struct, fields of the
+/// inlined structs are named by their path. (e.g `color.r`)
+fn collect_layout(
+    root: &ast::Mod,
+    node: &ast::Struct,
+    prefix: &str,
+    path: &str,
+    layout: &mut Vec<Value>,
+) {
+    for (name, ty) in &node.fields {
+        let field = format!("{}{}", path, name);
+        match ty {
+            ast::Type::Object(obj) => {
+                let qualified = format!("{}{}", prefix, obj);
+                let (inner, inner_prefix) = find_struct(root, &qualified);
+                collect_layout(root, inner, &inner_prefix, &format!("{}.", field), layout);
+            }
+            _ => {
+                let mut item = Map::new();
+                item.insert("field".into(), json!(field));
+                if let Some(attributes) = node.attributes.get(name) {
+                    item.insert("attributes".into(), attributes_object(attributes));
+                }
+                layout.push(Value::Object(item));
+            }
+        }
+    }
+}
+
+/// Find a struct by its qualified name, returns the struct along with the
+/// prefix of the module that contains it.
+fn find_struct<'a>(root: &'a ast::Mod, qualified: &str) -> (&'a ast::Struct, String) {
+    let mut module = root;
+    let mut parts: Vec<&str> = qualified.split('.').collect();
+    let name = parts.pop().unwrap();
+    for part in &parts {
+        module = module.mods.get(*part).unwrap();
+    }
+    let prefix = parts.iter().map(|p| format!("{}.", p)).collect();
+    (module.structs.get(name).unwrap(), prefix)
+}
+
+fn attributes_object(attributes: &[ast::Attribute]) -> Value {
+    let mut object = Map::new();
+    for attribute in attributes {
+        let value = match attribute {
+            ast::Attribute::Default(literal) => match literal {
+                ast::Literal::Null => Value::Null,
+                ast::Literal::Bool(b) => json!(b),
+                ast::Literal::Number(n) => json!(n),
+                ast::Literal::String(s) => json!(s),
+            },
+            ast::Attribute::Min(n) | ast::Attribute::Max(n) => json!(n),
+            ast::Attribute::MaxLen(n) => json!(n),
+            ast::Attribute::Merge(policy) => json!(policy.name()),
+            ast::Attribute::Index => json!(true),
+        };
+        object.insert(attribute.name().into(), value);
+    }
+    Value::Object(object)
+}
+
+fn type_name(ty: &ast::Type, prefix: &str) -> String {
+    match ty {
+        ast::Type::Object(obj) => format!("{}{}", prefix, obj),
+        ast::Type::ObjectRef(obj) => format!("ref {}{}", prefix, obj),
+        ast::Type::Primitive(p) => match p {
+            ast::PrimitiveType::Null => "null".into(),
+            ast::PrimitiveType::Bool => "bool".into(),
+            ast::PrimitiveType::Str => "str".into(),
+            ast::PrimitiveType::Num => "num".into(),
+            ast::PrimitiveType::Hash => "hash".into(),
+            ast::PrimitiveType::U32 => "u32".into(),
+            ast::PrimitiveType::I64 => "i64".into(),
+            ast::PrimitiveType::F64 => "f64".into(),
+            ast::PrimitiveType::Decimal(scale) => format!("decimal({})", scale),
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::parse;
+
+    #[test]
+    fn artifact() {
+        let ast = parse(
+            "mod shapes {\n/// A point.\nstruct P { @default(1) @index x: num }\n}\nstruct Scene {}\n\
+             struct Box in Scene as .boxes {\n/// Position.\np: shapes.P,\n@min(0) @merge(lww) size: decimal(2) }\n\
+             /// Add a box.\naction add(s: ref Scene, b: Box) { insert b; }",
+        )
+        .unwrap();
+        let artifact: Value = serde_json::from_str(&generate(&ast)).unwrap();
+        assert_eq!(artifact["version"], json!(VERSION));
+        assert_eq!(artifact["structs"][0]["name"], json!("shapes.P"));
+        assert_eq!(artifact["structs"][0]["doc"], json!("A point."));
+        assert_eq!(
+            artifact["structs"][1]["members"],
+            json!([{ "name": "boxes", "type": "Box" }])
+        );
+        assert_eq!(
+            artifact["structs"][2]["fields"],
+            json!([
+                { "name": "owner", "type": "ref Scene" },
+                { "name": "p", "type": "shapes.P", "doc": "Position." },
+                {
+                    "name": "size",
+                    "type": "decimal(2)",
+                    "attributes": { "min": "0", "merge": "lww" },
+                },
+            ])
+        );
+        assert_eq!(
+            artifact["structs"][2]["layout"],
+            json!([
+                { "field": "owner" },
+                { "field": "p.x", "attributes": { "default": "1", "index": true } },
+                { "field": "size", "attributes": { "min": "0", "merge": "lww" } },
+            ])
+        );
+        assert_eq!(
+            artifact["structs"][2]["owner"],
+            json!({ "type": "Scene", "field": "boxes" })
+        );
+        assert_eq!(artifact["actions"][0]["doc"], json!("Add a box."));
+        assert_eq!(
+            artifact["actions"][0]["parameters"][0],
+            json!({ "name": "s", "type": "ref Scene" })
+        );
+    }
+}
@@ -46,7 +46,7 @@ impl Writer {
             if i > 0 {
                 let current = self.current.trim_end();
                 if !current.is_empty() {
-                    self.buffer.push_str(&current);
+                    self.buffer.push_str(current);
                 }
                 self.buffer.push('\n');
                 //
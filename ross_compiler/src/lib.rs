@@ -0,0 +1,12 @@
+#[macro_use]
+extern crate pest_derive;
+
+pub mod ast;
+pub mod cli;
+pub mod config;
+pub mod fmt;
+pub mod gen;
+pub mod lint;
+pub mod lock;
+pub mod lsp;
+pub mod parser;
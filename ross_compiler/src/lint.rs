@@ -0,0 +1,198 @@
+use crate::ast;
+use crate::parser;
+use std::collections::HashSet;
+use std::fmt;
+
+#[derive(Debug, PartialEq)]
+pub enum Warning {
+    /// The struct is not used by any field or action, and it is not a member
+    /// of another struct.
+    UnusedStruct(String),
+    /// The action inserts an object that belongs to an owner, but the action
+    /// does not take a reference to the owner.
+    InsertWithoutOwner {
+        action: String,
+        parameter: String,
+        owner: String,
+    },
+    /// The name only differs in case with a keyword, backends that change the
+    /// case of the names (e.g `Default` -> `default`) will produce an invalid
+    /// code.
+    KeywordName(String),
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::UnusedStruct(name) => write!(f, "Struct '{}' is never used.", name),
+            Warning::InsertWithoutOwner {
+                action,
+                parameter,
+                owner,
+            } => write!(
+                f,
+                "Action '{}' inserts '{}' without a reference to its owner '{}'.",
+                action, parameter, owner
+            ),
+            Warning::KeywordName(name) => {
+                write!(
+                    f,
+                    "Name '{}' collides with a target language keyword.",
+                    name
+                )
+            }
+        }
+    }
+}
+
+/// Find the declarations that are valid but are likely to be a mistake.
+pub fn lint(root: &ast::Mod) -> Vec<Warning> {
+    let mut linter = Linter {
+        root,
+        declared: Vec::new(),
+        used: HashSet::new(),
+        warnings: Vec::new(),
+    };
+    linter.visit(root, "");
+
+    let used = &linter.used;
+    let unused = linter
+        .declared
+        .iter()
+        .filter(|name| !used.contains(*name))
+        .map(|name| Warning::UnusedStruct(name.clone()))
+        .collect::<Vec<_>>();
+    linter.warnings.extend(unused);
+    linter.warnings
+}
+
+struct Linter<'a> {
+    root: &'a ast::Mod,
+    /// Qualified name of all of the structs.
+    declared: Vec<String>,
+    /// Qualified name of the structs that are used.
+    used: HashSet<String>,
+    warnings: Vec<Warning>,
+}
+
+impl<'a> Linter<'a> {
+    fn visit(&mut self, module: &ast::Mod, prefix: &str) {
+        for (name, m) in &module.mods {
+            self.check_name(name);
+            self.visit(m, &format!("{}{}.", prefix, name));
+        }
+
+        for (name, st) in &module.structs {
+            self.check_name(name);
+            self.declared.push(format!("{}{}", prefix, name));
+            if let Some((owner, _)) = &st.owner {
+                self.used.insert(format!("{}{}", prefix, name));
+                self.used.insert(format!("{}{}", prefix, owner));
+            }
+            for (field, ty) in &st.fields {
+                self.check_name(field);
+                self.use_type(prefix, ty);
+            }
+        }
+
+        for (name, action) in &module.actions {
+            self.check_name(name);
+            for (parameter, ty) in &action.parameters {
+                self.check_name(parameter);
+                self.use_type(prefix, ty);
+            }
+
+            for atom in &action.actions {
+                if let ast::ActionAtom::Insert { parameter, ty, .. } = atom {
+                    let qualified = format!("{}{}", prefix, ty);
+                    let owner = match self.find(&qualified).and_then(|st| st.owner.as_ref()) {
+                        Some((owner, _)) => owner,
+                        None => continue,
+                    };
+
+                    // The owner is declared in the same module as the struct.
+                    let owner = match qualified.rfind('.') {
+                        Some(i) => format!("{}{}", &qualified[..=i], owner),
+                        None => owner.clone(),
+                    };
+                    let has_owner = action.parameters.values().any(|ty| match ty {
+                        ast::Type::ObjectRef(name) => format!("{}{}", prefix, name) == owner,
+                        _ => false,
+                    });
+                    if !has_owner {
+                        self.warnings.push(Warning::InsertWithoutOwner {
+                            action: format!("{}{}", prefix, name),
+                            parameter: parameter.clone(),
+                            owner,
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    fn use_type(&mut self, prefix: &str, ty: &ast::Type) {
+        match ty {
+            ast::Type::Object(name) | ast::Type::ObjectRef(name) => {
+                self.used.insert(format!("{}{}", prefix, name));
+            }
+            ast::Type::Primitive(_) => {}
+        }
+    }
+
+    fn check_name(&mut self, name: &str) {
+        let lower = name.to_lowercase();
+        if lower != name && parser::is_keyword(&lower) {
+            self.warnings.push(Warning::KeywordName(name.into()));
+        }
+    }
+
+    /// Find a struct by its qualified name.
+    fn find(&self, name: &str) -> Option<&'a ast::Struct> {
+        let mut module = self.root;
+        let mut parts: Vec<&str> = name.split('.').collect();
+        let name = parts.pop()?;
+        for part in parts {
+            module = module.mods.get(part)?;
+        }
+        module.structs.get(name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::parse;
+
+    #[test]
+    fn warnings() {
+        let ast = parse(
+            r#"
+            mod shapes {
+                struct Point { x: num, y: num }
+                struct Unused { x: num }
+            }
+            struct Scene { title: str }
+            struct Box in Scene as .boxes { p: shapes.Point }
+            struct Default { x: num }
+            action add(b: Box) { insert b; }
+            action addTo(s: ref Scene, b: Box) { insert b; }
+            action draw(d: ref Default) {}
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            lint(&ast),
+            vec![
+                Warning::KeywordName("Default".into()),
+                Warning::InsertWithoutOwner {
+                    action: "add".into(),
+                    parameter: "b".into(),
+                    owner: "Scene".into(),
+                },
+                Warning::UnusedStruct("shapes.Unused".into()),
+            ]
+        );
+    }
+}
@@ -1,36 +1,365 @@
-use std::collections::HashMap;
-
 use crate::ast;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// The lock file pins the id of every struct and action to its qualified name
+/// (e.g `shapes.Circle`), so the ids remain the same when the declarations are
+/// reordered.
+/// The declarations of an imported file are named after the path of the file
+/// instead of the alias it is imported as (e.g `lib/shapes.ross:Circle`), so
+/// renaming an import does not change the ids. When a file is moved, the entries
+/// at its old path are carried over to the new one, as long as the old path is
+/// not imported anymore and it is the only such path with the same declaration.
+/// Entries are never removed from the lock, so the id of a deleted struct is
+/// not reused by another one.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Lock {
+    #[serde(default)]
+    pub structs: BTreeMap<String, LockedStruct>,
+    #[serde(default)]
+    pub actions: BTreeMap<String, u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LockedStruct {
+    pub id: u32,
+    /// The flattened types of the struct, stored objects are encoded using
+    /// this layout so it can only be extended.
+    pub types: Vec<ast::PrimitiveType>,
+}
 
 #[derive(Debug)]
-pub struct Lock<'a> {
-    types: HashMap<u32, &'a Vec<ast::PrimitiveType>>,
+pub enum LockError {
+    IO(String),
+    InvalidFormat(String),
+    IncompatibleTypes(String),
 }
 
-impl<'a> Default for Lock<'a> {
-    fn default() -> Self {
-        Lock {
-            types: HashMap::new(),
+impl std::error::Error for LockError {}
+
+impl fmt::Display for LockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LockError::IO(e) => write!(f, "Cannot access the lock file: {}", e),
+            LockError::InvalidFormat(e) => write!(f, "Invalid lock file: {}", e),
+            LockError::IncompatibleTypes(name) => write!(
+                f,
+                "Fields of '{}' are not compatible with the lock file, fields can only be appended.",
+                name
+            ),
         }
     }
 }
 
-impl<'a> From<&'a ast::Mod> for Lock<'a> {
-    fn from(module: &'a ast::Mod) -> Self {
-        let mut lock = Lock::default();
+impl Lock {
+    /// Read the lock file, returns an empty lock if the file does not exist.
+    pub fn open(path: &Path) -> Result<Self, LockError> {
+        if !path.exists() {
+            return Ok(Lock::default());
+        }
 
-        fn collect<'l>(mut lock: &mut Lock<'l>, module: &'l ast::Mod) {
-            for (_, st) in &module.structs {
-                lock.types.insert(st.id, &st.type_vec);
+        let source = fs::read_to_string(path).map_err(|e| LockError::IO(e.to_string()))?;
+        serde_yaml::from_str(&source).map_err(|e| LockError::InvalidFormat(e.to_string()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), LockError> {
+        let source =
+            serde_yaml::to_string(self).map_err(|e| LockError::InvalidFormat(e.to_string()))?;
+        fs::write(path, source).map_err(|e| LockError::IO(e.to_string()))
+    }
+
+    /// Replace the ids in the AST with the ones in the lock, and add the new
+    /// declarations to the lock.
+    pub fn apply(&mut self, root: &mut ast::Mod) -> Result<(), LockError> {
+        let mut structs = Vec::new();
+        let mut actions = Vec::new();
+        collect(root, String::new(), &mut structs, &mut actions);
+        let sources: BTreeSet<String> = structs
+            .iter()
+            .map(|(name, _)| name)
+            .chain(actions.iter().map(|(name, _)| name))
+            .filter_map(|name| split_source(name))
+            .map(|(source, _)| source.to_string())
+            .collect();
+
+        let mut used: BTreeSet<u32> = self.structs.values().map(|s| s.id).collect();
+        let mut new = Vec::new();
+        for (name, st) in structs {
+            match self.structs.get_mut(&name) {
+                Some(locked) => {
+                    let n = locked.types.len();
+                    if st.type_vec.len() < n || st.type_vec[..n] != locked.types[..] {
+                        return Err(LockError::IncompatibleTypes(name));
+                    }
+                    locked.types = st.type_vec.clone();
+                    st.id = locked.id;
+                }
+                None => new.push((name, st)),
+            }
+        }
+        for (name, st) in new {
+            // The same file may be imported more than once.
+            if let Some(locked) = self.structs.get(&name) {
+                st.id = locked.id;
+                continue;
+            }
+            let moved = moved(&self.structs, &name, &sources, |locked| {
+                let n = locked.types.len();
+                st.type_vec.len() >= n && st.type_vec[..n] == locked.types[..]
+            });
+            if let Some(old) = moved {
+                let mut locked = self.structs.remove(&old).unwrap();
+                locked.types = st.type_vec.clone();
+                st.id = locked.id;
+                self.structs.insert(name, locked);
+                continue;
             }
+            st.id = allocate(&mut used, st.id);
+            self.structs.insert(
+                name,
+                LockedStruct {
+                    id: st.id,
+                    types: st.type_vec.clone(),
+                },
+            );
+        }
 
-            for (_, m) in &module.mods {
-                collect(&mut lock, m);
+        let mut used: BTreeSet<u32> = self.actions.values().cloned().collect();
+        let mut new = Vec::new();
+        for (name, action) in actions {
+            match self.actions.get(&name) {
+                Some(id) => action.id = *id,
+                None => new.push((name, action)),
+            }
+        }
+        for (name, action) in new {
+            if let Some(id) = self.actions.get(&name) {
+                action.id = *id;
+                continue;
+            }
+            if let Some(old) = moved(&self.actions, &name, &sources, |_| true) {
+                action.id = self.actions.remove(&old).unwrap();
+                self.actions.insert(name, action.id);
+                continue;
             }
+            action.id = allocate(&mut used, action.id);
+            self.actions.insert(name, action.id);
+        }
+
+        Ok(())
+    }
+}
+
+/// Use the preferred id if it is not already used, otherwise use the smallest
+/// free id.
+fn allocate(used: &mut BTreeSet<u32>, preferred: u32) -> u32 {
+    let mut id = preferred;
+    if used.contains(&id) {
+        id = 0;
+        while used.contains(&id) {
+            id += 1;
         }
+    }
+    used.insert(id);
+    id
+}
+
+/// Split the name of an imported declaration to the path of its file and its
+/// name in that file.
+fn split_source(name: &str) -> Option<(&str, &str)> {
+    name.rfind(':').map(|i| (&name[..i], &name[i + 1..]))
+}
+
+/// Returns the entry of an imported declaration whose file was moved, it must be
+/// the only entry with the same name in a file that is no longer imported.
+fn moved<T>(
+    entries: &BTreeMap<String, T>,
+    name: &str,
+    sources: &BTreeSet<String>,
+    compatible: impl Fn(&T) -> bool,
+) -> Option<String> {
+    let (_, local) = split_source(name)?;
+    let mut candidates = entries
+        .iter()
+        .filter(|(key, value)| match split_source(key) {
+            Some((source, key)) => key == local && !sources.contains(source) && compatible(value),
+            None => false,
+        });
+    match (candidates.next(), candidates.next()) {
+        (Some((key, _)), None) => Some(key.clone()),
+        _ => None,
+    }
+}
 
-        collect(&mut lock, module);
+fn collect<'a>(
+    module: &'a mut ast::Mod,
+    prefix: String,
+    structs: &mut Vec<(String, &'a mut ast::Struct)>,
+    actions: &mut Vec<(String, &'a mut ast::Action)>,
+) {
+    for (name, st) in module.structs.iter_mut() {
+        structs.push((format!("{}{}", prefix, name), st));
+    }
+
+    for (name, action) in module.actions.iter_mut() {
+        actions.push((format!("{}{}", prefix, name), action));
+    }
+
+    for (name, m) in module.mods.iter_mut() {
+        let prefix = match &m.source {
+            Some(source) => format!("{}:", source),
+            None => format!("{}{}.", prefix, name),
+        };
+        collect(m, prefix, structs, actions);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::parse;
+
+    #[test]
+    fn stable_ids() {
+        let mut lock = Lock::default();
+        let mut ast = parse("mod a { struct A { x: num } } struct B { y: str }").unwrap();
+        lock.apply(&mut ast).unwrap();
+        let a = ast.mods["a"].structs["A"].id;
+        let b = ast.structs["B"].id;
+
+        // Move `A` to another module position and add a new struct before `B`.
+        let mut ast =
+            parse("struct C { z: bool } struct B { y: str, w: num } mod a { struct A { x: num } }")
+                .unwrap();
+        lock.apply(&mut ast).unwrap();
+        assert_eq!(ast.mods["a"].structs["A"].id, a);
+        assert_eq!(ast.structs["B"].id, b);
+        assert_ne!(ast.structs["C"].id, b);
+        assert_ne!(ast.structs["C"].id, a);
+
+        let mut ast = parse("struct B { y: num }").unwrap();
+        match lock.apply(&mut ast) {
+            Err(LockError::IncompatibleTypes(name)) => assert_eq!(name, "B"),
+            r => panic!("Unexpected result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn imported_ids() {
+        let dir = std::env::temp_dir().join("ross-lock-imports");
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        std::fs::write(
+            dir.join("lib/shapes.ross"),
+            "struct Circle { r: num } mod inner { struct Point { x: num } }",
+        )
+        .unwrap();
+        let schema = |source: &str| {
+            std::fs::write(dir.join("main.ross"), source).unwrap();
+            crate::parser::parse_file(&dir.join("main.ross")).unwrap()
+        };
+
+        let mut lock = Lock::default();
+        let mut ast = schema("import \"lib/shapes.ross\" as shapes; struct A { x: num }");
+        lock.apply(&mut ast).unwrap();
+        let circle = ast.mods["shapes"].structs["Circle"].id;
+        let point = ast.mods["shapes"].mods["inner"].structs["Point"].id;
+        assert_eq!(lock.structs["lib/shapes.ross:Circle"].id, circle);
+        assert_eq!(lock.structs["lib/shapes.ross:inner.Point"].id, point);
+
+        // Renaming the import keeps the ids, and so does importing it twice.
+        let mut ast = schema(
+            "struct B { y: num } import \"lib/shapes.ross\" as geometry;\n\
+             import \"lib/shapes.ross\" as other;",
+        );
+        lock.apply(&mut ast).unwrap();
+        for name in &["geometry", "other"] {
+            assert_eq!(ast.mods[*name].structs["Circle"].id, circle);
+            assert_eq!(ast.mods[*name].mods["inner"].structs["Point"].id, point);
+        }
+        assert_eq!(lock.structs.len(), 4);
+    }
+
+    #[test]
+    fn moved_imports() {
+        let dir = std::env::temp_dir().join(format!("ross-lock-moves-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        std::fs::create_dir_all(dir.join("shared")).unwrap();
+        let shapes = "struct Circle { r: num } action grow(c: ref Circle) { set c.r = 2; }";
+        let schema = |source: &str| {
+            std::fs::write(dir.join("main.ross"), source).unwrap();
+            crate::parser::parse_file(&dir.join("main.ross")).unwrap()
+        };
+
+        let mut lock = Lock::default();
+        std::fs::write(dir.join("lib/shapes.ross"), shapes).unwrap();
+        let mut ast = schema("struct A { x: num } import \"lib/shapes.ross\" as shapes;");
+        lock.apply(&mut ast).unwrap();
+        let circle = ast.mods["shapes"].structs["Circle"].id;
+        let grow = ast.mods["shapes"].actions["grow"].id;
+
+        // Move the file, and add a field to the struct while at it.
+        std::fs::remove_file(dir.join("lib/shapes.ross")).unwrap();
+        std::fs::write(
+            dir.join("shared/geometry.ross"),
+            shapes.replace("r: num", "r: num, x: num"),
+        )
+        .unwrap();
+        let mut ast =
+            schema("struct B { x: num } struct A { x: num } import \"shared/geometry.ross\" as g;");
+        lock.apply(&mut ast).unwrap();
+        assert_eq!(ast.mods["g"].structs["Circle"].id, circle);
+        assert_eq!(ast.mods["g"].actions["grow"].id, grow);
+        assert_eq!(lock.structs["shared/geometry.ross:Circle"].id, circle);
+        assert!(!lock.structs.contains_key("lib/shapes.ross:Circle"));
+        assert_eq!(lock.structs.len(), 3);
+
+        // Two files that were imported together can not be told apart.
+        std::fs::write(dir.join("lib/a.ross"), "struct P { x: num }").unwrap();
+        std::fs::write(dir.join("lib/b.ross"), "struct P { x: num }").unwrap();
+        let mut ast = schema("import \"lib/a.ross\" as a; import \"lib/b.ross\" as b;");
+        lock.apply(&mut ast).unwrap();
+        let ids = [ast.mods["a"].structs["P"].id, ast.mods["b"].structs["P"].id];
+        std::fs::write(dir.join("lib/c.ross"), "struct P { x: num }").unwrap();
+        let mut ast = schema("import \"lib/c.ross\" as c;");
+        lock.apply(&mut ast).unwrap();
+        assert!(!ids.contains(&ast.mods["c"].structs["P"].id));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn no_id_limits() {
+        let mut source = String::new();
+        for i in 0..300 {
+            source.push_str(&format!("struct S{} {{ x: num }} ", i));
+        }
+        source = format!(
+            "mod a {{ mod b {{ mod c {{ mod d {{ mod e {{ {} }} }} }} }} }}",
+            source
+        );
+        let mut ast = parse(&source).unwrap();
+        let mut ids: BTreeSet<u32> = BTreeSet::new();
+        let e = &ast.mods["a"].mods["b"].mods["c"].mods["d"].mods["e"];
+        assert!(e.structs.values().all(|s| ids.insert(s.id)));
+        assert_eq!(ids.len(), 300);
+
+        let mut lock = Lock::default();
+        lock.apply(&mut ast).unwrap();
+        assert_eq!(lock.structs.len(), 300);
+        assert_eq!(lock.structs["a.b.c.d.e.S299"].id, 299);
+    }
 
-        lock
+    #[test]
+    fn packed_ids() {
+        // Ids of small schemas should match the ones from before the lock file
+        // so the already stored objects remain valid.
+        let ast =
+            parse("struct A { x: num } mod m { struct B { x: num } struct C { x: num } }").unwrap();
+        assert_eq!(ast.structs["A"].id, 0);
+        assert_eq!(ast.mods["m"].structs["B"].id, 1 << 8);
+        assert_eq!(ast.mods["m"].structs["C"].id, (1 << 8) | 1);
     }
 }
@@ -0,0 +1,349 @@
+use crate::ast;
+use crate::lock::{Lock, LockError};
+use crate::parser::{self, Rule};
+use pest::iterators::Pair;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A (line, character) position, the characters are counted in UTF-16 code
+/// units as required by the protocol.
+pub type Position = (usize, usize);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+    pub uri: String,
+    pub start: Position,
+    pub end: Position,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Diagnostic {
+    pub start: Position,
+    pub end: Position,
+    pub message: String,
+}
+
+pub struct Document {
+    pub uri: String,
+    pub path: PathBuf,
+    pub text: String,
+    /// The AST of the last version of the document that had no errors, ids
+    /// are already assigned from the lock file.
+    pub ast: Option<ast::Mod>,
+    pub index: Index,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Document {
+    pub fn new(uri: String, text: String) -> Self {
+        let path = uri_to_path(&uri);
+        let mut document = Document {
+            uri,
+            path,
+            text: String::new(),
+            ast: None,
+            index: Index::default(),
+            diagnostics: Vec::new(),
+        };
+        document.update(text);
+        document
+    }
+
+    pub fn update(&mut self, text: String) {
+        self.text = text;
+        self.diagnostics.clear();
+
+        // Keep the old index when there is a syntax error, so navigation keeps
+        // working while the user is typing.
+        if let Ok(pairs) = parser::parse_program(&self.text) {
+            let mut index = Index::default();
+            let mut files =
+                vec![fs::canonicalize(&self.path).unwrap_or_else(|_| self.path.clone())];
+            let file = File {
+                source: &self.text,
+                uri: &self.uri,
+                dir: self.path.parent().unwrap_or_else(|| Path::new("")),
+                root: true,
+            };
+            for pair in pairs {
+                index.visit_declaration(&file, pair, "", &mut files);
+            }
+            self.index = index;
+        }
+
+        let mut root = match parser::parse_source(&self.text, &self.path) {
+            Ok(root) => root,
+            Err(e) => {
+                let mut message = e.error.to_string();
+                let path = fs::canonicalize(&self.path).unwrap_or_else(|_| self.path.clone());
+                if e.file.is_some() && e.file != Some(path) {
+                    message = e.to_string();
+                }
+                self.diagnostics.push(Diagnostic {
+                    start: position(&self.text, e.span.0),
+                    end: position(&self.text, e.span.1),
+                    message,
+                });
+                return;
+            }
+        };
+
+        let lock_path = self.path.with_extension("lock");
+        if let Err(e) = Lock::open(&lock_path).and_then(|mut lock| lock.apply(&mut root)) {
+            let (start, end) = match &e {
+                LockError::IncompatibleTypes(name) => self
+                    .index
+                    .definitions
+                    .get(name)
+                    .filter(|l| l.uri == self.uri)
+                    .map(|l| (l.start, l.end))
+                    .unwrap_or_default(),
+                _ => Default::default(),
+            };
+            self.diagnostics.push(Diagnostic {
+                start,
+                end,
+                message: e.to_string(),
+            });
+        }
+
+        self.ast = Some(root);
+    }
+
+    /// Qualified name of the struct which is referenced at the given offset.
+    pub fn reference_at(&self, offset: usize) -> Option<&str> {
+        self.index
+            .references
+            .iter()
+            .find(|(start, end, _)| *start <= offset && offset <= *end)
+            .map(|(_, _, name)| name.as_str())
+    }
+
+    /// Find a struct by its qualified name.
+    pub fn find_struct(&self, name: &str) -> Option<&ast::Struct> {
+        let mut module = self.ast.as_ref()?;
+        let mut parts: Vec<&str> = name.split('.').collect();
+        let name = parts.pop()?;
+        for part in parts {
+            module = module.mods.get(part)?;
+        }
+        module.structs.get(name)
+    }
+
+    /// Name prefix of the innermost module that contains the offset.
+    pub fn module_at(&self, offset: usize) -> &str {
+        self.index
+            .modules
+            .iter()
+            .filter(|(start, end, _)| *start < offset && offset < *end)
+            .max_by_key(|(start, _, _)| *start)
+            .map(|(_, _, prefix)| prefix.as_str())
+            .unwrap_or("")
+    }
+}
+
+/// The symbols of a document, the names are qualified from the root of the
+/// document. (e.g `shapes.Circle`)
+#[derive(Default)]
+pub struct Index {
+    /// Location of the struct declarations, including the imported ones.
+    pub definitions: HashMap<String, Location>,
+    /// Byte range of the struct names in the document.
+    pub references: Vec<(usize, usize, String)>,
+    /// Byte range and the name prefix of the modules in the document.
+    pub modules: Vec<(usize, usize, String)>,
+}
+
+struct File<'a> {
+    source: &'a str,
+    uri: &'a str,
+    dir: &'a Path,
+    /// Whether this is the document itself and not an imported file.
+    root: bool,
+}
+
+impl Index {
+    fn visit_declaration(
+        &mut self,
+        file: &File,
+        pair: Pair<Rule>,
+        prefix: &str,
+        files: &mut Vec<PathBuf>,
+    ) {
+        match pair.as_rule() {
+            Rule::import_declaration => {
+                let mut inner = pair.into_inner();
+                let quoted = inner.next().unwrap().as_str();
+                let name = inner.next().unwrap().as_str();
+                let path = match fs::canonicalize(file.dir.join(&quoted[1..quoted.len() - 1])) {
+                    Ok(path) if !files.contains(&path) => path,
+                    _ => return,
+                };
+                let source = match fs::read_to_string(&path) {
+                    Ok(source) => source,
+                    Err(_) => return,
+                };
+                let pairs = match parser::parse_program(&source) {
+                    Ok(pairs) => pairs,
+                    Err(_) => return,
+                };
+
+                let uri = path_to_uri(&path);
+                let imported = File {
+                    source: &source,
+                    uri: &uri,
+                    dir: path.parent().unwrap_or_else(|| Path::new("")),
+                    root: false,
+                };
+                let prefix = format!("{}{}.", prefix, name);
+                files.push(path.clone());
+                for pair in pairs {
+                    self.visit_declaration(&imported, pair, &prefix, files);
+                }
+                files.pop();
+            }
+            Rule::mod_declaration => {
+                let span = pair.as_span();
+                let mut inner = pair.into_inner();
+                let prefix = format!("{}{}.", prefix, inner.next().unwrap().as_str());
+                if file.root {
+                    self.modules
+                        .push((span.start(), span.end(), prefix.clone()));
+                }
+                for pair in inner {
+                    self.visit_declaration(file, pair, &prefix, files);
+                }
+            }
+            Rule::struct_declaration | Rule::action_declaration => {
+                for pair in pair.into_inner() {
+                    match pair.as_rule() {
+                        Rule::struct_name => {
+                            let name = format!("{}{}", prefix, pair.as_str());
+                            let span = pair.as_span();
+                            let location = Location {
+                                uri: file.uri.into(),
+                                start: position(file.source, span.start()),
+                                end: position(file.source, span.end()),
+                            };
+                            self.definitions.insert(name.clone(), location);
+                            self.reference(file, &pair, name);
+                        }
+                        Rule::owner_name => {
+                            self.reference(file, &pair, format!("{}{}", prefix, pair.as_str()));
+                        }
+                        Rule::struct_field_type | Rule::parameter_type => {
+                            let ty = pair.into_inner().next().unwrap();
+                            let ty = match ty.as_rule() {
+                                Rule::ref_type => ty.into_inner().next().unwrap(),
+                                _ => ty,
+                            };
+                            if ty.as_rule() == Rule::object_type {
+                                self.reference(file, &ty, format!("{}{}", prefix, ty.as_str()));
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn reference(&mut self, file: &File, pair: &Pair<Rule>, name: String) {
+        if file.root {
+            let span = pair.as_span();
+            self.references.push((span.start(), span.end(), name));
+        }
+    }
+}
+
+/// Convert a byte offset to a position.
+pub fn position(text: &str, offset: usize) -> Position {
+    let before = &text[..offset.min(text.len())];
+    let line_start = before.rfind('\n').map(|n| n + 1).unwrap_or(0);
+    let line = before.matches('\n').count();
+    let character = before[line_start..].encode_utf16().count();
+    (line, character)
+}
+
+/// Convert a position to a byte offset, positions after the end of a line
+/// are clamped to the end of that line.
+pub fn offset(text: &str, (line, character): Position) -> usize {
+    let mut line_start = 0;
+    for _ in 0..line {
+        match text[line_start..].find('\n') {
+            Some(n) => line_start += n + 1,
+            None => return text.len(),
+        }
+    }
+
+    let mut units = 0;
+    for (i, c) in text[line_start..].char_indices() {
+        if units >= character || c == '\n' {
+            return line_start + i;
+        }
+        units += c.len_utf16();
+    }
+    text.len()
+}
+
+pub fn uri_to_path(uri: &str) -> PathBuf {
+    let path = uri.strip_prefix("file://").unwrap_or(uri);
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = path
+            .get(i + 1..i + 3)
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    PathBuf::from(String::from_utf8_lossy(&decoded).into_owned())
+}
+
+pub fn path_to_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn positions() {
+        let text = "ab\n\u{1F600}c\n";
+        assert_eq!(position(text, 0), (0, 0));
+        assert_eq!(position(text, 3), (1, 0));
+        assert_eq!(position(text, 7), (1, 2));
+        assert_eq!(offset(text, (1, 2)), 7);
+        assert_eq!(offset(text, (0, 10)), 2);
+        assert_eq!(offset(text, (5, 0)), text.len());
+        assert_eq!(
+            uri_to_path("file:///a%20b/c.ross"),
+            PathBuf::from("/a b/c.ross")
+        );
+        assert_eq!(
+            path_to_uri(Path::new("/a b/c.ross")),
+            "file:///a%20b/c.ross"
+        );
+    }
+}
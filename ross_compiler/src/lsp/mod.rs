@@ -0,0 +1,377 @@
+//! A language server for the schema files, it talks JSON-RPC over the given
+//! streams. (usually stdin and stdout)
+use crate::ast::PrimitiveType;
+use document::{Document, Location, Position};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+pub mod document;
+pub mod rpc;
+
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INVALID_REQUEST: i64 = -32600;
+
+const KEYWORDS: &[&str] = &[
+    "mod", "struct", "action", "import", "as", "in", "ref", "insert", "delete", "set", "touch",
+    "require", "null", "true", "false",
+];
+
+const PRIMITIVES: &[&str] = &["bool", "str", "num", "hash", "u32", "i64", "f64", "decimal"];
+
+/// Serve the requests until the `exit` notification or the end of the input,
+/// returns whether the server was shut down properly.
+pub fn run<R: BufRead, W: Write>(mut input: R, output: W) -> io::Result<bool> {
+    let mut server = Server {
+        output,
+        documents: HashMap::new(),
+        shutdown: false,
+    };
+
+    while let Some(message) = rpc::read_message(&mut input)? {
+        if message["method"] == "exit" {
+            return Ok(server.shutdown);
+        }
+        server.handle(message)?;
+    }
+
+    Ok(false)
+}
+
+struct Server<W: Write> {
+    output: W,
+    documents: HashMap<String, Document>,
+    shutdown: bool,
+}
+
+impl<W: Write> Server<W> {
+    fn handle(&mut self, message: Value) -> io::Result<()> {
+        let method = message["method"].as_str().unwrap_or("");
+        let params = &message["params"];
+
+        let id = match message.get("id") {
+            Some(id) => id,
+            None => return self.notification(method, params),
+        };
+
+        let response = match self.request(method, params) {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": code, "message": message },
+            }),
+        };
+        rpc::write_message(&mut self.output, &response)
+    }
+
+    fn request(&mut self, method: &str, params: &Value) -> Result<Value, (i64, String)> {
+        if self.shutdown {
+            return Err((INVALID_REQUEST, "The server is shut down.".into()));
+        }
+
+        match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "definitionProvider": true,
+                    "hoverProvider": true,
+                    "completionProvider": { "triggerCharacters": ["."] },
+                },
+                "serverInfo": { "name": "ross-lsp", "version": env!("CARGO_PKG_VERSION") },
+            })),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            "textDocument/definition" => {
+                let (document, offset) = self.locate(params)?;
+                Ok(document
+                    .reference_at(offset)
+                    .and_then(|name| document.index.definitions.get(name))
+                    .map(location)
+                    .unwrap_or(Value::Null))
+            }
+            "textDocument/hover" => {
+                let (document, offset) = self.locate(params)?;
+                let name = match document.reference_at(offset) {
+                    Some(name) => name,
+                    None => return Ok(Value::Null),
+                };
+                let st = match document.find_struct(name) {
+                    Some(st) => st,
+                    None => return Ok(Value::Null),
+                };
+
+                let types: Vec<String> = st.type_vec.iter().map(type_name).collect();
+                let mut value = format!("```ross\nstruct {}\n```\n\n", name);
+                if let Some(doc) = &st.doc {
+                    value.push_str(doc);
+                    value.push_str("\n\n");
+                }
+                value.push_str(&format!(
+                    "id: `{}`\n\ntype_vec: `[{}]`",
+                    st.id,
+                    types.join(", ")
+                ));
+                Ok(json!({ "contents": { "kind": "markdown", "value": value } }))
+            }
+            "textDocument/completion" => {
+                let (document, offset) = self.locate(params)?;
+                Ok(Value::Array(completions(document, offset)))
+            }
+            _ => Err((METHOD_NOT_FOUND, format!("Unknown method '{}'.", method))),
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: &Value) -> io::Result<()> {
+        let uri = match params["textDocument"]["uri"].as_str() {
+            Some(uri) => uri.to_string(),
+            None => return Ok(()),
+        };
+
+        match method {
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or("");
+                let document = Document::new(uri.clone(), text.into());
+                self.documents.insert(uri.clone(), document);
+            }
+            "textDocument/didChange" => {
+                // Only full document sync is supported, so the last change has
+                // the whole text.
+                let text = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str());
+                match (self.documents.get_mut(&uri), text) {
+                    (Some(document), Some(text)) => document.update(text.into()),
+                    _ => return Ok(()),
+                }
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+            }
+            _ => return Ok(()),
+        }
+
+        self.publish_diagnostics(&uri)
+    }
+
+    fn publish_diagnostics(&mut self, uri: &str) -> io::Result<()> {
+        let diagnostics: Vec<Value> = match self.documents.get(uri) {
+            Some(document) => document
+                .diagnostics
+                .iter()
+                .map(|d| {
+                    json!({
+                        "range": range(d.start, d.end),
+                        "severity": 1,
+                        "source": "ross",
+                        "message": d.message,
+                    })
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+
+        let notification = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": uri, "diagnostics": diagnostics },
+        });
+        rpc::write_message(&mut self.output, &notification)
+    }
+
+    /// Find the document and the byte offset of the position in the params.
+    fn locate(&self, params: &Value) -> Result<(&Document, usize), (i64, String)> {
+        let document = params["textDocument"]["uri"]
+            .as_str()
+            .and_then(|uri| self.documents.get(uri))
+            .ok_or_else(|| (INVALID_PARAMS, "Unknown document.".into()))?;
+        let line = params["position"]["line"].as_u64();
+        let character = params["position"]["character"].as_u64();
+        match (line, character) {
+            (Some(line), Some(character)) => {
+                let position = (line as usize, character as usize);
+                Ok((document, document::offset(&document.text, position)))
+            }
+            _ => Err((INVALID_PARAMS, "Invalid position.".into())),
+        }
+    }
+}
+
+fn completions(document: &Document, offset: usize) -> Vec<Value> {
+    let before = &document.text[..offset];
+    let word_start = before
+        .rfind(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+        .map(|n| n + 1)
+        .unwrap_or(0);
+    let word = &before[word_start..];
+    // Names are relative to the module that contains the cursor, and after a
+    // `.` only the members of the module before it are suggested.
+    let prefix = document.module_at(offset);
+    let head = word.rfind('.').map(|n| &word[..=n]).unwrap_or("");
+
+    let mut items = Vec::new();
+    if head.is_empty() {
+        for keyword in KEYWORDS {
+            items.push(json!({ "label": keyword, "kind": 14 }));
+        }
+        for primitive in PRIMITIVES {
+            items.push(json!({ "label": primitive, "kind": 25 }));
+        }
+    }
+
+    let mut names: Vec<&str> = document
+        .index
+        .definitions
+        .keys()
+        .filter_map(|name| name.strip_prefix(prefix))
+        .filter_map(|name| name.strip_prefix(head))
+        .collect();
+    names.sort();
+    for name in names {
+        items.push(json!({ "label": name, "kind": 22 }));
+    }
+
+    items
+}
+
+fn type_name(ty: &PrimitiveType) -> String {
+    match ty {
+        PrimitiveType::Null => "null".into(),
+        PrimitiveType::Bool => "bool".into(),
+        PrimitiveType::Str => "str".into(),
+        PrimitiveType::Num => "num".into(),
+        PrimitiveType::Hash => "hash".into(),
+        PrimitiveType::U32 => "u32".into(),
+        PrimitiveType::I64 => "i64".into(),
+        PrimitiveType::F64 => "f64".into(),
+        PrimitiveType::Decimal(scale) => format!("decimal({})", scale),
+    }
+}
+
+fn range(start: Position, end: Position) -> Value {
+    json!({
+        "start": { "line": start.0, "character": start.1 },
+        "end": { "line": end.0, "character": end.1 },
+    })
+}
+
+fn location(location: &Location) -> Value {
+    json!({ "uri": location.uri, "range": range(location.start, location.end) })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Run the server with the given messages and return the messages it
+    /// wrote, along with the exit status.
+    fn script(messages: &[Value]) -> (Vec<Value>, bool) {
+        let mut input = Vec::new();
+        for message in messages {
+            rpc::write_message(&mut input, message).unwrap();
+        }
+
+        let mut output = Vec::new();
+        let ok = run(Cursor::new(input), &mut output).unwrap();
+        let mut reader = Cursor::new(output);
+        let mut responses = Vec::new();
+        while let Some(message) = rpc::read_message(&mut reader).unwrap() {
+            responses.push(message);
+        }
+        (responses, ok)
+    }
+
+    fn request(id: u64, method: &str, params: Value) -> Value {
+        json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+    }
+
+    fn notification(method: &str, params: Value) -> Value {
+        json!({ "jsonrpc": "2.0", "method": method, "params": params })
+    }
+
+    fn at(uri: &str, line: usize, character: usize) -> Value {
+        json!({
+            "textDocument": { "uri": uri },
+            "position": { "line": line, "character": character },
+        })
+    }
+
+    #[test]
+    fn session() {
+        let dir = std::env::temp_dir().join("ross-lsp-session");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("shapes.ross"), "struct Circle { r: num }").unwrap();
+        let uri = document::path_to_uri(&dir.join("main.ross"));
+        let source = "import \"shapes.ross\" as shapes;\n\
+                      mod m {\n    struct Point { x: num, y: decimal(2) }\n}\n\
+                      struct Scene { c: shapes.Circle, p: m.Point }\n\
+                      action a(s: ref Scene) {}\n";
+
+        let (responses, ok) = script(&[
+            request(1, "initialize", json!({})),
+            notification("initialized", json!({})),
+            notification(
+                "textDocument/didOpen",
+                json!({ "textDocument": { "uri": uri, "languageId": "ross", "version": 1, "text": source } }),
+            ),
+            request(2, "textDocument/definition", at(&uri, 4, 28)),
+            request(3, "textDocument/definition", at(&uri, 4, 37)),
+            request(4, "textDocument/hover", at(&uri, 5, 18)),
+            request(5, "textDocument/completion", at(&uri, 4, 25)),
+            notification(
+                "textDocument/didChange",
+                json!({ "textDocument": { "uri": uri, "version": 2 }, "contentChanges": [{ "text": "struct A { x: B }" }] }),
+            ),
+            request(6, "unknown/method", json!({})),
+            request(7, "shutdown", Value::Null),
+            notification("exit", Value::Null),
+        ]);
+
+        assert!(ok);
+        assert_eq!(responses.len(), 9);
+        assert_eq!(
+            responses[0]["result"]["capabilities"]["hoverProvider"],
+            true
+        );
+        assert_eq!(responses[1]["params"]["diagnostics"], json!([]));
+
+        let shapes =
+            document::path_to_uri(&std::fs::canonicalize(dir.join("shapes.ross")).unwrap());
+        assert_eq!(responses[2]["result"]["uri"], shapes);
+        assert_eq!(
+            responses[2]["result"]["range"]["start"],
+            json!({ "line": 0, "character": 7 })
+        );
+        assert_eq!(responses[3]["result"]["uri"], uri);
+        assert_eq!(
+            responses[3]["result"]["range"]["start"],
+            json!({ "line": 2, "character": 11 })
+        );
+
+        let hover = responses[4]["result"]["contents"]["value"]
+            .as_str()
+            .unwrap();
+        assert!(hover.contains("struct Scene"));
+        assert!(hover.contains("type_vec: `[num, num, decimal(2)]`"));
+
+        let labels: Vec<&str> = responses[5]["result"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["label"].as_str().unwrap())
+            .collect();
+        assert_eq!(labels, vec!["Circle"]);
+
+        let diagnostics = &responses[6]["params"]["diagnostics"];
+        assert_eq!(diagnostics[0]["message"], "Cannot resolve name 'B'");
+        assert_eq!(diagnostics[0]["range"], range((0, 14), (0, 15)));
+
+        assert_eq!(responses[7]["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(responses[8]["result"], Value::Null);
+    }
+}
@@ -1,11 +1,4 @@
-#[macro_use]
-extern crate pest_derive;
-
-pub mod ast;
-pub mod cli;
-pub mod gen;
-pub mod lock;
-pub mod parser;
+use ross_compiler::cli;
 
 fn main() {
     std::process::exit(match cli::Cli::default().run() {
@@ -2,25 +2,302 @@ use crate::ast::{
     self,
     builder::{ASTBuilder, BuilderError},
 };
-use pest::{iterators::Pair, Parser};
+use pest::{
+    error::ErrorVariant,
+    iterators::{Pair, Pairs},
+    Parser,
+};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
 
 #[derive(Parser)]
 #[grammar = "ross.pest"]
 struct RossParser;
 
+/// Parse the source into the pest parse tree, without building the AST.
+pub fn parse_program(source: &str) -> Result<Pairs<'_, Rule>, Box<pest::error::Error<Rule>>> {
+    RossParser::parse(Rule::program, source).map_err(Box::new)
+}
+
+/// Whether the name is one of the reserved keywords of Ross or the target
+/// languages.
+pub fn is_keyword(name: &str) -> bool {
+    match RossParser::parse(Rule::keyword, name) {
+        Ok(pairs) => pairs.as_str().len() == name.len(),
+        Err(_) => false,
+    }
+}
+
+/// A parse error along with where it happened.
+#[derive(Debug)]
+pub struct SourceError {
+    pub error: BuilderError,
+    /// The file that contains the error, it is `None` when the error is in a
+    /// source that does not belong to any file.
+    pub file: Option<PathBuf>,
+    /// One-based line and column of the error in its file.
+    pub line: usize,
+    pub column: usize,
+    /// Byte range of the error in the source that was given to the parser, if
+    /// the error is in an imported file this is the range of the import.
+    pub span: (usize, usize),
+}
+
+impl std::error::Error for SourceError {}
+
+impl fmt::Display for SourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}:", file.display())?;
+        }
+        write!(f, "{}:{}: {}", self.line, self.column, self.error)
+    }
+}
+
+/// Parse the source of a schema, imports are resolved relative to the current
+/// working directory.
 pub fn parse(source: &str) -> Result<ast::Mod, BuilderError> {
     let mut builder = ASTBuilder::new();
+    let mut importer = Importer::new(PathBuf::new());
 
-    let pairs = RossParser::parse(Rule::program, source).unwrap_or_else(|e| panic!("{}", e));
-    for pair in pairs {
-        visit_declaration(&mut builder, pair)?;
-    }
+    importer.visit_source(&mut builder, source)?;
 
     builder.finalize()
 }
 
-fn visit_declaration(builder: &mut ASTBuilder, pair: Pair<Rule>) -> Result<(), BuilderError> {
+/// Parse a schema file along with all of the files it imports, each imported
+/// file is merged into the AST as a nested module.
+pub fn parse_file(path: &Path) -> Result<ast::Mod, SourceError> {
+    let mut builder = ASTBuilder::new();
+    let mut importer = Importer::new(PathBuf::new());
+
+    let result = importer.visit_file(&mut builder, path);
+    importer.finish(builder, result)
+}
+
+/// Parse the source of a schema which is stored in the given path, the source
+/// may be different from the content of the file. (e.g an unsaved document)
+pub fn parse_source(source: &str, path: &Path) -> Result<ast::Mod, SourceError> {
+    let mut builder = ASTBuilder::new();
+    let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    let mut importer = Importer::new(dir);
+    importer
+        .stack
+        .push(fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()));
+
+    let result = importer.visit_source(&mut builder, source);
+    importer.finish(builder, result)
+}
+
+/// Keeps track of the files that are being parsed in order to resolve relative
+/// import paths and to detect import cycles.
+struct Importer {
+    /// Directory of the file that is currently being parsed.
+    dir: PathBuf,
+    /// Canonical path of the files that are currently being parsed.
+    stack: Vec<PathBuf>,
+    /// Number of sources that are currently being parsed.
+    depth: usize,
+    /// Range of the item that is being visited in the current source.
+    span: (usize, usize),
+    /// Range of the item that is being visited in the root source.
+    root_span: (usize, usize),
+    /// File, line and column of the first error.
+    location: Option<(Option<PathBuf>, usize, usize)>,
+}
+
+impl Importer {
+    fn new(dir: PathBuf) -> Self {
+        Importer {
+            dir,
+            stack: Vec::new(),
+            depth: 0,
+            span: (0, 0),
+            root_span: (0, 0),
+            location: None,
+        }
+    }
+
+    fn finish(
+        self,
+        builder: ASTBuilder,
+        result: Result<(), BuilderError>,
+    ) -> Result<ast::Mod, SourceError> {
+        let span = self.root_span;
+        let (file, line, column) = self.location.unwrap_or((None, 1, 1));
+        result
+            .and_then(|_| builder.finalize())
+            .map_err(|error| SourceError {
+                error,
+                file,
+                line,
+                column,
+                span,
+            })
+    }
+
+    /// Mark the item as the one that is being visited, so errors are reported
+    /// at its position.
+    fn locate(&mut self, pair: &Pair<Rule>) {
+        let span = pair.as_span();
+        self.span = (span.start(), span.end());
+        if self.depth == 1 {
+            self.root_span = self.span;
+        }
+    }
+
+    fn visit_file(&mut self, builder: &mut ASTBuilder, path: &Path) -> Result<(), BuilderError> {
+        let display = path.display().to_string();
+        let path = fs::canonicalize(path)
+            .map_err(|e| BuilderError::CanNotImport(display.clone(), e.to_string()))?;
+        if self.depth > 0 {
+            builder.source(self.source_name(&path))?;
+        }
+
+        if self.stack.contains(&path) {
+            let mut files: Vec<String> = self
+                .stack
+                .iter()
+                .skip_while(|p| *p != &path)
+                .map(|p| p.display().to_string())
+                .collect();
+            files.push(path.display().to_string());
+            return Err(BuilderError::ImportCycle(files));
+        }
+
+        let source = fs::read_to_string(&path)
+            .map_err(|e| BuilderError::CanNotImport(display, e.to_string()))?;
+
+        let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        let parent_dir = std::mem::replace(&mut self.dir, dir);
+        let span = self.span;
+        self.stack.push(path);
+        let result = self.visit_source(builder, &source);
+        self.stack.pop();
+        self.dir = parent_dir;
+        if result.is_ok() {
+            self.span = span;
+        }
+
+        result
+    }
+
+    /// Path of an imported file relative to the directory of the root file, it
+    /// identifies the module regardless of the name it is imported as.
+    fn source_name(&self, path: &Path) -> String {
+        let root = match self.stack.first() {
+            Some(file) => file.parent().map(Path::to_path_buf).unwrap_or_default(),
+            None => fs::canonicalize(".").unwrap_or_default(),
+        };
+        let common = root
+            .components()
+            .zip(path.components())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let parents = root.components().skip(common).map(|_| "..".into());
+        let rest = path
+            .components()
+            .skip(common)
+            .map(|c| c.as_os_str().to_string_lossy());
+        parents.chain(rest).collect::<Vec<_>>().join("/")
+    }
+
+    fn visit_source(&mut self, builder: &mut ASTBuilder, source: &str) -> Result<(), BuilderError> {
+        self.depth += 1;
+        let result = self.visit_program(builder, source);
+        self.depth -= 1;
+
+        // The innermost source is the first one to see the error.
+        if result.is_err() && self.location.is_none() {
+            let before = &source[..self.span.0];
+            let line = before.matches('\n').count() + 1;
+            let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+            self.location = Some((self.stack.last().cloned(), line, column));
+        }
+
+        result
+    }
+
+    fn visit_program(
+        &mut self,
+        builder: &mut ASTBuilder,
+        source: &str,
+    ) -> Result<(), BuilderError> {
+        let pairs = RossParser::parse(Rule::program, source).map_err(|e| {
+            self.span = match e.location {
+                pest::error::InputLocation::Pos(pos) => (pos, pos),
+                pest::error::InputLocation::Span(span) => span,
+            };
+            if self.depth == 1 {
+                self.root_span = self.span;
+            }
+            BuilderError::Syntax(syntax_message(&e.variant))
+        })?;
+
+        for pair in pairs {
+            visit_declaration(builder, self, pair)?;
+        }
+        Ok(())
+    }
+}
+
+/// The message of a syntax error without its position, which is reported
+/// separately.
+fn syntax_message(variant: &ErrorVariant<Rule>) -> String {
+    let list = |rules: &[Rule]| {
+        rules
+            .iter()
+            .map(|rule| format!("{:?}", rule))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    match variant {
+        ErrorVariant::ParsingError {
+            positives,
+            negatives,
+        } => match (negatives.is_empty(), positives.is_empty()) {
+            (false, false) => format!(
+                "unexpected {}; expected {}",
+                list(negatives),
+                list(positives)
+            ),
+            (false, true) => format!("unexpected {}", list(negatives)),
+            (true, false) => format!("expected {}", list(positives)),
+            (true, true) => "unknown parsing error".into(),
+        },
+        ErrorVariant::CustomError { message } => message.clone(),
+    }
+}
+
+fn visit_declaration(
+    builder: &mut ASTBuilder,
+    importer: &mut Importer,
+    pair: Pair<Rule>,
+) -> Result<(), BuilderError> {
+    importer.locate(&pair);
     match pair.as_rule() {
+        Rule::import_declaration => {
+            builder.enter_mod()?;
+
+            let mut path = None;
+            for pair in pair.into_inner() {
+                match pair.as_rule() {
+                    Rule::import_path => {
+                        let quoted = pair.as_str();
+                        path = Some(importer.dir.join(&quoted[1..quoted.len() - 1]));
+                    }
+                    Rule::mod_name => {
+                        builder.name(pair.as_str().into())?;
+                    }
+                    _ => unreachable!(),
+                }
+            }
+
+            importer.visit_file(builder, &path.unwrap())?;
+
+            builder.exit_mod()?;
+        }
         Rule::mod_declaration => {
             builder.enter_mod()?;
 
@@ -30,7 +307,7 @@ fn visit_declaration(builder: &mut ASTBuilder, pair: Pair<Rule>) -> Result<(), B
                         builder.name(pair.as_str().into())?;
                     }
                     _ => {
-                        visit_declaration(builder, pair)?;
+                        visit_declaration(builder, importer, pair)?;
                     }
                 }
             }
@@ -42,16 +319,26 @@ fn visit_declaration(builder: &mut ASTBuilder, pair: Pair<Rule>) -> Result<(), B
 
             let mut owner_name = None;
             for pair in pair.into_inner() {
+                importer.locate(&pair);
                 match pair.as_rule() {
+                    Rule::doc_comment => {
+                        builder.doc(doc_line(pair.as_str()))?;
+                    }
                     Rule::struct_name => {
                         builder.name(pair.as_str().into())?;
                     }
+                    Rule::field_attribute => {
+                        let mut inner = pair.into_inner();
+                        let name = inner.next().unwrap().as_str();
+                        let argument = inner.next().map(visit_attribute_argument);
+                        builder.attribute(name, argument)?;
+                    }
                     Rule::struct_field_name => {
                         builder.field_name(pair.as_str().into())?;
                     }
                     Rule::struct_field_type => {
                         let ty_pair = pair.into_inner().peek().unwrap();
-                        let ty = resolve_type(&builder, ty_pair)?;
+                        let ty = resolve_type(builder, ty_pair)?;
                         builder.field_type(ty)?;
                     }
                     Rule::owner_name => {
@@ -70,7 +357,11 @@ fn visit_declaration(builder: &mut ASTBuilder, pair: Pair<Rule>) -> Result<(), B
             builder.enter_action()?;
 
             for pair in pair.into_inner() {
+                importer.locate(&pair);
                 match pair.as_rule() {
+                    Rule::doc_comment => {
+                        builder.doc(doc_line(pair.as_str()))?;
+                    }
                     Rule::action_name => {
                         builder.name(pair.as_str().into())?;
                     }
@@ -79,7 +370,7 @@ fn visit_declaration(builder: &mut ASTBuilder, pair: Pair<Rule>) -> Result<(), B
                     }
                     Rule::parameter_type => {
                         let ty_pair = pair.into_inner().peek().unwrap();
-                        let ty = resolve_type(&builder, ty_pair)?;
+                        let ty = resolve_type(builder, ty_pair)?;
                         builder.parameter_type(ty)?;
                     }
                     Rule::insert_action => {
@@ -90,6 +381,22 @@ fn visit_declaration(builder: &mut ASTBuilder, pair: Pair<Rule>) -> Result<(), B
                         let name = pair.into_inner().peek().unwrap();
                         builder.delete(name.as_str())?;
                     }
+                    Rule::touch_action => {
+                        let name = pair.into_inner().peek().unwrap();
+                        builder.touch(name.as_str())?;
+                    }
+                    Rule::set_action => {
+                        let mut inner = pair.into_inner();
+                        let (object, path) = visit_field_access(inner.next().unwrap());
+                        let value = visit_action_value(builder, inner.next().unwrap())?;
+                        builder.set(object, path, value)?;
+                    }
+                    Rule::require_action => {
+                        let mut inner = pair.into_inner();
+                        let (object, path) = visit_field_access(inner.next().unwrap());
+                        let value = visit_action_value(builder, inner.next().unwrap())?;
+                        builder.require(object, path, value)?;
+                    }
                     _ => {
                         println!("Ac > {:?}", pair);
                     }
@@ -105,6 +412,70 @@ fn visit_declaration(builder: &mut ASTBuilder, pair: Pair<Rule>) -> Result<(), B
     Ok(())
 }
 
+/// Content of a `///` comment, without the slashes and the first space.
+fn doc_line(comment: &str) -> String {
+    let line = comment[3..].trim_end();
+    line.strip_prefix(' ').unwrap_or(line).into()
+}
+
+fn visit_field_access<'i>(pair: Pair<'i, Rule>) -> (&'i str, Vec<String>) {
+    let mut inner = pair.into_inner();
+    let object = inner.next().unwrap().as_str();
+    let path = inner.map(|p| p.as_str().into()).collect();
+    (object, path)
+}
+
+fn visit_action_value(
+    builder: &ASTBuilder,
+    pair: Pair<Rule>,
+) -> Result<ast::ActionValue, BuilderError> {
+    let pair = pair.into_inner().peek().unwrap();
+    match pair.as_rule() {
+        Rule::value_name => {
+            let (parameter_index, _) = builder.resolve_parameter(pair.as_str())?;
+            Ok(ast::ActionValue::Parameter {
+                parameter_index,
+                parameter: pair.as_str().into(),
+            })
+        }
+        _ => Ok(ast::ActionValue::Literal(visit_literal(pair))),
+    }
+}
+
+fn visit_attribute_argument(pair: Pair<Rule>) -> ast::AttributeArgument {
+    let pair = pair.into_inner().peek().unwrap();
+    match pair.as_rule() {
+        Rule::attribute_word => ast::AttributeArgument::Word(pair.as_str().into()),
+        _ => ast::AttributeArgument::Literal(visit_literal(pair)),
+    }
+}
+
+fn visit_literal(pair: Pair<Rule>) -> ast::Literal {
+    match pair.as_rule() {
+        Rule::null_literal => ast::Literal::Null,
+        Rule::bool_literal => ast::Literal::Bool(pair.as_str() == "true"),
+        Rule::number_literal => ast::Literal::Number(pair.as_str().into()),
+        Rule::string_literal => {
+            let quoted = pair.as_str();
+            let mut result = String::with_capacity(quoted.len());
+            let mut chars = quoted[1..quoted.len() - 1].chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => match chars.next() {
+                        Some('n') => result.push('\n'),
+                        Some('t') => result.push('\t'),
+                        Some(c) => result.push(c),
+                        None => {}
+                    },
+                    c => result.push(c),
+                }
+            }
+            ast::Literal::String(result)
+        }
+        _ => unreachable!(),
+    }
+}
+
 fn resolve_type(builder: &ASTBuilder, pair: Pair<Rule>) -> Result<ast::Type, BuilderError> {
     match pair.as_rule() {
         Rule::primitive_type => Ok(match pair.as_str() {
@@ -112,14 +483,24 @@ fn resolve_type(builder: &ASTBuilder, pair: Pair<Rule>) -> Result<ast::Type, Bui
             "str" => ast::Type::Primitive(ast::PrimitiveType::Str),
             "num" => ast::Type::Primitive(ast::PrimitiveType::Num),
             "hash" => ast::Type::Primitive(ast::PrimitiveType::Hash),
-            _ => unreachable!(),
+            "u32" => ast::Type::Primitive(ast::PrimitiveType::U32),
+            "i64" => ast::Type::Primitive(ast::PrimitiveType::I64),
+            "f64" => ast::Type::Primitive(ast::PrimitiveType::F64),
+            _ => {
+                let scale = pair
+                    .into_inner()
+                    .flatten()
+                    .find(|p| p.as_rule() == Rule::decimal_scale)
+                    .unwrap()
+                    .as_str();
+                builder.decimal(scale)?
+            }
         }),
         Rule::object_type => builder.resolve_obj(pair.as_str(), false),
         Rule::ref_type => {
             for pair in pair.into_inner() {
-                match pair.as_rule() {
-                    Rule::object_type => return builder.resolve_obj(pair.as_str(), true),
-                    _ => {}
+                if pair.as_rule() == Rule::object_type {
+                    return builder.resolve_obj(pair.as_str(), true);
                 }
             }
             unreachable!()
@@ -127,3 +508,246 @@ fn resolve_type(builder: &ASTBuilder, pair: Pair<Rule>) -> Result<ast::Type, Bui
         _ => unreachable!(),
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{parse, parse_file, parse_source, SourceError};
+    use crate::ast::{
+        builder::BuilderError, ActionAtom, ActionValue, Attribute, Literal, MergePolicy,
+        PrimitiveType,
+    };
+
+    const SCENE: &str = "
+        struct Scene { title: str }
+        struct RGB { r: u32, g: u32, b: u32 }
+        struct Box in Scene as .boxes { color: RGB, size: decimal(2) }
+        action recolor(b: ref Box, scene: ref Scene, g: u32) {
+            touch scene;
+            require b.size == 1.5;
+            set b.color.g = g;
+        }
+    ";
+
+    #[test]
+    fn action_statements() {
+        let ast = parse(SCENE).unwrap();
+        let action = ast.actions.get("recolor").unwrap();
+        match &action.actions[..] {
+            [ActionAtom::Touch { parameter, .. }, ActionAtom::Require {
+                field_index: 4,
+                field_type: PrimitiveType::Decimal(2),
+                value: ActionValue::Literal(Literal::Number(n)),
+                ..
+            }, ActionAtom::Set {
+                path,
+                field_index: 2,
+                value:
+                    ActionValue::Parameter {
+                        parameter_index: 2, ..
+                    },
+                ..
+            }] => {
+                assert_eq!(parameter, "scene");
+                assert_eq!(n, "1.5");
+                assert_eq!(path, &vec!["color".to_string(), "g".to_string()]);
+            }
+            atoms => panic!("Unexpected atoms {:?}", atoms),
+        }
+    }
+
+    #[test]
+    fn action_type_errors() {
+        let err = |action: &str| {
+            let source = format!("{}{}", SCENE, action);
+            parse(&source).err().map(|e| e.to_string())
+        };
+        assert_eq!(
+            err("action a(b: ref Box, s: str) { set b.size = s; }"),
+            Some(BuilderError::ValueTypeError("size".into()).to_string())
+        );
+        assert_eq!(
+            err("action a(b: ref Box) { require b.size == 1.255; }"),
+            Some(BuilderError::ValueTypeError("size".into()).to_string())
+        );
+        assert_eq!(
+            err("action a(b: ref Box) { set b.color = null; }"),
+            Some(BuilderError::FieldAccessTypeError.to_string())
+        );
+        assert_eq!(
+            err("action a(b: Box) { touch b; }"),
+            Some(BuilderError::TouchTypeError.to_string())
+        );
+        assert_eq!(
+            err("action a(b: ref Box, s: ref Scene) { set b.owner = s; }"),
+            None
+        );
+    }
+
+    #[test]
+    fn doc_comments() {
+        let ast = parse(
+            "/// A 2D point.\n///\n///   Indented.\nstruct Point {\n    /// Horizontal.\n    x: num,\n    y: num,\n    ///Vertical.\n    z: num\n}\n/// Move it.\naction move(p: ref Point) { touch p; }",
+        )
+        .unwrap();
+        let point = ast.structs.get("Point").unwrap();
+        assert_eq!(point.doc.as_deref(), Some("A 2D point.\n\n  Indented."));
+        assert_eq!(
+            point.field_docs.get("x").map(|d| &d[..]),
+            Some("Horizontal.")
+        );
+        assert_eq!(point.field_docs.get("y"), None);
+        assert_eq!(point.field_docs.get("z").map(|d| &d[..]), Some("Vertical."));
+        let action = ast.actions.get("move").unwrap();
+        assert_eq!(action.doc.as_deref(), Some("Move it."));
+    }
+
+    #[test]
+    fn attributes() {
+        let ast = parse(
+            "struct RGB { @min(0) @max(255) r: u32, g: u32 }\n\
+             struct Item {\n\
+                 /// Title.\n\
+                 @default(\"untitled\") @maxlen(200) @index title: str,\n\
+                 @merge(lww) color: RGB,\n\
+             }",
+        );
+        assert_eq!(
+            ast.err().map(|e| e.to_string()),
+            Some(BuilderError::AttributeTypeError("merge".into(), "color".into()).to_string())
+        );
+
+        let ast = parse(
+            "struct Item {\n\
+                 /// Title.\n\
+                 @default(\"untitled\") @maxlen(200) @index title: str,\n\
+                 @merge(lww) @min(-1.5) price: decimal(2),\n\
+                 count: u32,\n\
+             }",
+        )
+        .unwrap();
+        let item = ast.structs.get("Item").unwrap();
+        assert_eq!(item.field_docs.get("title").map(|d| &d[..]), Some("Title."));
+        assert_eq!(
+            item.attributes.get("title"),
+            Some(&vec![
+                Attribute::Default(Literal::String("untitled".into())),
+                Attribute::MaxLen(200),
+                Attribute::Index,
+            ])
+        );
+        assert_eq!(
+            item.attributes.get("price"),
+            Some(&vec![
+                Attribute::Merge(MergePolicy::Lww),
+                Attribute::Min("-1.5".into())
+            ])
+        );
+        assert_eq!(item.attributes.get("count"), None);
+
+        let err = |source: &str| parse(source).err().map(|e| e.to_string());
+        assert_eq!(
+            err("struct A { @unique x: u32 }"),
+            Some(BuilderError::UnknownAttribute("unique".into()).to_string())
+        );
+        assert_eq!(
+            err("struct A { @merge(last) x: u32 }"),
+            Some(BuilderError::InvalidAttributeArgument("merge".into()).to_string())
+        );
+        assert_eq!(
+            err("struct A { @index @index x: u32 }"),
+            Some(BuilderError::DuplicateAttribute("index".into()).to_string())
+        );
+        assert_eq!(
+            err("struct A { @maxlen(3) x: u32 }"),
+            Some(BuilderError::AttributeTypeError("maxlen".into(), "x".into()).to_string())
+        );
+        assert_eq!(
+            err("struct A { @default(-1) x: u32 }"),
+            Some(BuilderError::AttributeTypeError("default".into(), "x".into()).to_string())
+        );
+        assert_eq!(
+            err("struct A { @min(5) @default(2) x: u32 }"),
+            Some(BuilderError::ConflictingAttributes("x".into()).to_string())
+        );
+        assert_eq!(
+            err("struct B {}\nstruct A { @default(null) @index x: ref B }"),
+            None
+        );
+    }
+
+    fn write_files(name: &str, files: &[(&str, &str)]) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("ross-parser-{}", name));
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        for (path, source) in files {
+            std::fs::write(dir.join(path), source).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn imports() {
+        let dir = write_files(
+            "imports",
+            &[
+                ("main.ross", "import \"lib/shapes.ross\" as shapes;\nstruct Scene { c: shapes.Circle, p: ref shapes.Point }"),
+                ("lib/shapes.ross", "import \"point.ross\" as point;\nstruct Point { x: num, y: num }\nstruct Circle { center: point.P, r: num }"),
+                ("lib/point.ross", "struct P { x: u32, y: u32 }"),
+            ],
+        );
+        let ast = parse_file(&dir.join("main.ross")).unwrap();
+        let shapes = ast.mods.get("shapes").unwrap();
+        assert!(shapes.mods.get("point").unwrap().structs.contains_key("P"));
+        assert_eq!(
+            ast.structs.get("Scene").unwrap().type_vec,
+            vec![
+                PrimitiveType::U32,
+                PrimitiveType::U32,
+                PrimitiveType::Num,
+                PrimitiveType::Hash
+            ]
+        );
+    }
+
+    #[test]
+    fn import_cycle() {
+        let dir = write_files(
+            "cycle",
+            &[
+                ("a.ross", "import \"b.ross\" as b;"),
+                ("b.ross", "import \"lib/c.ross\" as c;"),
+                ("lib/c.ross", "import \"../b.ross\" as b;"),
+            ],
+        );
+        match parse_file(&dir.join("a.ross")) {
+            Err(SourceError {
+                error: BuilderError::ImportCycle(files),
+                ..
+            }) => {
+                assert_eq!(files.len(), 3);
+                assert!(files[0].ends_with("b.ross"));
+                assert!(files[2].ends_with("b.ross"));
+            }
+            r => panic!("Unexpected result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn error_location() {
+        let dir = write_files(
+            "location",
+            &[("lib/shapes.ross", "struct A {\n  x: num,\n  x: str\n}")],
+        );
+        let path = dir.join("main.ross");
+
+        let e = parse_source("struct B {\n  a: num\n  b: num }", &path).unwrap_err();
+        assert!(matches!(e.error, BuilderError::Syntax(_)));
+        assert_eq!(e.line, 2);
+
+        let source = "struct B {}\nimport \"lib/shapes.ross\" as shapes;";
+        let e = parse_source(source, &path).unwrap_err();
+        assert!(matches!(e.error, BuilderError::NameAlreadyInUse(_)));
+        assert!(e.file.unwrap().ends_with("shapes.ross"));
+        assert_eq!((e.line, e.column), (3, 3));
+        assert_eq!(e.span, (12, source.len()));
+    }
+}
@@ -3,6 +3,7 @@ const { execSync } = require("child_process");
 
 execSync("../target/debug/ross_compiler gen ross/circle.ross dist/circle");
 execSync("../target/debug/ross_compiler gen ross/scene.ross dist/scene");
+execSync("../target/debug/ross_compiler gen ross/bank.ross dist/bank");
 
 test("object constructor", (t) => {
   const {
@@ -51,3 +52,26 @@ test("decode", (t) => {
   t.deepEqual(p, decode(null, p.encode()));
   t.deepEqual(c, decode(null, c.encode()));
 });
+
+test("tagged values", (t) => {
+  const {
+    root: { Account },
+    decode,
+    s,
+    r,
+  } = require("./dist/bank/client");
+
+  const a = new Account(5n, "1.50");
+  t.deepEqual(a.encode(), [0, { i64: "5" }, { decimal: "1.50" }]);
+  t.deepEqual(a, decode(null, JSON.parse(JSON.stringify(a.encode()))));
+
+  // The literals of the generated actions are a bigint and a string.
+  const ref = { id: "0".repeat(32), version: 0, data: a };
+  t.is(
+    JSON.stringify([...s(ref, 0, 7n), ...r(ref, 1, "1.5")]),
+    JSON.stringify([
+      { type: "cas", id: ref.id, field: 1, base: { i64: "5" }, target: { i64: "7" } },
+      { type: "cas", id: ref.id, field: 2, base: { decimal: "1.5" }, target: { decimal: "1.5" } },
+    ])
+  );
+});
@@ -0,0 +1,332 @@
+use super::gc::Pending;
+use super::Context;
+use crate::db::keys::{self, DbReadKey};
+use crate::error::*;
+use crate::types::*;
+use std::collections::HashSet;
+use std::io::{Read, Write};
+
+impl<'a, R> Context<'a, R> {
+    /// Write a repository as a bundle, the live changes are included but the
+    /// history of the origin of a fork is not.
+    pub fn export_bundle<W: Write>(&self, repository: RepositoryId, writer: W) -> Result<W> {
+        if self.db.get(keys::RepositoryDeleted(&repository))?.is_some() {
+            return Err(Error::RepositoryNotFound);
+        }
+        let info = self
+            .db
+            .get(keys::Repository(&repository))?
+            .ok_or(Error::RepositoryNotFound)?;
+
+        let mut writer = BundleWriter::new(writer);
+        writer.write(&BundleRecord::Header {
+            version: BUNDLE_VERSION,
+            repository,
+        })?;
+        writer.write(&BundleRecord::Repository(info))?;
+        if let Some(log) = self.db.get(keys::Log(&repository))? {
+            writer.write(&BundleRecord::Log(log))?;
+        }
+        for (id, info) in keys::Branch::key_value_iterator(&self.db, &repository)
+            .take_while(|(id, _)| id.repository == repository)
+        {
+            writer.write(&BundleRecord::Branch(id.id, info))?;
+        }
+        for (id, info) in keys::Tag::key_value_iterator(&self.db, &repository)
+            .take_while(|(id, _)| id.repository == repository)
+        {
+            writer.write(&BundleRecord::Tag(id.name, info))?;
+        }
+        for (id, info) in keys::Commit::key_value_iterator(&self.db, &repository)
+            .take_while(|(id, _)| id.repository == repository)
+        {
+            writer.write(&BundleRecord::Commit(id.hash, info))?;
+        }
+        for (id, entry) in keys::CommitSnapshot::key_value_iterator(&self.db, &repository)
+            .take_while(|(id, _)| id.repository == repository)
+        {
+            writer.write(&BundleRecord::Snapshot(id.hash, entry))?;
+        }
+        for (id, patches) in keys::LiveChanges::key_value_iterator(&self.db, &repository)
+            .take_while(|(id, _)| id.repository == repository)
+        {
+            writer.write(&BundleRecord::LiveChanges(id.id, patches))?;
+        }
+        for (id, delta) in keys::PackedDelta::key_value_iterator(&self.db, &repository)
+            .take_while(|(id, _)| id.repository == repository)
+        {
+            writer.write(&BundleRecord::PackedDelta(id.id, delta))?;
+        }
+        writer.finish()
+    }
+
+    /// Import a repository from a bundle, the repository keeps its id so it must
+    /// not exist. The records are written in batches of `batch_size` while the
+    /// repository is marked as deleted, and they're removed if the bundle turns
+    /// out to be invalid.
+    pub fn import_bundle<Rd: Read>(&self, reader: Rd, batch_size: usize) -> Result<RepositoryId> {
+        let mut reader = BundleReader::new(reader);
+        let repository = match reader.read()? {
+            BundleRecord::Header {
+                version: BUNDLE_VERSION,
+                repository,
+            } => repository,
+            BundleRecord::Header { .. } => {
+                return Err(Error::InvalidBundle("unsupported version".into()))
+            }
+            _ => return Err(Error::InvalidBundle("missing the header".into())),
+        };
+        let info = match reader.read()? {
+            BundleRecord::Repository(info) => info,
+            _ => return Err(Error::InvalidBundle("missing the repository".into())),
+        };
+        if self.db.get(keys::RepositoryExists(&repository))?.is_some() {
+            return Err(Error::RepositoryExists);
+        }
+
+        let mut batch = self.db.batch();
+        batch.put(keys::RepositoryDeleted(&repository), &());
+        batch.put(keys::Repository(&repository), &info);
+        batch.write()?;
+
+        if let Err(e) = self.import_records(repository, &mut reader, batch_size) {
+            self.purge(repository)?;
+            return Err(e);
+        }
+
+        let mut batch = self.db.batch();
+        batch.delete(keys::RepositoryDeleted(&repository));
+        batch.write()?;
+        Ok(repository)
+    }
+
+    fn import_records<Rd: Read>(
+        &self,
+        repository: RepositoryId,
+        reader: &mut BundleReader<Rd>,
+        batch_size: usize,
+    ) -> Result<()> {
+        let branch = |id| BranchIdentifier { repository, id };
+        let commit = |hash| CommitIdentifier { repository, hash };
+        let mut commits = HashSet::new();
+        let mut snapshots = HashSet::new();
+        // The commits that must exist once everything is imported.
+        let mut references = Vec::new();
+        let mut pending = Pending::new(self, batch_size);
+
+        loop {
+            match reader.read()? {
+                BundleRecord::End(_) => break,
+                BundleRecord::Header { .. } | BundleRecord::Repository(_) => {
+                    return Err(Error::InvalidBundle("unexpected record".into()));
+                }
+                BundleRecord::Log(events) => {
+                    pending.batch.put(keys::Log(&repository), &events);
+                }
+                BundleRecord::Branch(id, info) => {
+                    references.push(info.head);
+                    references.extend(info.fork_point.map(|(_, fork)| fork));
+                    pending.batch.put(keys::Branch(&branch(id)), &info);
+                }
+                BundleRecord::Tag(name, info) => {
+                    references.push(info.commit);
+                    let id = TagIdentifier { repository, name };
+                    pending.batch.put(keys::Tag(&id), &info);
+                }
+                BundleRecord::Commit(hash, info) => {
+                    if info.hash() != hash {
+                        return Err(Error::InvalidBundle(format!(
+                            "hash mismatch of the commit {}",
+                            String::from(&hash.0)
+                        )));
+                    }
+                    references.extend(info.parents.iter().copied());
+                    commits.insert(hash);
+                    pending.batch.put(keys::Commit(&commit(hash)), &info);
+                }
+                BundleRecord::Snapshot(hash, entry) => {
+                    if let SnapshotEntry::Delta { base, .. } = &entry {
+                        references.push(*base);
+                    }
+                    snapshots.insert(hash);
+                    pending
+                        .batch
+                        .put(keys::CommitSnapshot(&commit(hash)), &entry);
+                }
+                BundleRecord::LiveChanges(id, patches) => {
+                    pending.batch.put(keys::LiveChanges(&branch(id)), &patches);
+                }
+                BundleRecord::PackedDelta(id, delta) => {
+                    pending.batch.put(keys::PackedDelta(&branch(id)), &delta);
+                }
+            }
+            pending.flush(self)?;
+        }
+        pending.write(self)?;
+
+        if commits != snapshots {
+            return Err(Error::InvalidBundle(
+                "the commits and the snapshots do not match".into(),
+            ));
+        }
+        for id in references {
+            let exists = if id.repository == repository {
+                commits.contains(&id.hash)
+            } else {
+                self.db.get(keys::CommitOrigin(&id))?.is_some()
+            };
+            if !exists {
+                return Err(Error::InvalidBundle(format!(
+                    "missing the commit {}",
+                    String::from(&id.hash.0)
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test_utils::{id, TempContext};
+    use super::*;
+    use crate::db::keys::DbKey;
+    use crate::utils::hash::Hash16;
+
+    /// Create a repository with two commits, a tag and a live change.
+    fn seed(ctx: &Context<()>) -> (RepositoryId, BranchIdentifier) {
+        let repository = RepositoryId(rand::random());
+        let branch = BranchIdentifier {
+            repository,
+            id: BranchId(Hash16::MIN),
+        };
+        let user = UserId(Hash16::MIN);
+        let u = PrimitiveValue::U32;
+        let insert = |oid, data| PatchAtom::Insert {
+            oid,
+            data,
+            version: None,
+        };
+
+        let mut state = State::default();
+        state
+            .perform(vec![insert(id(1), vec![u(0), u(1)])])
+            .unwrap();
+        let root = CommitInfo {
+            origin: CommitInfoOrigin {
+                branch,
+                fork_point: None,
+                order: 0,
+            },
+            time: 1,
+            parents: vec![],
+            committer: user,
+            authors: vec![user],
+            message: "Init".into(),
+        };
+        let root_id = CommitIdentifier {
+            repository,
+            hash: root.hash(),
+        };
+        let info = BranchInfo {
+            head: root_id,
+            fork_point: None,
+            created_at: 1,
+            user,
+            mode: BranchMode::Normal,
+            title: "main".into(),
+        };
+        let mut batch = ctx.db.batch();
+        batch.put(
+            keys::Repository(&repository),
+            &RepositoryInfo {
+                owner: user,
+                fork_of: None,
+                created_at: 1,
+            },
+        );
+        batch.put(keys::Commit(&root_id), &root);
+        batch.put(
+            keys::CommitSnapshot(&root_id),
+            &SnapshotEntry::Snapshot(state),
+        );
+        batch.put(keys::Branch(&branch), &info);
+        batch.push(keys::Log(&repository), &LogEvent::Init { user, time: 1 });
+        batch.write().unwrap();
+
+        let mut delta = Delta::new();
+        delta.insert(
+            id(2),
+            DeltaEntry::Inserted {
+                data: vec![u(0), u(2)],
+                version: 0,
+            },
+        );
+        let head = ctx
+            .commit_delta(&branch, info, delta, user, "Add".into())
+            .unwrap();
+        ctx.create_tag("v1".into(), &head, user, Some("First".into()))
+            .unwrap();
+        ctx.db
+            .push(
+                keys::LiveChanges(&branch),
+                &Patch {
+                    user,
+                    time: 2,
+                    action: 0,
+                    actions: vec![insert(id(3), vec![u(0), u(3)])],
+                },
+            )
+            .unwrap();
+        (repository, branch)
+    }
+
+    #[test]
+    fn round_trip() {
+        let source = TempContext::new();
+        let (repository, branch) = seed(&source);
+        let bundle = source.export_bundle(repository, Vec::new()).unwrap();
+
+        let target = TempContext::new();
+        assert_eq!(target.import_bundle(&bundle[..], 2).unwrap(), repository);
+        let revision = Revision::Branch(branch);
+        let state = target.state(&revision).unwrap();
+        assert_eq!(state.len(), 3);
+        assert!(source.state(&revision).unwrap().diff(&state).is_empty());
+        assert_eq!(target.log(&repository, 0, 10).unwrap().len(), 3);
+        assert_eq!(target.tags(repository).len(), 1);
+        assert_eq!(target.commits(&branch, true).unwrap().count(), 2);
+
+        assert!(matches!(
+            target.import_bundle(&bundle[..], 2),
+            Err(Error::RepositoryExists)
+        ));
+    }
+
+    #[test]
+    fn invalid() {
+        let source = TempContext::new();
+        let (repository, _) = seed(&source);
+        let mut bundle = source.export_bundle(repository, Vec::new()).unwrap();
+        let len = bundle.len();
+        bundle[len - 30] ^= 1;
+
+        let target = TempContext::new();
+        assert!(matches!(
+            target.import_bundle(&bundle[..], 2),
+            Err(Error::InvalidBundle(_))
+        ));
+        // Nothing is left behind.
+        assert!(target
+            .db
+            .get(keys::Repository(&repository))
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            keys::Commit::key_iterator(&target.db, &repository)
+                .take_while(|id| id.repository == repository)
+                .count(),
+            0
+        );
+    }
+}
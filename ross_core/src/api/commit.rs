@@ -0,0 +1,433 @@
+use super::{Context, Recipient};
+use crate::db::{keys, Batch};
+use crate::error::*;
+use crate::types::*;
+use crate::utils::clock::now;
+
+impl<'a, R> Context<'a, R>
+where
+    R: Recipient,
+{
+    /// Undo the changes of a commit on a branch by a new commit, a merge commit
+    /// is reverted with respect to its first parent.
+    pub fn revert(
+        &self,
+        branch: &BranchIdentifier,
+        commit: &CommitIdentifier,
+        user: UserId,
+    ) -> Result<CommitIdentifier> {
+        let info = self.commit(commit)?;
+        let parent = self.parent_state(&info)?;
+        let state = self.checkout(commit)?;
+        let delta = state.diff(&parent);
+        let message = format!("Revert \"{}\"", info.message);
+        self.replay(branch, &state, &delta, user, message)
+    }
+
+    /// Replay the changes of a commit, possibly from another branch, on a branch
+    /// by a new commit.
+    pub fn cherry_pick(
+        &self,
+        branch: &BranchIdentifier,
+        commit: &CommitIdentifier,
+        user: UserId,
+    ) -> Result<CommitIdentifier> {
+        let info = self.commit(commit)?;
+        let parent = self.parent_state(&info)?;
+        let delta = parent.diff(&self.checkout(commit)?);
+        self.replay(branch, &parent, &delta, user, info.message)
+    }
+
+    /// Replay the commits of a branch on the current head of the branch that it
+    /// was forked from, the live changes are replayed too if `live_changes` is
+    /// set, otherwise the branch must not have any.  
+    /// The commits are replayed along their first-parent chain, so the rebased
+    /// branch is linear. Nothing is changed if any of them conflicts.
+    pub fn rebase(
+        &self,
+        branch: &BranchIdentifier,
+        live_changes: bool,
+        user: UserId,
+    ) -> Result<CommitIdentifier> {
+        let mut info = self
+            .db
+            .get(keys::Branch(branch))?
+            .ok_or(Error::BranchNotFound)?;
+        if let BranchMode::Archived | BranchMode::StaticArchived = info.mode {
+            return Err(Error::BranchArchived);
+        }
+        let (parent, fork) = info.fork_point.ok_or(Error::NotForked)?;
+        let base = self
+            .db
+            .get(keys::Branch(&parent))?
+            .ok_or(Error::BranchNotFound)?
+            .head;
+        if base == fork {
+            return Ok(info.head);
+        }
+
+        let packed_delta = self.db.get(keys::PackedDelta(branch))?;
+        let changes = self.db.get(keys::LiveChanges(branch))?.unwrap_or_default();
+        if (packed_delta.is_some() || !changes.is_empty()) && !live_changes {
+            return Err(Error::UncommittedChanges);
+        }
+
+        let mut commits = Vec::new();
+        for item in self.walk(&info.head, true)? {
+            let (commit, commit_info) = item?;
+            if commit == fork {
+                break;
+            }
+            commits.push((commit, commit_info));
+        }
+
+        // The rebased commits come after the new base in the order of the LCA.
+        let base_order = self
+            .db
+            .get(keys::CommitOrigin(&base))?
+            .ok_or(Error::CommitNotFound)?
+            .order;
+
+        // `old` follows the original commits and `state` the rebased ones.
+        let mut old = self.checkout(&fork)?;
+        let mut state = self.checkout(&base)?;
+        let mut previous = fork;
+        let mut head = base;
+        let mut batch = self.db.batch();
+        let mut time = now();
+        for (i, (commit, mut commit_info)) in commits.into_iter().rev().enumerate() {
+            let delta = self.diff(&previous, &commit)?;
+            let rebased = state
+                .merge_delta(&old, &delta, &self.schema)
+                .map_err(Error::MergeConflict)?;
+            old.apply_delta_trusted(delta);
+            state.apply_delta_trusted(rebased.clone());
+
+            // Keep the times increasing, so the commits are found by `checkout_at`.
+            time = now().max(time + 1);
+            commit_info.origin = CommitInfoOrigin {
+                branch: *branch,
+                fork_point: Some((parent, base)),
+                order: base_order + 1 + i as u32,
+            };
+            commit_info.time = time;
+            commit_info.parents = vec![head];
+            commit_info.committer = user;
+            head = put_commit(&mut batch, branch.repository, &commit_info, rebased);
+            previous = commit;
+        }
+
+        // The live changes are kept as they are, they only have to apply on the
+        // new head.
+        if let Some(delta) = packed_delta {
+            let rebased = state
+                .merge_delta(&old, &delta, &self.schema)
+                .map_err(Error::MergeConflict)?;
+            state.apply_delta_trusted(rebased.clone());
+            batch.put(keys::PackedDelta(branch), &rebased);
+        }
+        for patch in changes {
+            state
+                .perform_with_schema(patch.actions, &self.schema)
+                .map_err(Error::PatchConflict)?;
+        }
+
+        info.head = head;
+        info.fork_point = Some((parent, base));
+        batch.put(keys::Branch(branch), &info);
+        batch.push(
+            keys::Log(&branch.repository),
+            &LogEvent::Rebased {
+                branch: branch.id,
+                head: head.hash,
+                user,
+                time,
+            },
+        );
+        batch.write()?;
+        self.reload_editor(branch)?;
+
+        Ok(head)
+    }
+
+    /// The state of the first parent of a commit, the root commit has an empty
+    /// parent.
+    fn parent_state(&self, info: &CommitInfo) -> Result<State> {
+        match info.parents.first() {
+            Some(parent) => self.checkout(parent),
+            None => Ok(State::default()),
+        }
+    }
+
+    /// Commit the delta that was computed on the `base` state to the branch, the
+    /// branch must not have any live changes.
+    fn replay(
+        &self,
+        branch: &BranchIdentifier,
+        base: &State,
+        delta: &Delta,
+        user: UserId,
+        message: String,
+    ) -> Result<CommitIdentifier> {
+        let info = self.clean_branch(branch)?;
+        let delta = self
+            .checkout(&info.head)?
+            .merge_delta(base, delta, &self.schema)
+            .map_err(Error::MergeConflict)?;
+        let commit = self.commit_delta(branch, info, delta, user, message)?;
+        self.reload_editor(branch)?;
+        Ok(commit)
+    }
+
+    /// Returns the info of a branch that can be committed to, it must not be
+    /// archived or have uncommitted changes.
+    pub(super) fn clean_branch(&self, branch: &BranchIdentifier) -> Result<BranchInfo> {
+        let info = self
+            .db
+            .get(keys::Branch(branch))?
+            .ok_or(Error::BranchNotFound)?;
+        if let BranchMode::Archived | BranchMode::StaticArchived = info.mode {
+            return Err(Error::BranchArchived);
+        }
+        let live_changes = self.db.get(keys::LiveChanges(branch))?;
+        if matches!(live_changes, Some(c) if !c.is_empty())
+            || self.db.get(keys::PackedDelta(branch))?.is_some()
+        {
+            return Err(Error::UncommittedChanges);
+        }
+        Ok(info)
+    }
+
+    /// Store a new commit on top of the head of the branch, the delta is the
+    /// change from the head.
+    pub(super) fn commit_delta(
+        &self,
+        branch: &BranchIdentifier,
+        mut info: BranchInfo,
+        delta: Delta,
+        user: UserId,
+        message: String,
+    ) -> Result<CommitIdentifier> {
+        let head = self
+            .db
+            .get(keys::CommitOrigin(&info.head))?
+            .ok_or(Error::CommitNotFound)?;
+        let time = now();
+        let commit_info = CommitInfo {
+            origin: CommitInfoOrigin {
+                branch: *branch,
+                fork_point: info.fork_point,
+                // The order increases along the history, even across branches,
+                // the LCA relies on it.
+                order: head.order + 1,
+            },
+            time,
+            parents: vec![info.head],
+            committer: user,
+            authors: vec![user],
+            message,
+        };
+
+        let mut batch = self.db.batch();
+        let commit = put_commit(&mut batch, branch.repository, &commit_info, delta);
+        info.head = commit;
+        batch.put(keys::Branch(branch), &info);
+        batch.push(
+            keys::Log(&branch.repository),
+            &LogEvent::Committed {
+                branch: branch.id,
+                hash: commit.hash,
+                user,
+                time,
+            },
+        );
+        batch.write()?;
+
+        Ok(commit)
+    }
+}
+
+/// Add a commit to the batch, the delta is the change from its first parent.
+fn put_commit(
+    batch: &mut Batch,
+    repository: RepositoryId,
+    info: &CommitInfo,
+    delta: Delta,
+) -> CommitIdentifier {
+    let commit = CommitIdentifier {
+        repository,
+        hash: info.hash(),
+    };
+    batch.put(keys::Commit(&commit), info);
+    batch.put(
+        keys::CommitSnapshot(&commit),
+        &SnapshotEntry::Delta {
+            base: info.parents[0],
+            delta,
+        },
+    );
+    commit
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test_utils::*;
+    use super::*;
+
+    fn field(state: &State, n: u8) -> Option<&PrimitiveValue> {
+        state.get(&id(n)).map(|obj| obj.get(1))
+    }
+
+    #[test]
+    fn rebase() {
+        let ctx = TempContext::new();
+        let main = init(&ctx);
+        let branch = branch(&ctx, &main, 1);
+        commit(&ctx, &branch, vec![insert(1, 1)]);
+        commit(&ctx, &branch, vec![set(1, 1, 2)]);
+        let base = commit(&ctx, &main, vec![insert(2, 5)]);
+
+        let head = ctx.rebase(&branch, false, USER).unwrap();
+        let info = ctx.db.get(keys::Branch(&branch)).unwrap().unwrap();
+        assert_eq!(info.head, head);
+        assert_eq!(info.fork_point, Some((main, base)));
+
+        let state = ctx.state(&Revision::Branch(branch)).unwrap();
+        assert_eq!(field(&state, 1), Some(&PrimitiveValue::U32(2)));
+        assert_eq!(field(&state, 2), Some(&PrimitiveValue::U32(5)));
+
+        let base_info = ctx.commit(&base).unwrap();
+        let commits: Vec<_> = ctx
+            .walk(&head, true)
+            .unwrap()
+            .map(|item| item.unwrap())
+            .take(3)
+            .collect();
+        assert_eq!(commits[1].1.parents, vec![base]);
+        assert_eq!(commits[2].0, base);
+        assert!(commits[0].1.time > commits[1].1.time);
+        assert!(commits[1].1.time > base_info.time);
+        assert_eq!(commits[0].1.origin.order, base_info.origin.order + 2);
+        assert_eq!(commits[1].1.origin.order, base_info.origin.order + 1);
+
+        // Nothing to do when the branch is already on the head of its parent.
+        assert_eq!(ctx.rebase(&branch, false, USER).unwrap(), head);
+    }
+
+    #[test]
+    fn rebase_conflict() {
+        let ctx = TempContext::new();
+        let main = init(&ctx);
+        commit(&ctx, &main, vec![insert(1, 1)]);
+        let branch = branch(&ctx, &main, 1);
+        let head = commit(&ctx, &branch, vec![set(1, 1, 2)]);
+        commit(&ctx, &main, vec![set(1, 1, 3)]);
+
+        assert!(matches!(
+            ctx.rebase(&branch, false, USER),
+            Err(Error::MergeConflict(_))
+        ));
+        let info = ctx.db.get(keys::Branch(&branch)).unwrap().unwrap();
+        assert_eq!(info.head, head);
+    }
+
+    #[test]
+    fn rebase_live_changes() {
+        let ctx = TempContext::new();
+        let main = init(&ctx);
+        let branch = branch(&ctx, &main, 1);
+        commit(&ctx, &branch, vec![insert(1, 1)]);
+        live(&ctx, &branch, vec![set(1, 1, 2)]);
+        live(&ctx, &branch, vec![insert(3, 3)]);
+        commit(&ctx, &main, vec![insert(2, 5)]);
+
+        assert!(matches!(
+            ctx.rebase(&branch, false, USER),
+            Err(Error::UncommittedChanges)
+        ));
+        ctx.rebase(&branch, true, USER).unwrap();
+
+        // Every patch is kept on its own.
+        let changes = ctx.db.get(keys::LiveChanges(&branch)).unwrap().unwrap();
+        assert_eq!(changes.len(), 2);
+        let state = ctx.state(&Revision::Branch(branch)).unwrap();
+        assert_eq!(field(&state, 1), Some(&PrimitiveValue::U32(2)));
+        assert_eq!(field(&state, 2), Some(&PrimitiveValue::U32(5)));
+        assert_eq!(field(&state, 3), Some(&PrimitiveValue::U32(3)));
+    }
+
+    #[test]
+    fn merge_base() {
+        let ctx = TempContext::new();
+        let main = init(&ctx);
+        let fork = commit(&ctx, &main, vec![insert(1, 1)]);
+        let branch = branch(&ctx, &main, 1);
+        let theirs = commit(&ctx, &main, vec![insert(2, 2)]);
+        commit(&ctx, &branch, vec![insert(3, 3)]);
+        let ours = commit(&ctx, &branch, vec![set(3, 3, 4)]);
+
+        let fork_order = ctx.commit(&fork).unwrap().origin.order;
+        assert_eq!(ctx.commit(&ours).unwrap().origin.order, fork_order + 2);
+        assert_eq!(ctx.merge_base(&ours, &theirs).unwrap(), fork);
+        assert_eq!(ctx.merge_base(&theirs, &ours).unwrap(), fork);
+        assert_eq!(ctx.merge_base(&fork, &ours).unwrap(), fork);
+    }
+
+    #[test]
+    fn revert() {
+        let ctx = TempContext::new();
+        let main = init(&ctx);
+        let object = PatchAtom::Insert {
+            oid: id(1),
+            data: vec![PrimitiveValue::U32(0), 1u32.into(), 1u32.into()],
+            version: None,
+        };
+        let cas = |field, current: u32, target: u32| PatchAtom::CAS {
+            oid: id(1),
+            field,
+            current: current.into(),
+            target: target.into(),
+        };
+        commit(&ctx, &main, vec![object]);
+        let change = commit(&ctx, &main, vec![cas(1, 1, 2), cas(2, 1, 2)]);
+        commit(&ctx, &main, vec![insert(2, 5)]);
+
+        let head = ctx.revert(&main, &change, USER).unwrap();
+        assert_eq!(ctx.commit(&head).unwrap().message, "Revert \"Commit\"");
+        let state = ctx.checkout(&head).unwrap();
+        let obj = state.get(&id(1)).unwrap();
+        assert_eq!(obj.data[1..], [1u32.into(), 1u32.into()]);
+        // Both of the fields were written twice.
+        assert_eq!(obj.version, 4);
+        assert_eq!(field(&state, 2), Some(&PrimitiveValue::U32(5)));
+    }
+
+    #[test]
+    fn cherry_pick() {
+        let ctx = TempContext::new();
+        let main = init(&ctx);
+        commit(&ctx, &main, vec![insert(1, 1)]);
+        let branch = branch(&ctx, &main, 1);
+        let pick = commit(&ctx, &branch, vec![set(1, 1, 2)]);
+        let added = commit(&ctx, &branch, vec![insert(2, 5)]);
+        commit(&ctx, &main, vec![set(1, 1, 3)]);
+
+        let head = ctx.cherry_pick(&main, &added, USER).unwrap();
+        let state = ctx.checkout(&head).unwrap();
+        assert_eq!(field(&state, 1), Some(&PrimitiveValue::U32(3)));
+        assert_eq!(field(&state, 2), Some(&PrimitiveValue::U32(5)));
+
+        assert!(matches!(
+            ctx.cherry_pick(&main, &pick, USER),
+            Err(Error::MergeConflict(_))
+        ));
+
+        live(&ctx, &main, vec![set(1, 3, 4)]);
+        assert!(matches!(
+            ctx.cherry_pick(&main, &added, USER),
+            Err(Error::UncommittedChanges)
+        ));
+        let info = ctx.db.get(keys::Branch(&main)).unwrap().unwrap();
+        assert_eq!(info.head, head);
+    }
+}
@@ -8,17 +8,31 @@ use std::sync::Mutex;
 
 pub struct Context<'a, R> {
     pub(super) db: DB,
+    /// Constraints that are enforced on the patches.
+    pub(super) schema: Schema,
     editors: Mutex<TTLMap<BranchIdentifier, EditorLock<'a, R>>>,
 }
 
 impl<'a, R> Context<'a, R> {
     pub fn new(path: &str) -> Self {
+        Self::with_schema(path, Schema::default())
+    }
+
+    /// Open the context with the schema that the patches must follow.
+    pub fn with_schema(path: &str, schema: Schema) -> Self {
         Self {
             db: DB::open(path),
+            schema,
             editors: Mutex::new(TTLMap::new(10, 60000)),
         }
     }
 
+    /// The schema that the patches must follow.
+    #[inline]
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
     /// Returns the snapshot of a commit.
     #[inline]
     pub fn checkout(&self, commit: &CommitIdentifier) -> Result<State> {
@@ -69,6 +83,52 @@ impl<'a, R> Context<'a, R> {
         Ok(Session::new(EditorBox::new(editor), user, sender)?)
     }
 
+    /// Reload the editor of a branch if it is open, so it sees the changes that
+    /// are made to the branch outside of the editor.
+    pub(super) fn reload_editor(&self, target: &BranchIdentifier) -> Result<()>
+    where
+        R: Recipient,
+    {
+        let editor = {
+            let editors = self.editors.lock().map_err(|_| Error::AcquireLock)?;
+            editors.get(target).cloned()
+        };
+        if let Some(editor) = editor {
+            editor
+                .write()
+                .map_err(|_| Error::AcquireWriteLock)?
+                .reload()?;
+        }
+        Ok(())
+    }
+
+    /// Run the function while no session can be opened, it fails if there is an
+    /// open session on any branch of the repository. The idle editors of the
+    /// repository are closed.
+    pub(super) fn without_sessions<T, F: FnOnce() -> Result<T>>(
+        &self,
+        repository: RepositoryId,
+        f: F,
+    ) -> Result<T> {
+        let mut editors = self.editors.lock().map_err(|_| Error::AcquireLock)?;
+        let targets: Vec<BranchIdentifier> = editors
+            .iter()
+            .filter(|(target, _)| target.repository == repository)
+            .map(|(target, editor)| {
+                // The one in the map is the only reference to an idle editor.
+                if editor.strong_count() > 1 {
+                    Err(Error::RepositoryInUse)
+                } else {
+                    Ok(*target)
+                }
+            })
+            .collect::<Result<_>>()?;
+        for target in &targets {
+            editors.remove(target);
+        }
+        f()
+    }
+
     #[inline]
     pub(super) fn drop_editor(&self, target: BranchIdentifier) {
         if let Ok(mut editors) = self.editors.lock() {
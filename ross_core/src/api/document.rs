@@ -0,0 +1,35 @@
+use super::{Context, Recipient};
+use crate::error::*;
+use crate::types::*;
+use serde_json::Value;
+
+impl<'a, R> Context<'a, R> {
+    /// Dump the state of a commit as a JSON document, the struct and field names
+    /// are resolved from the schema of the context.
+    pub fn export_json(&self, commit: &CommitIdentifier) -> Result<Value> {
+        Ok(self.checkout(commit)?.to_json(&self.schema))
+    }
+}
+
+impl<'a, R> Context<'a, R>
+where
+    R: Recipient,
+{
+    /// Replace the content of a branch with the objects of a JSON document, in
+    /// the format of `export_json`, by a new commit.
+    /// The branch must not have uncommitted changes.
+    pub fn import_json(
+        &self,
+        branch: &BranchIdentifier,
+        document: &Value,
+        user: UserId,
+        message: String,
+    ) -> Result<CommitIdentifier> {
+        let info = self.clean_branch(branch)?;
+        let head = self.checkout(&info.head)?;
+        let state = State::from_json(document, &self.schema, &head)?;
+        let commit = self.commit_delta(branch, info, head.diff(&state), user, message)?;
+        self.reload_editor(branch)?;
+        Ok(commit)
+    }
+}
@@ -17,6 +17,7 @@ pub struct EditorData {
     packed_delta: Option<Delta>,
     live_changes: Vec<Patch>,
     state: State,
+    indexes: Indexes,
 }
 
 /// An opaque type to represent a handle to a session, used in some methods
@@ -46,16 +47,23 @@ where
             return Ok(());
         }
 
+        let deleted = keys::RepositoryDeleted(&self.target.repository);
+        if self.context.db.get(deleted)?.is_some() {
+            return Err(Error::RepositoryNotFound);
+        }
+
         let info = self
             .context
             .db
             .get(keys::Branch(&self.target))?
             .ok_or(Error::BranchNotFound)?;
 
+        let schema = &self.context.schema;
         let mut state = self.context.checkout(&info.head)?;
+        let mut indexes = Indexes::build(schema, &state);
         let packed_delta = self.context.db.get(keys::PackedDelta(&self.target))?;
         if let Some(delta) = &packed_delta {
-            state.apply_delta_trusted(delta.clone());
+            indexes.apply_delta(schema, &mut state, delta.clone());
         }
 
         let live_changes = self
@@ -64,9 +72,10 @@ where
             .get(keys::LiveChanges(&self.target))?
             .unwrap_or(Vec::new());
         for patch in live_changes.iter() {
-            state
-                .perform(patch.actions.clone())
+            let inverse = state
+                .perform_with_schema(patch.actions.clone(), schema)
                 .map_err(|_| Error::CheckoutFailed)?;
+            indexes.update(schema, &state, &inverse);
         }
 
         self.data.replace(EditorData {
@@ -74,11 +83,19 @@ where
             packed_delta,
             live_changes,
             state,
+            indexes,
         });
 
         Ok(())
     }
 
+    /// Load the data from the DB again, it is used when the branch is changed
+    /// outside of the editor. (e.g a new commit)
+    pub(super) fn reload(&mut self) -> Result<()> {
+        self.data = None;
+        self.open()
+    }
+
     /// Subscribe to the messages sent by the editor, this method will
     /// return a `RecipientHandle` which can later be used to unsubscribe
     /// from the editor.
@@ -111,7 +128,57 @@ where
         }
     }
 
-    pub fn perform(&mut self, user: &UserId, patch: Patch) {}
+    /// Perform a patch on the live state of the branch, the patch is rejected
+    /// as a whole if it conflicts with the current state or does not satisfy
+    /// the constraints of the schema. Accepted patches are stored as the live
+    /// changes of the branch.
+    pub fn perform(&mut self, user: &UserId, mut patch: Patch) -> Result<()> {
+        self.open()?;
+        let data = self.data.as_mut().unwrap();
+        match data.info.mode {
+            BranchMode::Normal => {}
+            BranchMode::Static => return Err(Error::StaticBranch),
+            BranchMode::Archived | BranchMode::StaticArchived => return Err(Error::BranchArchived),
+        }
+
+        let schema = &self.context.schema;
+        let inverse = data
+            .state
+            .perform_with_schema(patch.actions.clone(), schema)
+            .map_err(Error::PatchConflict)?;
+        patch.user = *user;
+        if let Err(e) = self
+            .context
+            .db
+            .push(keys::LiveChanges(&self.target), &patch)
+        {
+            data.state.apply_delta_trusted(inverse);
+            return Err(e);
+        }
+        data.indexes.update(schema, &data.state, &inverse);
+        data.live_changes.push(patch);
+        Ok(())
+    }
+
+    /// The current state of the branch, it is `None` if the editor is not open.
+    #[inline]
+    pub fn state(&self) -> Option<&State> {
+        self.data.as_ref().map(|data| &data.state)
+    }
+
+    /// The secondary indexes of the current state of the branch.
+    #[inline]
+    pub fn indexes(&self) -> Option<&Indexes> {
+        self.data.as_ref().map(|data| &data.indexes)
+    }
+
+    /// Read the current state of the branch using the schema of the context.
+    #[inline]
+    pub fn query(&self) -> Option<Query<'_>> {
+        self.data
+            .as_ref()
+            .map(|data| Query::with_indexes(&data.state, &self.context.schema, &data.indexes))
+    }
 }
 
 impl<'a, R> Editor<'a, R> {
@@ -121,3 +188,59 @@ impl<'a, R> Editor<'a, R> {
         self.recipients.remove(&session_handle.0);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::super::test_utils::*;
+    use super::*;
+    use crate::utils::hash::Hash16;
+
+    const ARTIFACT: &str = r#"{
+        "version": 1,
+        "structs": [{
+            "name": "Counter",
+            "id": 0,
+            "fields": [],
+            "members": [],
+            "types": ["U32"],
+            "layout": [{ "field": "count", "attributes": { "max": "10" } }]
+        }],
+        "actions": []
+    }"#;
+
+    #[test]
+    fn perform() {
+        let dir = TempDir::new();
+        let schema = Schema::from_artifact(ARTIFACT).unwrap();
+        let ctx = Context::<()>::with_schema(dir.path(), schema);
+        let branch = init(&ctx);
+        let mut editor = Editor::new(&ctx, branch);
+        let patch = |actions| Patch {
+            user: UserId(Hash16::MAX),
+            time: 1,
+            action: 0,
+            actions,
+        };
+        let rejected = |result: Result<()>| matches!(result, Err(Error::PatchConflict(_)));
+
+        editor.perform(&USER, patch(vec![insert(1, 5)])).unwrap();
+        let string = PatchAtom::CAS {
+            oid: id(1),
+            field: 1,
+            current: 5u32.into(),
+            target: "6".into(),
+        };
+        assert!(rejected(editor.perform(&USER, patch(vec![string]))));
+        assert!(rejected(editor.perform(&USER, patch(vec![set(1, 5, 11)]))));
+        assert!(rejected(editor.perform(&USER, patch(vec![set(1, 4, 6)]))));
+        editor.perform(&USER, patch(vec![set(1, 5, 6)])).unwrap();
+
+        // Only the accepted patches are stored.
+        let live_changes = ctx.db.get(keys::LiveChanges(&branch)).unwrap().unwrap();
+        assert_eq!(live_changes.len(), 2);
+        assert_eq!(live_changes[1].user, USER);
+        editor.reload().unwrap();
+        let state = editor.state().unwrap();
+        assert_eq!(state.get(&id(1)).unwrap().get(1), &PrimitiveValue::U32(6));
+    }
+}
@@ -0,0 +1,357 @@
+use super::Context;
+use crate::db::keys::{self, DbKey, DbReadKey};
+use crate::db::Batch;
+use crate::error::*;
+use crate::types::*;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// What was removed by a garbage collection.
+#[derive(Debug, Default)]
+pub struct GcReport {
+    /// Number of the commits that were removed along with their snapshots.
+    pub commits: usize,
+    /// Number of the commits whose delta base was removed, so they are stored
+    /// as a full snapshot now.
+    pub rerooted: usize,
+    /// Number of the deleted branches whose live changes were removed.
+    pub branches: usize,
+}
+
+impl<'a, R> Context<'a, R> {
+    /// Remove the commits of a repository that are not reachable from any branch
+    /// or tag, along with the live changes of the deleted branches. The changes
+    /// are written in batches of at most `batch_size` entries.  
+    /// The keys that are removed and next to each other in the column families
+    /// are deleted with a single range, so a run of unreachable commits is one
+    /// entry of the batch.  
+    /// Forks share their history, so the branches and tags of every repository
+    /// in the fork tree are used as the roots.
+    pub fn gc(&self, repository: RepositoryId, batch_size: usize) -> Result<GcReport> {
+        if self.db.get(keys::RepositoryExists(&repository))?.is_none() {
+            return Err(Error::RepositoryNotFound);
+        }
+        let mut report = GcReport::default();
+
+        // Mark.
+        let mut roots = Vec::new();
+        for related in self.fork_tree(repository)? {
+            let branches = keys::Branch::key_value_iterator(&self.db, &related)
+                .take_while(|(id, _)| id.repository == related);
+            for (_, info) in branches {
+                roots.push(info.head);
+                if let Some((_, fork)) = info.fork_point {
+                    roots.push(fork);
+                }
+            }
+            let tags = keys::Tag::key_value_iterator(&self.db, &related)
+                .take_while(|(id, _)| id.repository == related);
+            roots.extend(tags.map(|(_, tag)| tag.commit));
+        }
+        let mut marked = HashSet::new();
+        while let Some(commit) = roots.pop() {
+            if !marked.insert(commit) {
+                continue;
+            }
+            if let Some(info) = self.db.get(keys::Commit(&commit))? {
+                roots.extend(info.parents);
+            }
+        }
+
+        let mut pending = Pending::new(self, batch_size);
+
+        // Promote the deltas whose base is going to be removed to snapshots,
+        // before anything is removed.
+        let snapshots = keys::CommitSnapshot::key_value_iterator(&self.db, &repository)
+            .take_while(|(id, _)| id.repository == repository);
+        for (commit, entry) in snapshots {
+            match entry {
+                SnapshotEntry::Delta { base, .. }
+                    if marked.contains(&commit)
+                        && base.repository == repository
+                        && !marked.contains(&base) =>
+                {
+                    let state = self.checkout(&commit)?;
+                    pending.batch.put(
+                        keys::CommitSnapshot(&commit),
+                        &SnapshotEntry::Snapshot(state),
+                    );
+                    pending.flush(self)?;
+                    report.rerooted += 1;
+                }
+                _ => {}
+            }
+        }
+        pending.write(self)?;
+
+        // Sweep.
+        let commits = keys::Commit::key_iterator(&self.db, &repository)
+            .take_while(|id| id.repository == repository);
+        let commits = runs(commits, |commit| {
+            let removed = !marked.contains(commit);
+            report.commits += removed as usize;
+            Ok(removed)
+        })?;
+        for (first, last) in commits {
+            pending
+                .batch
+                .delete_range(keys::Commit(&first), keys::Commit(&last));
+            pending
+                .batch
+                .delete_range(keys::CommitSnapshot(&first), keys::CommitSnapshot(&last));
+            pending.batch.delete(keys::Commit(&last));
+            pending.batch.delete(keys::CommitSnapshot(&last));
+            pending.flush(self)?;
+        }
+
+        let mut branches: BTreeSet<BranchIdentifier> =
+            keys::LiveChanges::key_iterator(&self.db, &repository)
+                .take_while(|id| id.repository == repository)
+                .collect();
+        branches.extend(
+            keys::PackedDelta::key_iterator(&self.db, &repository)
+                .take_while(|id| id.repository == repository),
+        );
+        let branches = runs(branches.into_iter(), |branch| {
+            let removed = self.db.get(keys::Branch(branch))?.is_none();
+            report.branches += removed as usize;
+            Ok(removed)
+        })?;
+        for (first, last) in branches {
+            pending
+                .batch
+                .delete_range(keys::LiveChanges(&first), keys::LiveChanges(&last));
+            pending
+                .batch
+                .delete_range(keys::PackedDelta(&first), keys::PackedDelta(&last));
+            pending.batch.delete(keys::LiveChanges(&last));
+            pending.batch.delete(keys::PackedDelta(&last));
+            pending.flush(self)?;
+        }
+        pending.write(self)?;
+
+        Ok(report)
+    }
+
+    /// Returns every repository that is in the same fork tree as the given one.
+    fn fork_tree(&self, repository: RepositoryId) -> Result<Vec<RepositoryId>> {
+        let mut children: HashMap<RepositoryId, Vec<RepositoryId>> = HashMap::new();
+        let mut parents = HashMap::new();
+        for (id, info) in keys::Repository::key_value_iterator(&self.db, &()) {
+            if let Some(origin) = info.fork_of {
+                children.entry(origin).or_default().push(id);
+                parents.insert(id, origin);
+            }
+        }
+
+        let mut root = repository;
+        let mut seen = HashSet::new();
+        while let Some(origin) = parents.get(&root) {
+            if !seen.insert(root) {
+                break;
+            }
+            root = *origin;
+        }
+
+        let mut result = Vec::new();
+        let mut queue = vec![root];
+        let mut seen = HashSet::new();
+        while let Some(id) = queue.pop() {
+            if seen.insert(id) {
+                queue.extend(children.get(&id).into_iter().flatten());
+                result.push(id);
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// Group the sorted keys that are removed into runs of consecutive keys, returns
+/// the first and the last key of each run.
+fn runs<K: Copy>(
+    keys: impl Iterator<Item = K>,
+    mut removed: impl FnMut(&K) -> Result<bool>,
+) -> Result<Vec<(K, K)>> {
+    let mut result = Vec::new();
+    let mut run = None;
+    for key in keys {
+        if removed(&key)? {
+            run = Some(match run {
+                Some((first, _)) => (first, key),
+                None => (key, key),
+            });
+        } else if let Some(run) = run.take() {
+            result.push(run);
+        }
+    }
+    result.extend(run);
+    Ok(result)
+}
+
+/// A batch that is written once it has reached the limit.
+pub(super) struct Pending<'b> {
+    pub batch: Batch<'b>,
+    size: usize,
+    limit: usize,
+}
+
+impl<'b> Pending<'b> {
+    pub fn new<R>(context: &'b Context<R>, limit: usize) -> Self {
+        Pending {
+            batch: context.db.batch(),
+            size: 0,
+            limit: limit.max(1),
+        }
+    }
+
+    #[inline]
+    pub fn flush<R>(&mut self, context: &'b Context<R>) -> Result<()> {
+        self.size += 1;
+        if self.size >= self.limit {
+            self.write(context)?;
+        }
+        Ok(())
+    }
+
+    pub fn write<R>(&mut self, context: &'b Context<R>) -> Result<()> {
+        let batch = std::mem::replace(&mut self.batch, context.db.batch());
+        self.size = 0;
+        batch.write()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test_utils::*;
+    use super::*;
+
+    fn delete_branch(ctx: &Context<()>, branch: &BranchIdentifier) {
+        let mut batch = ctx.db.batch();
+        batch.delete(keys::Branch(branch));
+        batch.write().unwrap();
+    }
+
+    fn exists(ctx: &Context<()>, commit: &CommitIdentifier) -> bool {
+        ctx.db.get(keys::Commit(commit)).unwrap().is_some()
+            || ctx.db.get(keys::CommitSnapshot(commit)).unwrap().is_some()
+    }
+
+    #[test]
+    fn deleted_branch() {
+        let ctx = TempContext::new();
+        let main = init(&ctx);
+        let kept = commit(&ctx, &main, vec![insert(1, 1)]);
+        let branch = branch(&ctx, &main, 1);
+        let first = commit(&ctx, &branch, vec![insert(2, 2)]);
+        let second = commit(&ctx, &branch, vec![set(2, 2, 3)]);
+        live(&ctx, &branch, vec![set(2, 3, 4)]);
+        delete_branch(&ctx, &branch);
+
+        let report = ctx.gc(main.repository, 1).unwrap();
+        assert_eq!(report.commits, 2);
+        assert_eq!(report.rerooted, 0);
+        assert_eq!(report.branches, 1);
+        assert!(exists(&ctx, &kept));
+        assert!(!exists(&ctx, &first));
+        assert!(!exists(&ctx, &second));
+        assert!(ctx.db.get(keys::LiveChanges(&branch)).unwrap().is_none());
+
+        let report = ctx.gc(main.repository, 1).unwrap();
+        assert_eq!(report.commits, 0);
+        assert_eq!(report.branches, 0);
+    }
+
+    #[test]
+    fn ranges() {
+        let ctx = TempContext::new();
+        let main = init(&ctx);
+        let mut kept = Vec::new();
+        let mut removed = Vec::new();
+        for n in 1..8 {
+            kept.push(commit(&ctx, &main, vec![insert(n, 1)]));
+            let branch = branch(&ctx, &main, n);
+            removed.push(commit(&ctx, &branch, vec![set(n, 1, 2)]));
+            removed.push(commit(&ctx, &branch, vec![set(n, 2, 3)]));
+            live(&ctx, &branch, vec![set(n, 3, 4)]);
+            if n % 3 != 0 {
+                delete_branch(&ctx, &branch);
+            } else {
+                kept.push(removed.pop().unwrap());
+                kept.push(removed.pop().unwrap());
+            }
+        }
+
+        let report = ctx.gc(main.repository, 2).unwrap();
+        assert_eq!(report.commits, removed.len());
+        assert_eq!(report.branches, 5);
+        assert!(kept.iter().all(|commit| exists(&ctx, commit)));
+        assert!(removed.iter().all(|commit| !exists(&ctx, commit)));
+        for n in 1..8 {
+            let branch = BranchIdentifier {
+                repository: main.repository,
+                id: BranchId(id(n)),
+            };
+            let changes = ctx.db.get(keys::LiveChanges(&branch)).unwrap();
+            assert_eq!(changes.is_some(), n % 3 == 0);
+        }
+    }
+
+    #[test]
+    fn promote_delta() {
+        let ctx = TempContext::new();
+        let main = init(&ctx);
+        let branch = branch(&ctx, &main, 1);
+        let base = commit(&ctx, &branch, vec![insert(2, 2)]);
+        let head = commit(&ctx, &main, vec![insert(1, 1)]);
+
+        // Store the head of `main` as a delta on the commit of the other branch.
+        let delta = ctx
+            .checkout(&base)
+            .unwrap()
+            .diff(&ctx.checkout(&head).unwrap());
+        let mut batch = ctx.db.batch();
+        batch.put(
+            keys::CommitSnapshot(&head),
+            &SnapshotEntry::Delta { base, delta },
+        );
+        batch.write().unwrap();
+        let before = ctx.checkout(&head).unwrap();
+        delete_branch(&ctx, &branch);
+
+        let report = ctx.gc(main.repository, 64).unwrap();
+        assert_eq!(report.commits, 1);
+        assert_eq!(report.rerooted, 1);
+        assert!(!exists(&ctx, &base));
+        assert!(matches!(
+            ctx.db.get(keys::CommitSnapshot(&head)).unwrap(),
+            Some(SnapshotEntry::Snapshot(_))
+        ));
+        assert!(before.diff(&ctx.checkout(&head).unwrap()).is_empty());
+    }
+
+    #[test]
+    fn forks() {
+        let ctx = TempContext::new();
+        let main = init(&ctx);
+        let shared = commit(&ctx, &main, vec![insert(1, 1)]);
+        let repository = ctx.fork(main.repository, USER).unwrap();
+        let forked = BranchIdentifier {
+            repository,
+            id: main.id,
+        };
+        let ours = commit(&ctx, &forked, vec![set(1, 1, 2)]);
+        let branch = branch(&ctx, &main, 1);
+        let removed = commit(&ctx, &branch, vec![insert(2, 2)]);
+        delete_branch(&ctx, &branch);
+        delete_branch(&ctx, &main);
+
+        // The fork still uses the history of the origin.
+        let report = ctx.gc(main.repository, 1).unwrap();
+        assert_eq!(report.commits, 1);
+        assert!(exists(&ctx, &shared));
+        assert!(!exists(&ctx, &removed));
+        assert_eq!(ctx.gc(repository, 1).unwrap().commits, 0);
+        assert!(exists(&ctx, &ours));
+        let state = ctx.state(&Revision::Branch(forked)).unwrap();
+        assert_eq!(state.get(&id(1)).unwrap().get(1), &PrimitiveValue::U32(2));
+    }
+}
@@ -0,0 +1,477 @@
+use super::Context;
+use crate::db::keys;
+use crate::error::*;
+use crate::types::*;
+use crate::utils::lca::lca;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+/// A commit on the first-parent chain that changed an object.
+#[derive(Debug)]
+pub struct ObjectChange {
+    pub commit: CommitIdentifier,
+    pub info: CommitInfo,
+    /// The change that was made to the object in the commit.
+    pub change: DeltaEntry,
+}
+
+/// An iterator over the commits that are reachable from a commit, the newest
+/// commits are returned first.
+pub struct CommitWalk<'c, 'a, R> {
+    context: &'c Context<'a, R>,
+    /// Only follow the first parent of each commit.
+    first_parent: bool,
+    queue: BinaryHeap<(Timestamp, CommitIdentifier)>,
+    pending: HashMap<CommitIdentifier, CommitInfo>,
+    seen: HashSet<CommitIdentifier>,
+}
+
+/// A read-only view of a branch as it was at a past instant.
+pub struct BranchView<'c> {
+    /// The last commit of the branch at that time.
+    pub commit: CommitIdentifier,
+    /// Number of the live changes that were replayed on the commit.
+    pub live_changes: usize,
+    pub state: State,
+    schema: &'c Schema,
+}
+
+impl<'a, R> Context<'a, R> {
+    /// Returns a page of the log of a repository, the newest events come first.
+    /// The log is a single value in the database, so the whole log is read to
+    /// return a page.
+    pub fn log(
+        &self,
+        repository: &RepositoryId,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<LogEvent>> {
+        let events = self
+            .db
+            .get(keys::Log(repository))?
+            .ok_or(Error::RepositoryNotFound)?;
+        Ok(events.into_iter().rev().skip(offset).take(limit).collect())
+    }
+
+    /// Returns the information of a commit.
+    #[inline]
+    pub fn commit(&self, commit: &CommitIdentifier) -> Result<CommitInfo> {
+        self.db
+            .get(keys::Commit(commit))?
+            .ok_or(Error::CommitNotFound)
+    }
+
+    /// Walk the commit graph from the head of a branch.
+    pub fn commits(
+        &self,
+        branch: &BranchIdentifier,
+        first_parent: bool,
+    ) -> Result<CommitWalk<'_, 'a, R>> {
+        let info = self
+            .db
+            .get(keys::Branch(branch))?
+            .ok_or(Error::BranchNotFound)?;
+        self.walk(&info.head, first_parent)
+    }
+
+    /// Walk the commit graph from the given commit.
+    pub fn walk(
+        &self,
+        commit: &CommitIdentifier,
+        first_parent: bool,
+    ) -> Result<CommitWalk<'_, 'a, R>> {
+        let mut walk = CommitWalk {
+            context: self,
+            first_parent,
+            queue: BinaryHeap::new(),
+            pending: HashMap::new(),
+            seen: HashSet::new(),
+        };
+        walk.push(*commit)?;
+        Ok(walk)
+    }
+
+    /// Find the lowest common ancestor of two commits, they may belong to two
+    /// different repositories when one is a fork of the other.
+    pub fn merge_base(
+        &self,
+        a: &CommitIdentifier,
+        b: &CommitIdentifier,
+    ) -> Result<CommitIdentifier> {
+        // Load the commits that are visited by the LCA, the fork points.
+        let mut commits = HashMap::new();
+        let mut queue = vec![*a, *b];
+        while let Some(commit) = queue.pop() {
+            if commits.contains_key(&commit) {
+                continue;
+            }
+            let origin = self
+                .db
+                .get(keys::CommitOrigin(&commit))?
+                .ok_or(Error::CommitNotFound)?;
+            if let Some((_, fork)) = origin.fork_point {
+                queue.push(fork);
+            }
+            commits.insert(commit, origin);
+        }
+
+        lca(
+            &mut |commit| commits.get(commit).ok_or(Error::CommitNotFound),
+            vec![*a, *b],
+        )
+    }
+
+    /// Returns the delta that turns the state of the commit `from` to the state
+    /// of the commit `to`.
+    pub fn diff(&self, from: &CommitIdentifier, to: &CommitIdentifier) -> Result<Delta> {
+        if from == to {
+            return Ok(Delta::new());
+        }
+        match self
+            .db
+            .get(keys::CommitSnapshot(to))?
+            .ok_or(Error::CommitNotFound)?
+        {
+            SnapshotEntry::Delta { base, delta } if &base == from => Ok(delta),
+            SnapshotEntry::Snapshot(state) => Ok(self.checkout(from)?.diff(&state)),
+            SnapshotEntry::Delta { .. } => Ok(self.checkout(from)?.diff(&self.checkout(to)?)),
+        }
+    }
+
+    /// Returns the state at a revision, the state of a branch is its head along
+    /// with the packed delta and the live changes.
+    pub fn state(&self, revision: &Revision) -> Result<State> {
+        let branch = match revision {
+            Revision::Commit(commit) => return self.checkout(commit),
+            Revision::Branch(branch) => branch,
+        };
+        let info = self
+            .db
+            .get(keys::Branch(branch))?
+            .ok_or(Error::BranchNotFound)?;
+        let mut state = self.checkout(&info.head)?;
+        if let Some(delta) = self.db.get(keys::PackedDelta(branch))? {
+            state.apply_delta_trusted(delta);
+        }
+        for patch in self.db.get(keys::LiveChanges(branch))?.unwrap_or_default() {
+            state
+                .perform_with_schema(patch.actions, &self.schema)
+                .map_err(|_| Error::CheckoutFailed)?;
+        }
+        Ok(state)
+    }
+
+    /// Open a view of a branch as it was at the given time, it starts from the
+    /// last commit before that time and replays the live changes that were made
+    /// until then.  
+    /// Once committed, the live changes are only kept as the commit delta, so
+    /// they are only replayed when the commit is still the head of the branch.
+    /// The packed delta is applied only if a live change that was made after it
+    /// is not newer than `time`, since the time of its changes is lost.
+    pub fn checkout_at(
+        &self,
+        branch: &BranchIdentifier,
+        time: Timestamp,
+    ) -> Result<BranchView<'_>> {
+        let info = self
+            .db
+            .get(keys::Branch(branch))?
+            .ok_or(Error::BranchNotFound)?;
+        if time < info.created_at {
+            return Err(Error::BeforeBranchCreated);
+        }
+
+        let mut commit = None;
+        for item in self.walk(&info.head, true)? {
+            let (id, commit_info) = item?;
+            if commit_info.time <= time {
+                commit = Some(id);
+                break;
+            }
+        }
+        let commit = commit.ok_or(Error::CommitNotFound)?;
+        let mut state = self.checkout(&commit)?;
+        let mut live_changes = 0;
+
+        if commit == info.head {
+            let patches = self.db.get(keys::LiveChanges(branch))?.unwrap_or_default();
+            let patches: Vec<Patch> = patches.into_iter().take_while(|p| p.time <= time).collect();
+            if !patches.is_empty() {
+                if let Some(delta) = self.db.get(keys::PackedDelta(branch))? {
+                    state.apply_delta_trusted(delta);
+                }
+            }
+            for patch in patches {
+                state
+                    .perform_with_schema(patch.actions, &self.schema)
+                    .map_err(|_| Error::CheckoutFailed)?;
+                live_changes += 1;
+            }
+        }
+
+        Ok(BranchView {
+            commit,
+            live_changes,
+            state,
+            schema: &self.schema,
+        })
+    }
+
+    /// Compare the states at two revisions, use `StateDiff::to_json` with the
+    /// schema of the context to render it.
+    pub fn compare(&self, from: &Revision, to: &Revision) -> Result<StateDiff> {
+        Ok(StateDiff::new(&self.state(from)?, &self.state(to)?))
+    }
+
+    /// Returns the commits on the first-parent chain of `head` that changed the
+    /// object, or one of its fields, the newest changes come first.
+    pub fn history(
+        &self,
+        head: &CommitIdentifier,
+        oid: &ObjectId,
+        field: Option<FieldIndex>,
+        limit: usize,
+    ) -> Result<Vec<ObjectChange>> {
+        let mut result = Vec::new();
+        if limit == 0 {
+            return Ok(result);
+        }
+
+        for item in self.walk(head, true)? {
+            let (commit, info) = item?;
+            let mut delta = match info.parents.first() {
+                Some(parent) => self.diff(parent, &commit)?,
+                None => State::default().diff(&self.checkout(&commit)?),
+            };
+            match delta.remove(oid) {
+                Some(change) if change.touches(field) => {
+                    result.push(ObjectChange {
+                        commit,
+                        info,
+                        change,
+                    });
+                    if result.len() == limit {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Returns the last commit on the first-parent chain of `head` that changed
+    /// the object, or one of its fields.
+    #[inline]
+    pub fn blame(
+        &self,
+        head: &CommitIdentifier,
+        oid: &ObjectId,
+        field: Option<FieldIndex>,
+    ) -> Result<Option<ObjectChange>> {
+        Ok(self.history(head, oid, field, 1)?.pop())
+    }
+}
+
+impl<'c, 'a, R> CommitWalk<'c, 'a, R> {
+    fn push(&mut self, commit: CommitIdentifier) -> Result<()> {
+        if self.seen.insert(commit) {
+            let info = self.context.commit(&commit)?;
+            self.queue.push((info.time, commit));
+            self.pending.insert(commit, info);
+        }
+        Ok(())
+    }
+}
+
+impl<'c, 'a, R> Iterator for CommitWalk<'c, 'a, R> {
+    type Item = Result<(CommitIdentifier, CommitInfo)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (_, commit) = self.queue.pop()?;
+        let info = self.pending.remove(&commit).unwrap();
+        let parents = if self.first_parent {
+            &info.parents[..info.parents.len().min(1)]
+        } else {
+            &info.parents[..]
+        };
+        for parent in parents {
+            if let Err(e) = self.push(*parent) {
+                return Some(Err(e));
+            }
+        }
+        Some(Ok((commit, info)))
+    }
+}
+
+impl<'c> BranchView<'c> {
+    /// Query the state of the view with the schema of the context.
+    #[inline]
+    pub fn query(&self) -> Query<'_> {
+        Query::new(&self.state, self.schema)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test_utils::*;
+    use super::*;
+    use crate::utils::clock::now;
+    use crate::utils::hash::Hash16;
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    /// Wait so the next change has a later time.
+    fn tick() -> Timestamp {
+        sleep(Duration::from_millis(2));
+        let time = now();
+        sleep(Duration::from_millis(2));
+        time
+    }
+
+    #[test]
+    fn checkout_at() {
+        let ctx = TempContext::new();
+        let main = init(&ctx);
+        let created_at = ctx.db.get(keys::Branch(&main)).unwrap().unwrap().created_at;
+        tick();
+        let first = commit(&ctx, &main, vec![insert(1, 1)]);
+        let between_commits = tick();
+        let second = commit(&ctx, &main, vec![set(1, 1, 2)]);
+        tick();
+        live(&ctx, &main, vec![set(1, 2, 3)]);
+        let between_patches = tick();
+        live(&ctx, &main, vec![insert(2, 5)]);
+
+        let view = ctx.checkout_at(&main, between_commits).unwrap();
+        assert_eq!(view.commit, first);
+        assert_eq!(view.live_changes, 0);
+        assert_eq!(
+            view.state.get(&id(1)).unwrap().get(1),
+            &PrimitiveValue::U32(1)
+        );
+
+        let view = ctx.checkout_at(&main, between_patches).unwrap();
+        assert_eq!(view.commit, second);
+        assert_eq!(view.live_changes, 1);
+        assert_eq!(
+            view.state.get(&id(1)).unwrap().get(1),
+            &PrimitiveValue::U32(3)
+        );
+        assert!(view.state.get(&id(2)).is_none());
+
+        assert_eq!(ctx.checkout_at(&main, now()).unwrap().live_changes, 2);
+        assert!(matches!(
+            ctx.checkout_at(&main, created_at - 1),
+            Err(Error::BeforeBranchCreated)
+        ));
+    }
+
+    const ALICE: UserId = UserId(Hash16::MAX);
+
+    fn bob() -> UserId {
+        UserId(id(2))
+    }
+
+    /// Build a graph where a branch that changed the object 2 is merged back to
+    /// the main branch, that changed the object 1 and deleted the object 3:
+    /// `root - c1 - c2 - merge` and `c1 - c3 - merge`.
+    fn graph(ctx: &TempContext) -> [CommitIdentifier; 5] {
+        let main = init(ctx);
+        let root = ctx.db.get(keys::Branch(&main)).unwrap().unwrap().head;
+        tick();
+        let c1 = commit(ctx, &main, vec![insert(1, 1), insert(2, 1), insert(3, 1)]);
+        let feature = branch(ctx, &main, 1);
+        tick();
+        let delete = PatchAtom::Delete {
+            oid: id(3),
+            version: 0,
+        };
+        let c2 = commit_as(ctx, &main, ALICE, vec![set(1, 1, 2), delete]);
+        tick();
+        let c3 = commit_as(ctx, &feature, bob(), vec![set(2, 1, 2)]);
+        tick();
+        let merge = merge(ctx, &main, &c3);
+        [root, c1, c2, c3, merge]
+    }
+
+    fn walk(
+        ctx: &TempContext,
+        head: &CommitIdentifier,
+        first_parent: bool,
+    ) -> Vec<CommitIdentifier> {
+        ctx.walk(head, first_parent)
+            .unwrap()
+            .map(|item| item.unwrap().0)
+            .collect()
+    }
+
+    #[test]
+    fn walk_merge() {
+        let ctx = TempContext::new();
+        let [root, c1, c2, c3, merge] = graph(&ctx);
+        assert_eq!(walk(&ctx, &merge, false), vec![merge, c3, c2, c1, root]);
+        assert_eq!(walk(&ctx, &merge, true), vec![merge, c2, c1, root]);
+        assert_eq!(walk(&ctx, &c3, false), vec![c3, c1, root]);
+    }
+
+    #[test]
+    fn diff_merge() {
+        let ctx = TempContext::new();
+        let [root, c1, c2, c3, merge] = graph(&ctx);
+        assert!(ctx.diff(&merge, &merge).unwrap().is_empty());
+
+        // The delta of the merge is stored from its first parent.
+        let delta = ctx.diff(&c2, &merge).unwrap();
+        assert_eq!(delta.keys().collect::<Vec<_>>(), vec![&id(2)]);
+        let delta = ctx.diff(&c3, &merge).unwrap();
+        assert_eq!(delta.len(), 2);
+        assert!(matches!(delta[&id(3)], DeltaEntry::Deleted));
+        assert!(delta[&id(1)].touches(Some(1)));
+
+        let mut state = ctx.checkout(&root).unwrap();
+        state.apply_delta_trusted(ctx.diff(&root, &merge).unwrap());
+        assert!(state.diff(&ctx.checkout(&merge).unwrap()).is_empty());
+        assert_eq!(ctx.diff(&root, &c1).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn blame_merge() {
+        let ctx = TempContext::new();
+        let [_, c1, c2, c3, merge] = graph(&ctx);
+
+        let change = ctx.blame(&merge, &id(1), Some(1)).unwrap().unwrap();
+        assert_eq!(change.commit, c2);
+        assert_eq!(change.info.authors, vec![ALICE]);
+        assert_eq!(
+            ctx.blame(&merge, &id(1), Some(0)).unwrap().unwrap().commit,
+            c1
+        );
+
+        // The history follows the first parents, a change of the merged branch
+        // is found on the merge commit.
+        let change = ctx.blame(&merge, &id(2), Some(1)).unwrap().unwrap();
+        assert_eq!(change.commit, merge);
+        assert_eq!(change.info.authors, vec![USER]);
+        let change = ctx.blame(&c3, &id(2), Some(1)).unwrap().unwrap();
+        assert_eq!(change.commit, c3);
+        assert_eq!(change.info.authors, vec![bob()]);
+        assert!(ctx.blame(&merge, &id(4), None).unwrap().is_none());
+    }
+
+    #[test]
+    fn history_deleted() {
+        let ctx = TempContext::new();
+        let [_, c1, c2, _, merge] = graph(&ctx);
+
+        let history = ctx.history(&merge, &id(3), None, 10).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].commit, c2);
+        assert!(matches!(history[0].change, DeltaEntry::Deleted));
+        assert_eq!(history[1].commit, c1);
+        assert!(matches!(history[1].change, DeltaEntry::Inserted { .. }));
+
+        assert_eq!(ctx.history(&merge, &id(3), Some(1), 1).unwrap().len(), 1);
+        assert!(ctx.history(&merge, &id(3), None, 0).unwrap().is_empty());
+    }
+}
@@ -1,14 +1,25 @@
 //! The public API for ROSS.
 
+mod bundle;
+mod commit;
 mod context;
 pub use context::*;
+mod document;
 mod editor;
 pub use editor::*;
+mod gc;
+pub use gc::*;
+mod history;
+pub use history::*;
 mod lock;
 pub use lock::*;
 mod message;
 pub use message::*;
 mod recipient;
 pub use recipient::*;
+mod repository;
 mod session;
 pub use session::*;
+mod tag;
+#[cfg(test)]
+mod test_utils;
@@ -0,0 +1,185 @@
+use super::Context;
+use crate::db::keys::{self, DbReadKey};
+use crate::error::*;
+use crate::types::*;
+use crate::utils::clock::now;
+use crate::utils::hash::Hash16;
+
+impl<'a, R> Context<'a, R> {
+    /// Fork a repository, the branches of the new repository point to the same
+    /// commits as the ones in the origin, so the history is shared and not
+    /// copied. The live changes are not forked.  
+    /// Each branch is forked from its origin branch, so it can be rebased on it
+    /// and merged back to it.
+    pub fn fork(&self, origin: RepositoryId, user: UserId) -> Result<RepositoryId> {
+        if !self.repository_exists(&origin)? {
+            return Err(Error::RepositoryNotFound);
+        }
+
+        let repository = RepositoryId(rand::random::<Hash16>());
+        let time = now();
+        let mut batch = self.db.batch();
+        batch.put(
+            keys::Repository(&repository),
+            &RepositoryInfo {
+                owner: user,
+                fork_of: Some(origin),
+                created_at: time,
+            },
+        );
+        batch.push(keys::Log(&repository), &LogEvent::Init { user, time });
+
+        let branches = keys::Branch::key_value_iterator(&self.db, &origin)
+            .take_while(|(id, _)| id.repository == origin);
+        for (id, info) in branches {
+            let branch = BranchIdentifier {
+                repository,
+                id: id.id,
+            };
+            let info = BranchInfo {
+                head: info.head,
+                fork_point: Some((id, info.head)),
+                created_at: time,
+                user,
+                mode: info.mode,
+                title: info.title,
+            };
+            batch.put(keys::Branch(&branch), &info);
+            batch.push(
+                keys::Log(&repository),
+                &LogEvent::BranchCreated {
+                    id: branch.id,
+                    head: info.head.hash,
+                    user,
+                    time,
+                },
+            );
+        }
+
+        batch.write()?;
+        Ok(repository)
+    }
+
+    /// Delete a repository and purge all of its data, the repository is marked
+    /// as deleted first so it can not be opened, so if the purge fails midway
+    /// it can be deleted again.  
+    /// It fails when there are open sessions on the repository, or when it has
+    /// forks since they share its history.
+    pub fn delete_repository(&self, repository: RepositoryId) -> Result<()> {
+        if self.db.get(keys::RepositoryExists(&repository))?.is_none() {
+            return Err(Error::RepositoryNotFound);
+        }
+        for (id, info) in keys::Repository::key_value_iterator(&self.db, &()) {
+            if info.fork_of == Some(repository) && self.repository_exists(&id)? {
+                return Err(Error::RepositoryHasForks);
+            }
+        }
+
+        self.without_sessions(repository, || {
+            let mut batch = self.db.batch();
+            batch.put(keys::RepositoryDeleted(&repository), &());
+            batch.write()
+        })?;
+        self.purge(repository)
+    }
+
+    /// Returns true if the repository exists and it is not being deleted.
+    pub(super) fn repository_exists(&self, repository: &RepositoryId) -> Result<bool> {
+        Ok(self.db.get(keys::RepositoryExists(repository))?.is_some()
+            && self.db.get(keys::RepositoryDeleted(repository))?.is_none())
+    }
+
+    /// Remove every key of a repository, the repository info and its tombstone
+    /// are removed last.
+    pub(super) fn purge(&self, repository: RepositoryId) -> Result<()> {
+        let mut batch = self.db.batch();
+        batch.delete_prefix::<keys::Branch, _>(&repository);
+        batch.delete_prefix::<keys::Commit, _>(&repository);
+        batch.delete_prefix::<keys::CommitSnapshot, _>(&repository);
+        batch.delete_prefix::<keys::Tag, _>(&repository);
+        batch.delete_prefix::<keys::Log, _>(&repository);
+        batch.delete_prefix::<keys::LiveChanges, _>(&repository);
+        batch.delete_prefix::<keys::PackedDelta, _>(&repository);
+        batch.write()?;
+
+        let mut batch = self.db.batch();
+        batch.delete(keys::Repository(&repository));
+        batch.delete(keys::RepositoryDeleted(&repository));
+        batch.write()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test_utils::*;
+    use super::*;
+
+    #[test]
+    fn fork() {
+        let ctx = TempContext::new();
+        let main = init(&ctx);
+        let shared = commit(&ctx, &main, vec![insert(1, 1)]);
+        assert!(matches!(
+            ctx.fork(RepositoryId(id(9)), USER),
+            Err(Error::RepositoryNotFound)
+        ));
+
+        let repository = ctx.fork(main.repository, USER).unwrap();
+        let forked = BranchIdentifier {
+            repository,
+            id: main.id,
+        };
+        let info = ctx.db.get(keys::Branch(&forked)).unwrap().unwrap();
+        assert_eq!(info.head, shared);
+        assert_eq!(info.fork_point, Some((main, shared)));
+        // The head is a commit of the origin repository.
+        let state = ctx.state(&Revision::Branch(forked)).unwrap();
+        assert_eq!(state.get(&id(1)).unwrap().get(1), &PrimitiveValue::U32(1));
+
+        let ours = commit(&ctx, &forked, vec![set(1, 1, 2)]);
+        let theirs = commit(&ctx, &main, vec![insert(2, 2)]);
+        assert_eq!(ours.repository, repository);
+        assert_eq!(ctx.merge_base(&ours, &theirs).unwrap(), shared);
+        assert_eq!(ctx.merge_base(&theirs, &ours).unwrap(), shared);
+        assert_eq!(ctx.merge_base(&shared, &ours).unwrap(), shared);
+    }
+
+    #[test]
+    fn delete() {
+        let ctx = TempContext::new();
+        let main = init(&ctx);
+        let head = commit(&ctx, &main, vec![insert(1, 1)]);
+        let fork = ctx.fork(main.repository, USER).unwrap();
+        assert!(matches!(
+            ctx.delete_repository(main.repository),
+            Err(Error::RepositoryHasForks)
+        ));
+
+        // A tombstone can not be forked, and it does not keep its origin.
+        let mut batch = ctx.db.batch();
+        batch.put(keys::RepositoryDeleted(&fork), &());
+        batch.write().unwrap();
+        assert!(matches!(
+            ctx.fork(fork, USER),
+            Err(Error::RepositoryNotFound)
+        ));
+        ctx.delete_repository(main.repository).unwrap();
+        ctx.delete_repository(fork).unwrap();
+
+        for repository in &[main.repository, fork] {
+            assert!(ctx.db.get(keys::Repository(repository)).unwrap().is_none());
+            assert!(ctx
+                .db
+                .get(keys::RepositoryDeleted(repository))
+                .unwrap()
+                .is_none());
+            assert!(ctx.db.get(keys::Log(repository)).unwrap().is_none());
+        }
+        assert!(ctx.db.get(keys::Branch(&main)).unwrap().is_none());
+        assert!(ctx.db.get(keys::Commit(&head)).unwrap().is_none());
+        assert!(matches!(
+            ctx.delete_repository(main.repository),
+            Err(Error::RepositoryNotFound)
+        ));
+    }
+}
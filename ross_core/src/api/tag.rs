@@ -0,0 +1,172 @@
+use super::Context;
+use crate::db::keys::{self, DbReadKey};
+use crate::error::*;
+use crate::types::*;
+use crate::utils::clock::now;
+
+impl<'a, R> Context<'a, R> {
+    /// Create a tag on a commit, the tag is annotated when a message is given.
+    pub fn create_tag(
+        &self,
+        name: String,
+        commit: &CommitIdentifier,
+        user: UserId,
+        message: Option<String>,
+    ) -> Result<()> {
+        let id = TagIdentifier {
+            repository: commit.repository,
+            name,
+        };
+        if self.db.get(keys::Tag(&id))?.is_some() {
+            return Err(Error::TagExists);
+        }
+        if self.db.get(keys::CommitOrigin(commit))?.is_none() {
+            return Err(Error::CommitNotFound);
+        }
+
+        let time = now();
+        let info = TagInfo {
+            commit: *commit,
+            annotation: message.map(|message| TagAnnotation {
+                tagger: user,
+                message,
+                time,
+            }),
+        };
+        let mut batch = self.db.batch();
+        batch.put(keys::Tag(&id), &info);
+        batch.push(
+            keys::Log(&commit.repository),
+            &LogEvent::TagCreated {
+                name: id.name.clone(),
+                commit: commit.hash,
+                user,
+                time,
+            },
+        );
+        batch.write()
+    }
+
+    /// Returns the information of a tag.
+    #[inline]
+    pub fn tag(&self, repository: RepositoryId, name: &str) -> Result<TagInfo> {
+        let id = TagIdentifier {
+            repository,
+            name: name.into(),
+        };
+        self.db.get(keys::Tag(&id))?.ok_or(Error::TagNotFound)
+    }
+
+    /// List the tags of a repository, sorted by their name.
+    pub fn tags(&self, repository: RepositoryId) -> Vec<(String, TagInfo)> {
+        let mut tags: Vec<(String, TagInfo)> = keys::Tag::key_value_iterator(&self.db, &repository)
+            .take_while(|(id, _)| id.repository == repository)
+            .map(|(id, info)| (id.name, info))
+            .collect();
+        // The keys are ordered by the length of the name first.
+        tags.sort_by(|a, b| a.0.cmp(&b.0));
+        tags
+    }
+
+    /// Delete a tag, the commit that it points to is not affected.
+    pub fn delete_tag(&self, repository: RepositoryId, name: &str, user: UserId) -> Result<()> {
+        let id = TagIdentifier {
+            repository,
+            name: name.into(),
+        };
+        if self.db.get(keys::Tag(&id))?.is_none() {
+            return Err(Error::TagNotFound);
+        }
+
+        let mut batch = self.db.batch();
+        batch.delete(keys::Tag(&id));
+        batch.push(
+            keys::Log(&repository),
+            &LogEvent::TagDeleted {
+                name: id.name,
+                user,
+                time: now(),
+            },
+        );
+        batch.write()
+    }
+
+    /// Returns the snapshot of the commit that a tag points to.
+    #[inline]
+    pub fn checkout_tag(&self, repository: RepositoryId, name: &str) -> Result<State> {
+        self.checkout(&self.tag(repository, name)?.commit)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test_utils::*;
+    use super::*;
+
+    #[test]
+    fn tags() {
+        let ctx = TempContext::new();
+        let main = init(&ctx);
+        let repository = main.repository;
+        let first = ctx.db.get(keys::Branch(&main)).unwrap().unwrap().head;
+        let second = commit(&ctx, &main, vec![insert(1, 1)]);
+
+        ctx.create_tag("v2".into(), &first, USER, None).unwrap();
+        ctx.create_tag("v10".into(), &second, USER, Some("Release".into()))
+            .unwrap();
+        ctx.create_tag("alpha".into(), &first, USER, None).unwrap();
+        assert!(matches!(
+            ctx.create_tag("v2".into(), &second, USER, None),
+            Err(Error::TagExists)
+        ));
+
+        let lightweight = ctx.tag(repository, "v2").unwrap();
+        assert_eq!(lightweight.commit, first);
+        assert!(lightweight.annotation.is_none());
+        let annotated = ctx.tag(repository, "v10").unwrap();
+        assert_eq!(annotated.commit, second);
+        let annotation = annotated.annotation.unwrap();
+        assert_eq!(annotation.tagger, USER);
+        assert_eq!(annotation.message, "Release");
+        assert!(ctx
+            .checkout_tag(repository, "v10")
+            .unwrap()
+            .get(&id(1))
+            .is_some());
+
+        let names: Vec<String> = ctx
+            .tags(repository)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(names, vec!["alpha", "v10", "v2"]);
+        assert!(ctx.tags(RepositoryId(id(9))).is_empty());
+
+        ctx.delete_tag(repository, "v2", USER).unwrap();
+        assert!(matches!(ctx.tag(repository, "v2"), Err(Error::TagNotFound)));
+        assert!(matches!(
+            ctx.delete_tag(repository, "v2", USER),
+            Err(Error::TagNotFound)
+        ));
+        assert_eq!(ctx.tags(repository).len(), 2);
+    }
+
+    #[test]
+    fn log() {
+        let ctx = TempContext::new();
+        let main = init(&ctx);
+        let head = ctx.db.get(keys::Branch(&main)).unwrap().unwrap().head;
+        ctx.create_tag("v1".into(), &head, USER, None).unwrap();
+        ctx.delete_tag(main.repository, "v1", USER).unwrap();
+
+        let events = ctx.log(&main.repository, 0, 2).unwrap();
+        assert!(matches!(
+            &events[0],
+            LogEvent::TagDeleted { name, user, .. } if name == "v1" && *user == USER
+        ));
+        assert!(matches!(
+            &events[1],
+            LogEvent::TagCreated { name, commit, .. } if name == "v1" && *commit == head.hash
+        ));
+    }
+}
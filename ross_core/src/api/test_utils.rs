@@ -0,0 +1,245 @@
+//! Helpers to build a repository with some history in the tests of the API.
+use super::Context;
+use crate::db::keys;
+use crate::types::*;
+use crate::utils::clock::now;
+use crate::utils::hash::Hash16;
+use std::ops::Deref;
+use std::path::PathBuf;
+
+pub(super) const USER: UserId = UserId(Hash16::MIN);
+
+/// A temporary directory that is removed when it is dropped.
+pub(super) struct TempDir(pub PathBuf);
+
+impl TempDir {
+    pub fn new() -> Self {
+        TempDir(std::env::temp_dir().join(format!("ross-{}", rand::random::<u64>())))
+    }
+
+    pub fn path(&self) -> &str {
+        self.0.to_str().unwrap()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A context on a temporary database, the editors can not be opened on it since
+/// they borrow the context, use a `TempDir` instead.
+pub(super) struct TempContext {
+    context: Context<'static, ()>,
+    _dir: TempDir,
+}
+
+impl TempContext {
+    pub fn new() -> Self {
+        Self::with_schema(Schema::default())
+    }
+
+    pub fn with_schema(schema: Schema) -> Self {
+        let dir = TempDir::new();
+        TempContext {
+            context: Context::with_schema(dir.path(), schema),
+            _dir: dir,
+        }
+    }
+}
+
+impl Deref for TempContext {
+    type Target = Context<'static, ()>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.context
+    }
+}
+
+pub(super) fn id(n: u8) -> ObjectId {
+    let mut bytes = [0; 16];
+    bytes[15] = n;
+    Hash16::from(bytes)
+}
+
+/// Insert the object `id(n)` with a single field.
+pub(super) fn insert(n: u8, value: u32) -> PatchAtom {
+    PatchAtom::Insert {
+        oid: id(n),
+        data: vec![PrimitiveValue::U32(0), PrimitiveValue::U32(value)],
+        version: None,
+    }
+}
+
+/// Change the field of an object that was inserted by `insert`.
+pub(super) fn set(n: u8, current: u32, target: u32) -> PatchAtom {
+    PatchAtom::CAS {
+        oid: id(n),
+        field: 1,
+        current: PrimitiveValue::U32(current),
+        target: PrimitiveValue::U32(target),
+    }
+}
+
+/// Create a repository whose `main` branch only has an empty root commit.
+pub(super) fn init(ctx: &Context<()>) -> BranchIdentifier {
+    let repository = RepositoryId(rand::random());
+    let branch = BranchIdentifier {
+        repository,
+        id: BranchId(Hash16::MIN),
+    };
+    let time = now();
+    let root = CommitInfo {
+        origin: CommitInfoOrigin {
+            branch,
+            fork_point: None,
+            order: 0,
+        },
+        time,
+        parents: vec![],
+        committer: USER,
+        authors: vec![USER],
+        message: "Init".into(),
+    };
+    let head = CommitIdentifier {
+        repository,
+        hash: root.hash(),
+    };
+
+    let mut batch = ctx.db.batch();
+    batch.put(
+        keys::Repository(&repository),
+        &RepositoryInfo {
+            owner: USER,
+            fork_of: None,
+            created_at: time,
+        },
+    );
+    batch.put(keys::Commit(&head), &root);
+    batch.put(
+        keys::CommitSnapshot(&head),
+        &SnapshotEntry::Snapshot(State::default()),
+    );
+    batch.put(
+        keys::Branch(&branch),
+        &BranchInfo {
+            head,
+            fork_point: None,
+            created_at: time,
+            user: USER,
+            mode: BranchMode::Normal,
+            title: "main".into(),
+        },
+    );
+    batch.push(keys::Log(&repository), &LogEvent::Init { user: USER, time });
+    batch.write().unwrap();
+    branch
+}
+/// Create a branch on the current head of another branch.
+pub(super) fn branch(ctx: &Context<()>, parent: &BranchIdentifier, n: u8) -> BranchIdentifier {
+    let head = ctx.db.get(keys::Branch(parent)).unwrap().unwrap().head;
+    let branch = BranchIdentifier {
+        repository: parent.repository,
+        id: BranchId(id(n)),
+    };
+    let info = BranchInfo {
+        head,
+        fork_point: Some((*parent, head)),
+        created_at: now(),
+        user: USER,
+        mode: BranchMode::Normal,
+        title: format!("branch-{}", n),
+    };
+    let mut batch = ctx.db.batch();
+    batch.put(keys::Branch(&branch), &info);
+    batch.write().unwrap();
+    branch
+}
+
+/// Commit the changes on top of the head of the branch.
+pub(super) fn commit(
+    ctx: &Context<()>,
+    branch: &BranchIdentifier,
+    atoms: Vec<PatchAtom>,
+) -> CommitIdentifier {
+    commit_as(ctx, branch, USER, atoms)
+}
+
+/// Commit the changes on top of the head of the branch as another user.
+pub(super) fn commit_as(
+    ctx: &Context<()>,
+    branch: &BranchIdentifier,
+    user: UserId,
+    atoms: Vec<PatchAtom>,
+) -> CommitIdentifier {
+    let info = ctx.db.get(keys::Branch(branch)).unwrap().unwrap();
+    let head = ctx.checkout(&info.head).unwrap();
+    let mut state = ctx.checkout(&info.head).unwrap();
+    state.perform(atoms).unwrap();
+    ctx.commit_delta(branch, info, head.diff(&state), user, "Commit".into())
+        .unwrap()
+}
+
+/// Merge a commit into the branch with a commit that has two parents, the
+/// head of the branch is the first parent.
+pub(super) fn merge(
+    ctx: &Context<()>,
+    branch: &BranchIdentifier,
+    other: &CommitIdentifier,
+) -> CommitIdentifier {
+    let mut info = ctx.db.get(keys::Branch(branch)).unwrap().unwrap();
+    let base = ctx.merge_base(&info.head, other).unwrap();
+    let delta = ctx
+        .checkout(&info.head)
+        .unwrap()
+        .merge_delta(
+            &ctx.checkout(&base).unwrap(),
+            &ctx.diff(&base, other).unwrap(),
+            ctx.schema(),
+        )
+        .unwrap();
+    let order = ctx.commit(&info.head).unwrap().origin.order;
+    let other_order = ctx.commit(other).unwrap().origin.order;
+    let commit_info = CommitInfo {
+        origin: CommitInfoOrigin {
+            branch: *branch,
+            fork_point: info.fork_point,
+            order: order.max(other_order) + 1,
+        },
+        time: now(),
+        parents: vec![info.head, *other],
+        committer: USER,
+        authors: vec![USER],
+        message: "Merge".into(),
+    };
+    let commit = CommitIdentifier {
+        repository: branch.repository,
+        hash: commit_info.hash(),
+    };
+
+    let mut batch = ctx.db.batch();
+    batch.put(keys::Commit(&commit), &commit_info);
+    batch.put(
+        keys::CommitSnapshot(&commit),
+        &SnapshotEntry::Delta {
+            base: info.head,
+            delta,
+        },
+    );
+    info.head = commit;
+    batch.put(keys::Branch(branch), &info);
+    batch.write().unwrap();
+    commit
+}
+
+/// Add the changes to the live changes of the branch.
+pub(super) fn live(ctx: &Context<()>, branch: &BranchIdentifier, atoms: Vec<PatchAtom>) {
+    let patch = Patch {
+        user: USER,
+        time: now(),
+        action: 0,
+        actions: atoms,
+    };
+    ctx.db.push(keys::LiveChanges(branch), &patch).unwrap();
+}
@@ -0,0 +1,494 @@
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use ross_core::api::Context;
+use ross_core::db::keys::{self, DbKey, DbReadKey};
+use ross_core::db::DB;
+use ross_core::types::{
+    BranchId, BranchIdentifier, CommitHash, CommitIdentifier, RepositoryId, Schema, SnapshotEntry,
+    TagIdentifier, UserId,
+};
+use ross_core::utils::hash::Hash16;
+use std::cell::Cell;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+
+fn main() {
+    let matches = app().get_matches();
+    std::process::exit(match run(&matches) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}", e);
+            -1
+        }
+    });
+}
+
+fn app() -> App<'static, 'static> {
+    App::new("Ross Admin")
+        .version("0.1.0")
+        .about("Administration of a ross database.")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .arg(
+            Arg::with_name("db")
+                .long("db")
+                .help("Path to the database directory.")
+                .takes_value(true)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("schema")
+                .long("schema")
+                .help("Path to the schema artifact, used to resolve the names of the structs.")
+                .takes_value(true),
+        )
+        .subcommands(vec![
+            SubCommand::with_name("export")
+                .about("Export a repository as a bundle.")
+                .arg(
+                    Arg::with_name("REPOSITORY")
+                        .help("Id of the repository.")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("FILE")
+                        .help("The bundle file to write.")
+                        .required(true),
+                ),
+            SubCommand::with_name("import")
+                .about("Import a repository from a bundle.")
+                .arg(
+                    Arg::with_name("FILE")
+                        .help("The bundle file to read.")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("batch-size")
+                        .long("batch-size")
+                        .help("Number of the records that are written at once.")
+                        .takes_value(true)
+                        .default_value("1024"),
+                ),
+            SubCommand::with_name("dump")
+                .about("Print the state of a commit as JSON.")
+                .arg(
+                    Arg::with_name("REPOSITORY")
+                        .help("Id of the repository.")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("COMMIT")
+                        .help("Hash of the commit.")
+                        .required(true),
+                ),
+            SubCommand::with_name("seed")
+                .about("Commit the objects of a JSON document on a branch.")
+                .arg(
+                    Arg::with_name("REPOSITORY")
+                        .help("Id of the repository.")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("BRANCH")
+                        .help("Id of the branch.")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("FILE")
+                        .help("The JSON document to read.")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("user")
+                        .long("user")
+                        .help("Id of the committer.")
+                        .takes_value(true)
+                        .default_value("00000000000000000000000000000000"),
+                )
+                .arg(
+                    Arg::with_name("message")
+                        .long("message")
+                        .short("m")
+                        .help("The commit message.")
+                        .takes_value(true)
+                        .default_value("Seed"),
+                ),
+            SubCommand::with_name("repositories").about("List the repositories."),
+            SubCommand::with_name("branches")
+                .about("List the branches of a repository.")
+                .arg(repository_arg()),
+            SubCommand::with_name("branch")
+                .about("Print the information of a branch.")
+                .arg(repository_arg())
+                .arg(
+                    Arg::with_name("BRANCH")
+                        .help("Id of the branch.")
+                        .required(true),
+                ),
+            SubCommand::with_name("log")
+                .about("Print the log of a repository.")
+                .arg(repository_arg()),
+            SubCommand::with_name("commit")
+                .about("Print a commit and the chain of its snapshot.")
+                .arg(repository_arg())
+                .arg(
+                    Arg::with_name("COMMIT")
+                        .help("Hash of the commit.")
+                        .required(true),
+                ),
+            SubCommand::with_name("live-changes")
+                .about("Count the live changes of each branch of a repository.")
+                .arg(repository_arg()),
+            SubCommand::with_name("verify")
+                .about("Check the integrity of the database.")
+                .arg(Arg::with_name("REPOSITORY").help("Only check the given repository.")),
+        ])
+}
+
+fn repository_arg() -> Arg<'static, 'static> {
+    Arg::with_name("REPOSITORY")
+        .help("Id of the repository.")
+        .required(true)
+}
+
+fn run(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let path = matches.value_of("db").unwrap();
+    match matches.subcommand() {
+        (name @ "export", Some(sub))
+        | (name @ "import", Some(sub))
+        | (name @ "dump", Some(sub))
+        | (name @ "seed", Some(sub)) => {
+            let schema = match matches.value_of("schema") {
+                Some(path) => Schema::from_artifact(&std::fs::read_to_string(path)?)?,
+                None => Schema::default(),
+            };
+            manage(&Context::with_schema(path, schema), name, sub)
+        }
+        (name, Some(sub)) => inspect(&DB::open(path), name, sub),
+        _ => unreachable!(),
+    }
+}
+
+/// Run the commands that go through the API.
+fn manage(context: &Context<()>, name: &str, matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    match name {
+        "export" => {
+            let repository = repository(matches)?;
+            let file = File::create(matches.value_of("FILE").unwrap())?;
+            context.export_bundle(repository, BufWriter::new(file))?;
+        }
+        "import" => {
+            let file = File::open(matches.value_of("FILE").unwrap())?;
+            let batch_size = matches.value_of("batch-size").unwrap().parse()?;
+            let repository = context.import_bundle(BufReader::new(file), batch_size)?;
+            println!("{}", String::from(&repository.0));
+        }
+        "dump" => {
+            let commit = CommitIdentifier {
+                repository: repository(matches)?,
+                hash: CommitHash(matches.value_of("COMMIT").unwrap().parse()?),
+            };
+            print_json(&context.export_json(&commit)?)?;
+        }
+        "seed" => {
+            let branch = BranchIdentifier {
+                repository: repository(matches)?,
+                id: BranchId(matches.value_of("BRANCH").unwrap().parse()?),
+            };
+            let file = File::open(matches.value_of("FILE").unwrap())?;
+            let document = serde_json::from_reader(BufReader::new(file))?;
+            let user = UserId(matches.value_of("user").unwrap().parse::<Hash16>()?);
+            let message = matches.value_of("message").unwrap().to_string();
+            let commit = context.import_json(&branch, &document, user, message)?;
+            println!("{}", String::from(&commit.hash.0));
+        }
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+/// Run the commands that read the database directly.
+fn inspect(db: &DB, name: &str, matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    match name {
+        "repositories" => {
+            for (id, info) in keys::Repository::key_value_iterator(db, &()) {
+                let mut line = String::from(&id.0);
+                if let Some(origin) = info.fork_of {
+                    line += &format!(" fork of {}", String::from(&origin.0));
+                }
+                if db.get(keys::RepositoryDeleted(&id))?.is_some() {
+                    line += " (deleted)";
+                }
+                println!("{}", line);
+            }
+        }
+        "branches" => {
+            let repository = repository(matches)?;
+            for (id, info) in keys::Branch::key_value_iterator(db, &repository)
+                .take_while(|(id, _)| id.repository == repository)
+            {
+                println!(
+                    "{} {} {:?} {}",
+                    String::from(&id.id.0),
+                    String::from(&info.head.hash.0),
+                    info.mode,
+                    info.title
+                );
+            }
+        }
+        "branch" => {
+            let branch = BranchIdentifier {
+                repository: repository(matches)?,
+                id: BranchId(matches.value_of("BRANCH").unwrap().parse()?),
+            };
+            let info = db.get(keys::Branch(&branch))?.ok_or("Branch not found.")?;
+            print_json(&info)?;
+        }
+        "log" => {
+            let log = db
+                .get(keys::Log(&repository(matches)?))?
+                .unwrap_or_default();
+            print_json(&log)?;
+        }
+        "commit" => {
+            let mut commit = CommitIdentifier {
+                repository: repository(matches)?,
+                hash: CommitHash(matches.value_of("COMMIT").unwrap().parse()?),
+            };
+            let info = db.get(keys::Commit(&commit))?.ok_or("Commit not found.")?;
+            print_json(&info)?;
+            // Follow the delta snapshots down to the full one.
+            loop {
+                match db.get(keys::CommitSnapshot(&commit))? {
+                    Some(SnapshotEntry::Delta { base, delta }) => {
+                        println!(
+                            "{} delta of {} object(s)",
+                            String::from(&commit.hash.0),
+                            delta.len()
+                        );
+                        commit = base;
+                    }
+                    Some(SnapshotEntry::Snapshot(state)) => {
+                        println!(
+                            "{} snapshot of {} object(s)",
+                            String::from(&commit.hash.0),
+                            state.len()
+                        );
+                        break;
+                    }
+                    None => {
+                        println!("{} missing", String::from(&commit.hash.0));
+                        break;
+                    }
+                }
+            }
+        }
+        "live-changes" => {
+            let repository = repository(matches)?;
+            for id in keys::Branch::key_iterator(db, &repository)
+                .take_while(|id| id.repository == repository)
+            {
+                let patches = db.get(keys::LiveChanges(&id))?.map_or(0, |p| p.len());
+                let packed = db.get(keys::PackedDelta(&id))?.map_or(0, |d| d.len());
+                println!(
+                    "{} {} patch(es), {} packed change(s)",
+                    String::from(&id.id.0),
+                    patches,
+                    packed
+                );
+            }
+        }
+        "verify" => {
+            let repository = match matches.value_of("REPOSITORY") {
+                Some(id) => Some(RepositoryId(id.parse()?)),
+                None => None,
+            };
+            let issues = verify(db, repository)?;
+            if issues > 0 {
+                return Err(format!("Found {} issue(s).", issues).into());
+            }
+        }
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+/// Print the integrity issues of the database, and return their count.
+fn verify(db: &DB, repository: Option<RepositoryId>) -> Result<usize, Box<dyn Error>> {
+    let selected = |id: &RepositoryId| match repository {
+        Some(r) => r == *id,
+        None => true,
+    };
+    let issues = Cell::new(0);
+    let report = |issue: String| {
+        println!("{}", issue);
+        issues.set(issues.get() + 1);
+    };
+
+    // Reading a value that can not be decoded panics, so every entry is read
+    // with `scan` and the invalid ones are only reported.
+    scan::<keys::Repository>(db, "repository", &selected, &report).for_each(drop);
+    scan::<keys::RepositoryDeleted>(db, "tombstone", &selected, &report).for_each(drop);
+    scan::<keys::Log>(db, "log", &selected, &report).for_each(drop);
+    scan::<keys::LiveChanges>(db, "live changes", &selected, &report).for_each(drop);
+    scan::<keys::PackedDelta>(db, "packed delta", &selected, &report).for_each(drop);
+
+    let mut snapshots = HashSet::new();
+    let mut bases = Vec::new();
+    for (id, entry) in scan::<keys::CommitSnapshot>(db, "snapshot", &selected, &report) {
+        if let SnapshotEntry::Delta { base, .. } = entry {
+            bases.push((id, base));
+        }
+        snapshots.insert(id);
+    }
+    for (id, base) in bases.into_iter().filter(|(id, _)| selected(&id.repository)) {
+        if !snapshots.contains(&base) {
+            report(format!(
+                "missing snapshot base {} of commit {}",
+                String::from(&base.hash.0),
+                String::from(&id.hash.0)
+            ));
+        }
+    }
+
+    // The commits of every repository, the forks use the ones of their origin.
+    let commits: HashSet<CommitIdentifier> = scan::<keys::Commit>(db, "commit", &selected, &report)
+        .map(|(id, _)| id)
+        .collect();
+    for id in commits.iter().filter(|id| selected(&id.repository)) {
+        if !snapshots.contains(id) {
+            report(format!(
+                "missing snapshot of commit {}",
+                String::from(&id.hash.0)
+            ));
+        }
+    }
+
+    for (id, info) in scan::<keys::Branch>(db, "branch", &selected, &report) {
+        if !selected(&id.repository) {
+            continue;
+        }
+        if !commits.contains(&info.head) {
+            report(format!(
+                "dangling head {} of branch {}",
+                String::from(&info.head.hash.0),
+                branch_name(&id)
+            ));
+        }
+        if let Some((_, fork)) = info.fork_point {
+            if !commits.contains(&fork) {
+                report(format!(
+                    "dangling fork point {} of branch {}",
+                    String::from(&fork.hash.0),
+                    branch_name(&id)
+                ));
+            }
+        }
+    }
+    for (id, info) in scan::<keys::Tag>(db, "tag", &selected, &report) {
+        if selected(&id.repository) && !commits.contains(&info.commit) {
+            report(format!(
+                "dangling tag {} of {}",
+                id.name,
+                String::from(&id.repository.0)
+            ));
+        }
+    }
+
+    Ok(issues.get())
+}
+
+/// The keys that belong to a repository.
+trait RepositoryKey {
+    fn repository(&self) -> &RepositoryId;
+
+    fn name(&self) -> String;
+}
+
+impl RepositoryKey for RepositoryId {
+    fn repository(&self) -> &RepositoryId {
+        self
+    }
+
+    fn name(&self) -> String {
+        String::from(&self.0)
+    }
+}
+
+impl RepositoryKey for BranchIdentifier {
+    fn repository(&self) -> &RepositoryId {
+        &self.repository
+    }
+
+    fn name(&self) -> String {
+        branch_name(self)
+    }
+}
+
+impl RepositoryKey for CommitIdentifier {
+    fn repository(&self) -> &RepositoryId {
+        &self.repository
+    }
+
+    fn name(&self) -> String {
+        String::from(&self.hash.0)
+    }
+}
+
+impl RepositoryKey for TagIdentifier {
+    fn repository(&self) -> &RepositoryId {
+        &self.repository
+    }
+
+    fn name(&self) -> String {
+        format!("{} of {}", self.name, String::from(&self.repository.0))
+    }
+}
+
+/// Read every entry of a key type, the ones that can not be decoded are reported
+/// if they belong to a selected repository.
+fn scan<'a, K>(
+    db: &'a DB,
+    kind: &'a str,
+    selected: &'a dyn Fn(&RepositoryId) -> bool,
+    report: &'a dyn Fn(String),
+) -> impl Iterator<Item = (K::Key, K::Value)> + 'a
+where
+    K: DbReadKey,
+    K::Key: RepositoryKey + 'a,
+    K::Value: 'a,
+{
+    db.try_entries::<K>().filter_map(move |entry| match entry {
+        (Some(key), Some(value)) => Some((key, value)),
+        (Some(key), None) => {
+            if selected(key.repository()) {
+                report(format!("undecodable {} {}", kind, key.name()));
+            }
+            None
+        }
+        (None, _) => {
+            report(format!("undecodable {} with an invalid key", kind));
+            None
+        }
+    })
+}
+
+fn repository(matches: &ArgMatches) -> Result<RepositoryId, Box<dyn Error>> {
+    Ok(RepositoryId(
+        matches.value_of("REPOSITORY").unwrap().parse()?,
+    ))
+}
+
+fn branch_name(id: &BranchIdentifier) -> String {
+    format!(
+        "{}/{}",
+        String::from(&id.repository.0),
+        String::from(&id.id.0)
+    )
+}
+
+fn print_json<T: serde::Serialize>(value: &T) -> Result<(), Box<dyn Error>> {
+    serde_json::to_writer_pretty(io::stdout(), value)?;
+    println!();
+    Ok(())
+}
@@ -44,6 +44,31 @@ impl<'a> Batch<'a> {
             .merge_cf(cf, serialize(key.key()), serialize(value));
     }
 
+    /// Delete every key of the column family of `K` that starts with the prefix.
+    pub fn delete_prefix<K: DbWriteKey, P: serde::Serialize>(&mut self, prefix: &P) {
+        let cf = K::cf(&self.db.cf);
+        let from = serialize(prefix);
+        let mut to = from.clone();
+        // The smallest key that is greater than all of the keys with the prefix.
+        while let Some(byte) = to.pop() {
+            if byte < 255 {
+                to.push(byte + 1);
+                break;
+            }
+        }
+        if to.is_empty() {
+            // The prefix is all `0xff`, so there is no upper bound.
+            for (key, _) in self.db.db.prefix_iterator_cf(cf, &from) {
+                if !key.starts_with(&from) {
+                    break;
+                }
+                self.batch.delete_cf(cf, key);
+            }
+        } else {
+            self.batch.delete_range_cf(cf, from, to);
+        }
+    }
+
     /// Perform the atomic batch write.
     #[inline(always)]
     pub fn write(self) -> Result<()> {
@@ -11,10 +11,23 @@ pub fn serialize<S: ?Sized + serde::Serialize>(t: &S) -> Vec<u8> {
         .unwrap()
 }
 
+/// The trailing bytes are allowed, so the partial keys can read a prefix of
+/// the value.
 #[inline(always)]
 pub fn deserialize<'a, T: serde::Deserialize<'a>>(bytes: &'a [u8]) -> T {
     bincode::DefaultOptions::new()
         .with_varint_encoding()
+        .allow_trailing_bytes()
         .deserialize(bytes)
         .unwrap()
 }
+
+/// Like `deserialize` but returns `None` instead of panicking on invalid data.
+#[inline(always)]
+pub fn try_deserialize<'a, T: serde::Deserialize<'a>>(bytes: &'a [u8]) -> Option<T> {
+    bincode::DefaultOptions::new()
+        .with_varint_encoding()
+        .allow_trailing_bytes()
+        .deserialize(bytes)
+        .ok()
+}
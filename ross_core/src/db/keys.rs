@@ -12,6 +12,9 @@ db_schema!((DbKey, DbWriteKey, DbReadKey) {
         /// its information.
         RepositoryExists -> ();
     },
+    /// Mark the repositories that are being deleted, they are tombstones until
+    /// every one of their keys is purged.
+    cf DELETED_REPOSITORIES(RepositoryDeleted:RepositoryId) -> () {},
     /// Store information regarding each branch.
     cf BRANCHES(Branch:BranchIdentifier) -> BranchInfo {},
     /// Map each commit identifier to the commit data.
@@ -25,5 +28,7 @@ db_schema!((DbKey, DbWriteKey, DbReadKey) {
     /// state and store that instead of all other patches.
     cf PACKED_DELTA(PackedDelta:BranchIdentifier) -> Delta {},
     /// This column family is used to store the snapshot of each commit.
-    cf SNAPSHOT(CommitSnapshot:CommitIdentifier) -> SnapshotEntry {}
+    cf SNAPSHOT(CommitSnapshot:CommitIdentifier) -> SnapshotEntry {},
+    /// Store the tags of each repository.
+    cf TAGS(Tag:TagIdentifier) -> TagInfo {}
 });
@@ -1,4 +1,4 @@
-use super::bincode::{deserialize, serialize};
+use super::bincode::{deserialize, serialize, try_deserialize};
 use super::iterator::*;
 use super::keys::{self, DbKey, DbReadKey, DbWriteKey, CF};
 use super::Batch;
@@ -27,6 +27,9 @@ impl DB {
                 rocksdb::ColumnFamilyDescriptor::new(keys::REPOSITORIES, {
                     rocksdb::Options::default()
                 }),
+                rocksdb::ColumnFamilyDescriptor::new(keys::DELETED_REPOSITORIES, {
+                    rocksdb::Options::default()
+                }),
                 rocksdb::ColumnFamilyDescriptor::new(keys::BRANCHES, {
                     rocksdb::Options::default()
                 }),
@@ -49,6 +52,9 @@ impl DB {
                 rocksdb::ColumnFamilyDescriptor::new(keys::PACKED_DELTA, {
                     rocksdb::Options::default()
                 }),
+                rocksdb::ColumnFamilyDescriptor::new(keys::TAGS, {
+                    rocksdb::Options::default()
+                }),
             ],
         )
         .unwrap();
@@ -115,6 +121,27 @@ impl DB {
             phantom: PhantomData,
         }
     }
+
+    /// Scan all of the values of a key type and return the keys of the ones that
+    /// fail to decode (e.g because of a corrupted merge operand), the keys that
+    /// can not be decoded either are returned as `None`.
+    pub fn undecodable<K: DbReadKey>(&self) -> Vec<Option<K::Key>> {
+        self.try_entries::<K>()
+            .filter(|(_, value)| value.is_none())
+            .map(|(key, _)| key)
+            .collect()
+    }
+
+    /// Iterate over all of the entries of a key type without panicking on the
+    /// invalid data, the keys or values that can not be decoded are `None`.
+    pub fn try_entries<K: DbReadKey>(
+        &self,
+    ) -> impl Iterator<Item = (Option<K::Key>, Option<K::Value>)> + '_ {
+        let cf = K::cf(&self.cf);
+        self.db
+            .prefix_iterator_cf(cf, [0u8; 0])
+            .map(|(key, value)| (try_deserialize(&key), try_deserialize(&value)))
+    }
 }
 
 #[inline]
@@ -126,3 +153,32 @@ fn vec_push_merge(
     let result = merge_push(existing_val, operands);
     Some(result)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::{LogEvent, RepositoryId, UserId};
+    use crate::utils::hash::Hash16;
+
+    #[test]
+    fn undecodable() {
+        let path = std::env::temp_dir().join(format!("ross-{}", rand::random::<u64>()));
+        let db = DB::open(path.to_str().unwrap());
+        let valid = RepositoryId(Hash16::MIN);
+        let corrupted = RepositoryId(Hash16::MAX);
+        let event = LogEvent::Init {
+            user: UserId(Hash16::MIN),
+            time: 0,
+        };
+        db.push(keys::Log(&valid), &event).unwrap();
+        db.push(keys::Log(&corrupted), &event).unwrap();
+        // An operand with an unknown variant of `LogEvent`.
+        db.db
+            .merge_cf(keys::Log::cf(&db.cf), serialize(&corrupted), [0x7f])
+            .unwrap();
+
+        assert_eq!(db.undecodable::<keys::Log>(), vec![Some(corrupted)]);
+        drop(db);
+        let _ = std::fs::remove_dir_all(path);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::types::{MergeConflict, PatchConflict};
 use rocksdb;
 use std::error;
 use std::fmt;
@@ -5,19 +6,44 @@ use std::fmt;
 #[derive(Debug)]
 pub enum Error {
     DBError(rocksdb::Error),
+    Io(std::io::Error),
     AcquireWriteLock,
     AcquireReadLock,
     AcquireLock,
     LcaNotFound,
     CommitNotFound,
     BranchNotFound,
+    /// The branch did not exist at the given time.
+    BeforeBranchCreated,
+    RepositoryNotFound,
     CheckoutFailed,
+    /// The branch has live changes that are not committed yet.
+    UncommittedChanges,
+    /// The branch is archived and can not be changed.
+    BranchArchived,
+    /// The branch is static and can only be changed by merges.
+    StaticBranch,
+    /// The branch does not have a parent branch.
+    NotForked,
+    TagNotFound,
+    TagExists,
+    /// There are open sessions on a branch of the repository.
+    RepositoryInUse,
+    /// The repository can not be deleted while it has forks.
+    RepositoryHasForks,
+    RepositoryExists,
+    InvalidBundle(String),
+    InvalidDocument(String),
+    MergeConflict(Vec<MergeConflict>),
+    /// The patch conflicts with the live state or breaks the schema.
+    PatchConflict(Vec<PatchConflict>),
 }
 
 impl error::Error for Error {
     fn cause(&self) -> Option<&dyn error::Error> {
         match self {
             Error::DBError(e) => Some(e),
+            Error::Io(e) => Some(e),
             _ => None,
         }
     }
@@ -27,13 +53,33 @@ impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Error::DBError(e) => write!(f, "RocksDB: {}", e),
+            Error::Io(e) => write!(f, "IO: {}", e),
             Error::AcquireWriteLock => write!(f, "Could not acquire the write lock."),
             Error::AcquireReadLock => write!(f, "Could not acquire the read lock."),
             Error::AcquireLock => write!(f, "Could not acquire a lock."),
             Error::LcaNotFound => write!(f, "Could not find LCA of two commits."),
             Error::CommitNotFound => write!(f, "Could not find the commit in DB."),
             Error::BranchNotFound => write!(f, "Could not find the branch in DB."),
+            Error::BeforeBranchCreated => write!(f, "The branch was not created yet."),
+            Error::RepositoryNotFound => write!(f, "Could not find the repository in DB."),
             Error::CheckoutFailed => write!(f, "Checkout failed."),
+            Error::UncommittedChanges => write!(f, "The branch has uncommitted changes."),
+            Error::BranchArchived => write!(f, "The branch is archived."),
+            Error::StaticBranch => write!(f, "The branch can only be changed by merges."),
+            Error::NotForked => write!(f, "The branch is not forked from another branch."),
+            Error::TagNotFound => write!(f, "Could not find the tag in DB."),
+            Error::TagExists => write!(f, "A tag with the same name already exists."),
+            Error::RepositoryInUse => write!(f, "The repository has open sessions."),
+            Error::RepositoryHasForks => write!(f, "The repository has forks."),
+            Error::RepositoryExists => write!(f, "The repository already exists."),
+            Error::InvalidBundle(reason) => write!(f, "Invalid bundle: {}", reason),
+            Error::InvalidDocument(reason) => write!(f, "Invalid document: {}", reason),
+            Error::MergeConflict(conflicts) => {
+                write!(f, "Merge failed with {} conflict(s).", conflicts.len())
+            }
+            Error::PatchConflict(conflicts) => {
+                write!(f, "Patch failed with {} conflict(s).", conflicts.len())
+            }
         }
     }
 }
@@ -0,0 +1,184 @@
+//! The portable bundle format of a repository.
+//! A bundle is a stream of records, each one is prefixed by its length as a
+//! little-endian `u32`. It starts with a `Header` and ends with an `End` that
+//! holds the SHA-1 of every record before it.
+
+use super::{
+    BranchId, BranchInfo, CommitHash, CommitInfo, Delta, LogEvent, Patch, RepositoryId,
+    RepositoryInfo, SnapshotEntry, TagInfo,
+};
+use crate::error::{Error, Result};
+use crate::utils::hash::Hash20;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::io::{self, Read, Write};
+
+/// Version of the bundle format, it is increased on breaking changes.
+pub const BUNDLE_VERSION: u32 = 1;
+
+/// Records larger than this are rejected when reading a bundle.
+const MAX_RECORD_SIZE: usize = 1 << 30;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum BundleRecord {
+    Header {
+        version: u32,
+        repository: RepositoryId,
+    },
+    Repository(RepositoryInfo),
+    Log(Vec<LogEvent>),
+    Branch(BranchId, BranchInfo),
+    Tag(String, TagInfo),
+    Commit(CommitHash, CommitInfo),
+    Snapshot(CommitHash, SnapshotEntry),
+    LiveChanges(BranchId, Vec<Patch>),
+    PackedDelta(BranchId, Delta),
+    End(Hash20),
+}
+
+pub struct BundleWriter<W: Write> {
+    writer: W,
+    hasher: Sha1,
+}
+
+pub struct BundleReader<R: Read> {
+    reader: R,
+    hasher: Sha1,
+    done: bool,
+}
+
+impl<W: Write> BundleWriter<W> {
+    pub fn new(writer: W) -> Self {
+        BundleWriter {
+            writer,
+            hasher: Sha1::new(),
+        }
+    }
+
+    pub fn write(&mut self, record: &BundleRecord) -> Result<()> {
+        let bytes = bincode::serialize(record).unwrap();
+        self.hasher.update(&bytes);
+        self.write_bytes(&bytes).map_err(Error::Io)
+    }
+
+    /// Write the `End` record and return the writer.
+    pub fn finish(mut self) -> Result<W> {
+        let mut hash = [0; 20];
+        hash.copy_from_slice(&self.hasher.finalize_reset());
+        let bytes = bincode::serialize(&BundleRecord::End(Hash20::from(hash))).unwrap();
+        self.write_bytes(&bytes).map_err(Error::Io)?;
+        self.writer.flush().map_err(Error::Io)?;
+        Ok(self.writer)
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.writer.write_all(&(bytes.len() as u32).to_le_bytes())?;
+        self.writer.write_all(bytes)
+    }
+}
+
+impl<R: Read> BundleReader<R> {
+    pub fn new(reader: R) -> Self {
+        BundleReader {
+            reader,
+            hasher: Sha1::new(),
+            done: false,
+        }
+    }
+
+    /// Read the next record, the checksum is verified when the `End` record is
+    /// read, nothing can be read after it.
+    pub fn read(&mut self) -> Result<BundleRecord> {
+        if self.done {
+            return Err(invalid("there is nothing after the end"));
+        }
+
+        let mut len = [0; 4];
+        self.read_exact(&mut len)?;
+        let len = u32::from_le_bytes(len) as usize;
+        if len > MAX_RECORD_SIZE {
+            return Err(invalid("the record is too large"));
+        }
+        let mut bytes = vec![0; len];
+        self.read_exact(&mut bytes)?;
+        let record = bincode::deserialize(&bytes).map_err(|_| invalid("malformed record"))?;
+
+        if let BundleRecord::End(hash) = &record {
+            let mut expected = [0; 20];
+            expected.copy_from_slice(&self.hasher.finalize_reset());
+            if Hash20::from(expected) != *hash {
+                return Err(invalid("checksum mismatch"));
+            }
+            self.done = true;
+        } else {
+            self.hasher.update(&bytes);
+        }
+        Ok(record)
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        self.reader.read_exact(buf).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => invalid("unexpected end of the bundle"),
+            _ => Error::Io(e),
+        })
+    }
+}
+
+#[inline]
+fn invalid(reason: &str) -> Error {
+    Error::InvalidBundle(reason.into())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::UserId;
+    use crate::utils::hash::Hash16;
+
+    fn bundle() -> Vec<u8> {
+        let mut writer = BundleWriter::new(Vec::new());
+        writer
+            .write(&BundleRecord::Header {
+                version: BUNDLE_VERSION,
+                repository: RepositoryId(Hash16::MAX),
+            })
+            .unwrap();
+        writer
+            .write(&BundleRecord::Log(vec![LogEvent::Init {
+                user: UserId(Hash16::MIN),
+                time: 7,
+            }]))
+            .unwrap();
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let bytes = bundle();
+        let mut reader = BundleReader::new(&bytes[..]);
+        assert!(matches!(
+            reader.read(),
+            Ok(BundleRecord::Header { version: 1, .. })
+        ));
+        assert!(matches!(reader.read(), Ok(BundleRecord::Log(log)) if log.len() == 1));
+        assert!(matches!(reader.read(), Ok(BundleRecord::End(_))));
+        assert!(reader.read().is_err());
+    }
+
+    #[test]
+    fn corrupted() {
+        let mut bytes = bundle();
+        // Change the time of the log event.
+        let index = bytes.iter().rposition(|b| *b == 7).unwrap();
+        bytes[index] = 8;
+        let mut reader = BundleReader::new(&bytes[..]);
+        reader.read().unwrap();
+        reader.read().unwrap();
+        assert!(matches!(reader.read(), Err(Error::InvalidBundle(_))));
+
+        let mut reader = BundleReader::new(&bytes[..bytes.len() - 1]);
+        reader.read().unwrap();
+        reader.read().unwrap();
+        assert!(matches!(reader.read(), Err(Error::InvalidBundle(_))));
+    }
+}
@@ -27,4 +27,6 @@ pub enum PatchConflict {
     WriteWrite { oid: ObjectId, field: FieldIndex },
     DeleteWrite { oid: ObjectId },
     WriteDelete { oid: ObjectId },
+    /// The value does not satisfy the constraints of the field in the schema.
+    InvalidValue { oid: ObjectId, field: FieldIndex },
 }
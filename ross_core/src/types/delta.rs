@@ -16,3 +16,15 @@ pub enum DeltaEntry {
 }
 
 pub type Delta = HashMap<ObjectId, DeltaEntry>;
+
+impl DeltaEntry {
+    /// Returns `true` if this entry changes the given field of the object, or
+    /// the object itself when no field is given.
+    #[inline]
+    pub fn touches(&self, field: Option<FieldIndex>) -> bool {
+        match (self, field) {
+            (DeltaEntry::Updated { changes, .. }, Some(field)) => changes.contains_key(&field),
+            _ => true,
+        }
+    }
+}
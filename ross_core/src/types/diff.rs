@@ -0,0 +1,217 @@
+use super::{DeltaEntry, FieldIndex, Object, ObjectId, PrimitiveValue, Schema, State};
+use serde_json::{json, Value};
+
+/// A structured diff between two states which is meant to be shown to the
+/// users, the objects are sorted by their id.
+#[derive(Debug, Default)]
+pub struct StateDiff {
+    pub inserted: Vec<ObjectDiff>,
+    pub deleted: Vec<ObjectDiff>,
+    pub updated: Vec<ObjectDiff>,
+}
+
+/// The changed fields of an object, every non-null field of the inserted and
+/// the deleted objects is listed.
+#[derive(Debug)]
+pub struct ObjectDiff {
+    pub id: ObjectId,
+    pub tag: Option<u32>,
+    pub fields: Vec<FieldDiff>,
+}
+
+#[derive(Debug)]
+pub struct FieldDiff {
+    pub field: FieldIndex,
+    pub old: PrimitiveValue,
+    pub new: PrimitiveValue,
+}
+
+impl StateDiff {
+    /// Compute the diff that turns the `from` state to the `to` state.
+    pub fn new(from: &State, to: &State) -> Self {
+        let mut diff = StateDiff::default();
+        for (oid, entry) in from.diff(to) {
+            match entry {
+                DeltaEntry::Deleted => {
+                    let obj = from.get(&oid).unwrap();
+                    diff.deleted
+                        .push(ObjectDiff::new(oid, obj, |value| FieldDiff {
+                            field: 0,
+                            old: value.clone(),
+                            new: PrimitiveValue::Null,
+                        }));
+                }
+                DeltaEntry::Inserted { .. } => {
+                    let obj = to.get(&oid).unwrap();
+                    diff.inserted
+                        .push(ObjectDiff::new(oid, obj, |value| FieldDiff {
+                            field: 0,
+                            old: PrimitiveValue::Null,
+                            new: value.clone(),
+                        }));
+                }
+                DeltaEntry::Updated { changes, .. } => {
+                    let old = from.get(&oid).unwrap();
+                    let fields = changes
+                        .into_iter()
+                        .map(|(field, new)| FieldDiff {
+                            field,
+                            old: old.get(field).clone(),
+                            new,
+                        })
+                        .collect();
+                    diff.updated.push(ObjectDiff {
+                        id: oid,
+                        tag: to.get(&oid).unwrap().tag(),
+                        fields,
+                    });
+                }
+            }
+        }
+        diff.inserted.sort_by_key(|obj| obj.id);
+        diff.deleted.sort_by_key(|obj| obj.id);
+        diff.updated.sort_by_key(|obj| obj.id);
+        diff
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.inserted.is_empty() && self.deleted.is_empty() && self.updated.is_empty()
+    }
+
+    /// Render the diff as JSON, the names of the structs and the fields are
+    /// resolved using the schema, the unknown ones are `null` and the index of
+    /// the field respectively.
+    pub fn to_json(&self, schema: &Schema) -> Value {
+        let objects =
+            |list: &[ObjectDiff]| -> Value { list.iter().map(|obj| obj.to_json(schema)).collect() };
+        json!({
+            "inserted": objects(&self.inserted),
+            "deleted": objects(&self.deleted),
+            "updated": objects(&self.updated),
+        })
+    }
+}
+
+impl ObjectDiff {
+    /// List the non-null fields of the object, the tag is not a field.
+    fn new<F: Fn(&PrimitiveValue) -> FieldDiff>(id: ObjectId, obj: &Object, f: F) -> Self {
+        let fields = obj
+            .data
+            .iter()
+            .enumerate()
+            .skip(1)
+            .filter(|(_, value)| **value != PrimitiveValue::Null)
+            .map(|(i, value)| FieldDiff {
+                field: i as FieldIndex,
+                ..f(value)
+            })
+            .collect();
+        ObjectDiff {
+            id,
+            tag: obj.tag(),
+            fields,
+        }
+    }
+
+    fn to_json(&self, schema: &Schema) -> Value {
+        let st = self.tag.and_then(|tag| schema.get_struct(tag));
+        let fields: Vec<Value> = self
+            .fields
+            .iter()
+            .map(|f| {
+                let field = f.field.checked_sub(1);
+                let name = match st.zip(field).and_then(|(st, i)| st.fields.get(i as usize)) {
+                    Some(field) => json!(field.name),
+                    None => json!(f.field),
+                };
+                json!({ "field": name, "old": f.old, "new": f.new })
+            })
+            .collect();
+        json!({
+            "id": self.id,
+            "struct": st.map(|st| &st.name),
+            "fields": fields,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::PatchAtom;
+    use crate::utils::hash::Hash16;
+
+    const ARTIFACT: &str = r#"{
+        "version": 1,
+        "structs": [
+            {
+                "name": "Box",
+                "id": 0,
+                "types": ["Str", "U32"],
+                "layout": [{ "field": "title" }, { "field": "size" }]
+            }
+        ],
+        "actions": []
+    }"#;
+
+    fn id(n: u8) -> ObjectId {
+        let mut bytes = [0; 16];
+        bytes[15] = n;
+        Hash16::from(bytes)
+    }
+
+    fn insert(oid: ObjectId, data: Vec<PrimitiveValue>) -> PatchAtom {
+        PatchAtom::Insert {
+            oid,
+            data,
+            version: None,
+        }
+    }
+
+    #[test]
+    fn diff() {
+        let schema = Schema::from_artifact(ARTIFACT).unwrap();
+        let u = PrimitiveValue::U32;
+        let mut from = State::default();
+        from.perform(vec![
+            insert(id(1), vec![u(0), "A".into(), u(1)]),
+            insert(id(2), vec![u(0), "B".into()]),
+        ])
+        .unwrap();
+        let mut to = State::default();
+        to.perform(vec![
+            insert(id(1), vec![u(0), "A".into(), u(2)]),
+            insert(id(3), vec![u(5), "C".into()]),
+        ])
+        .unwrap();
+
+        let diff = StateDiff::new(&from, &to);
+        assert!(!diff.is_empty());
+        assert!(StateDiff::new(&to, &to).is_empty());
+        assert_eq!(diff.deleted.len(), 1);
+        assert_eq!(diff.deleted[0].fields[0].old, "B".into());
+        assert_eq!(diff.updated[0].fields.len(), 1);
+
+        assert_eq!(
+            diff.to_json(&schema),
+            json!({
+                "inserted": [{
+                    "id": id(3),
+                    "struct": null,
+                    "fields": [{ "field": 1, "old": null, "new": "C" }],
+                }],
+                "deleted": [{
+                    "id": id(2),
+                    "struct": "Box",
+                    "fields": [{ "field": "title", "old": "B", "new": null }],
+                }],
+                "updated": [{
+                    "id": id(1),
+                    "struct": "Box",
+                    "fields": [{ "field": "size", "old": 1, "new": 2 }],
+                }],
+            })
+        );
+    }
+}
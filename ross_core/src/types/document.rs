@@ -0,0 +1,403 @@
+//! The human-readable JSON form of a state, it is used to inspect the content of
+//! a commit and to seed a repository from fixtures.
+//! ```json
+//! {
+//!   "version": 1,
+//!   "objects": [{
+//!     "id": "<hex id>",
+//!     "struct": "Scene",
+//!     "fields": { "title": "Untitled" },
+//!     "members": { "boxes": [{ "id": "<hex id>", "struct": "Box", "fields": { "size": 10 } }] }
+//!   }]
+//! }
+//! ```
+//! The owner field is omitted from the nested members, and the objects whose
+//! struct is not in the schema are written as `{ "id", "data" }` with the raw
+//! data-vector. An object of an ownership cycle, or that owns itself, is written
+//! at the top with its owner field.
+
+use super::{FieldIndex, ObjectId, PatchAtom, PrimitiveType, PrimitiveValue, Schema, State};
+use crate::error::{Error, Result};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Version of the document format, it is increased on breaking changes.
+pub const DOCUMENT_VERSION: u32 = 1;
+
+/// The members of each owner, grouped by the name of the member field.
+type Members<'a> = HashMap<ObjectId, BTreeMap<&'a str, Vec<ObjectId>>>;
+
+impl State {
+    /// Dump the objects as a JSON document with the names from the schema, the
+    /// owned objects are nested under their owners.
+    pub fn to_json(&self, schema: &Schema) -> Value {
+        let mut members = Members::new();
+        let mut roots = Vec::new();
+        for (oid, obj) in self.iter() {
+            let owner = obj
+                .tag()
+                .and_then(|tag| schema.get_struct(tag))
+                .and_then(|st| st.owner.as_ref());
+            match (owner, obj.get(1)) {
+                (Some((_, field)), PrimitiveValue::Hash16(owner)) if self.get(owner).is_some() => {
+                    members
+                        .entry(*owner)
+                        .or_default()
+                        .entry(field.as_str())
+                        .or_default()
+                        .push(*oid);
+                }
+                _ => roots.push(*oid),
+            }
+        }
+
+        // The objects of an ownership cycle are not reachable from the roots, the
+        // smallest one of each cycle is moved to the top with its owner field.
+        roots.sort();
+        let mut reached = HashSet::new();
+        let mut stack = roots.clone();
+        let mut rest: Vec<&ObjectId> = self.iter().map(|(oid, _)| oid).collect();
+        rest.sort();
+        for oid in rest {
+            while let Some(member) = stack.pop() {
+                if reached.insert(member) {
+                    if let Some(groups) = members.get(&member) {
+                        stack.extend(groups.values().flatten());
+                    }
+                }
+            }
+            if reached.contains(oid) {
+                continue;
+            }
+            if let PrimitiveValue::Hash16(owner) = self.get(oid).unwrap().get(1) {
+                if let Some(groups) = members.get_mut(owner) {
+                    for ids in groups.values_mut() {
+                        ids.retain(|id| id != oid);
+                    }
+                    groups.retain(|_, ids| !ids.is_empty());
+                    if groups.is_empty() {
+                        members.remove(owner);
+                    }
+                }
+            }
+            roots.push(*oid);
+            stack.push(*oid);
+        }
+
+        let objects: Vec<Value> = roots
+            .iter()
+            .filter_map(|oid| self.object_json(schema, &members, oid, false))
+            .collect();
+        json!({ "version": DOCUMENT_VERSION, "objects": objects })
+    }
+
+    /// Build a state from a document in the format of `to_json`, the objects
+    /// that are also in the base keep their version.
+    pub fn from_json(document: &Value, schema: &Schema, base: &State) -> Result<State> {
+        match document.get("version").and_then(Value::as_u64) {
+            Some(version) if version == DOCUMENT_VERSION as u64 => {}
+            Some(version) => return Err(invalid(format!("unsupported version {}", version))),
+            None => return Err(invalid("missing version")),
+        }
+        let objects = document
+            .get("objects")
+            .and_then(Value::as_array)
+            .ok_or_else(|| invalid("missing objects"))?;
+
+        let mut atoms = Vec::new();
+        for object in objects {
+            read_object(object, schema, None, &mut atoms)?;
+        }
+        for atom in &mut atoms {
+            if let PatchAtom::Insert { oid, version, .. } = atom {
+                *version = base.get(oid).map(|obj| obj.version);
+            }
+        }
+
+        let mut state = State::default();
+        state
+            .perform_with_schema(atoms, schema)
+            .map_err(|conflicts| invalid(format!("{:?}", conflicts[0])))?;
+        Ok(state)
+    }
+
+    fn object_json(
+        &self,
+        schema: &Schema,
+        members: &Members,
+        oid: &ObjectId,
+        nested: bool,
+    ) -> Option<Value> {
+        let obj = self.get(oid)?;
+        let mut value = Map::new();
+        value.insert("id".into(), json!(oid));
+        match obj.tag().and_then(|tag| schema.get_struct(tag)) {
+            Some(st) => {
+                // The owner is the first field, it is implied by the nesting.
+                let fields: Map<String, Value> = st
+                    .fields
+                    .iter()
+                    .enumerate()
+                    .skip(if nested { 1 } else { 0 })
+                    .map(|(i, field)| (field.name.clone(), json!(obj.get((i + 1) as FieldIndex))))
+                    .collect();
+                value.insert("struct".into(), json!(st.name));
+                value.insert("fields".into(), Value::Object(fields));
+            }
+            None => {
+                value.insert("data".into(), json!(obj.data));
+            }
+        }
+
+        if let Some(groups) = members.get(oid) {
+            let groups: Map<String, Value> = groups
+                .iter()
+                .map(|(field, ids)| {
+                    let mut ids = ids.clone();
+                    ids.sort();
+                    let list = ids
+                        .iter()
+                        .filter_map(|id| self.object_json(schema, members, id, true))
+                        .collect();
+                    (field.to_string(), Value::Array(list))
+                })
+                .collect();
+            value.insert("members".into(), Value::Object(groups));
+        }
+
+        Some(Value::Object(value))
+    }
+}
+
+/// Collect the insert actions of an object and its members, `owner` is the id of
+/// the owner and the name of the member field for the nested objects.
+fn read_object(
+    object: &Value,
+    schema: &Schema,
+    owner: Option<(ObjectId, &str)>,
+    atoms: &mut Vec<PatchAtom>,
+) -> Result<()> {
+    let oid: ObjectId = object
+        .get("id")
+        .and_then(Value::as_str)
+        .and_then(|id| id.parse().ok())
+        .ok_or_else(|| invalid("missing or invalid id"))?;
+
+    let data = match object.get("struct") {
+        Some(name) => {
+            let name = name.as_str().unwrap_or_default();
+            let st = schema
+                .find(name)
+                .ok_or_else(|| invalid(format!("unknown struct {}", name)))?;
+            let mut data = vec![PrimitiveValue::Null; st.fields.len() + 1];
+            data[0] = PrimitiveValue::U32(st.tag);
+            if let Some(fields) = object.get("fields") {
+                let fields = fields
+                    .as_object()
+                    .ok_or_else(|| invalid(format!("invalid fields of {:?}", oid)))?;
+                for (field, value) in fields {
+                    let index = st
+                        .field_index(field)
+                        .ok_or_else(|| invalid(format!("unknown field {}.{}", st.name, field)))?
+                        as usize;
+                    data[index] = read_value(st.fields[index - 1].ty, value).ok_or_else(|| {
+                        invalid(format!("invalid value of {}.{}", st.name, field))
+                    })?;
+                }
+            }
+            if let Some((owner, field)) = owner {
+                match &st.owner {
+                    Some((_, f)) if f == field => data[1] = PrimitiveValue::Hash16(owner),
+                    _ => {
+                        return Err(invalid(format!(
+                            "{} is not a member of .{}",
+                            st.name, field
+                        )))
+                    }
+                }
+            }
+            data
+        }
+        None => object
+            .get("data")
+            .and_then(|data| serde_json::from_value(data.clone()).ok())
+            .ok_or_else(|| invalid(format!("invalid data of {:?}", oid)))?,
+    };
+    atoms.push(PatchAtom::Insert {
+        oid,
+        data,
+        version: None,
+    });
+
+    if let Some(members) = object.get("members") {
+        let members = members
+            .as_object()
+            .ok_or_else(|| invalid(format!("invalid members of {:?}", oid)))?;
+        for (field, list) in members {
+            let list = list
+                .as_array()
+                .ok_or_else(|| invalid(format!("invalid members of {:?}", oid)))?;
+            for member in list {
+                read_object(member, schema, Some((oid, field)), atoms)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Read the value of a field, the value must be of the type of the field.
+fn read_value(ty: PrimitiveType, value: &Value) -> Option<PrimitiveValue> {
+    let value = match (ty, serde_json::from_value(value.clone()).ok()?) {
+        // A string of 32 hex digits is read as a hash.
+        (PrimitiveType::Str, PrimitiveValue::Hash16(hash)) => String::from(&hash).into(),
+        (_, value) => value,
+    };
+    match value {
+        PrimitiveValue::Null => Some(value),
+        _ if value.is_of(&ty) => Some(value),
+        _ => None,
+    }
+}
+
+#[inline]
+fn invalid<S: Into<String>>(reason: S) -> Error {
+    Error::InvalidDocument(reason.into())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::Decimal;
+    use crate::utils::hash::Hash16;
+
+    const ARTIFACT: &str = r#"{
+        "version": 1,
+        "structs": [{
+            "name": "Scene",
+            "id": 1,
+            "fields": [],
+            "members": [],
+            "types": ["Str"],
+            "layout": [{ "field": "title", "attributes": { "default": "Untitled" } }]
+        }, {
+            "name": "Box",
+            "id": 2,
+            "fields": [],
+            "members": [],
+            "owner": { "type": "Scene", "field": "boxes" },
+            "types": ["Hash", { "Decimal": 2 }],
+            "layout": [
+                { "field": "owner", "attributes": {} },
+                { "field": "size", "attributes": {} }
+            ]
+        }]
+    }"#;
+
+    #[test]
+    fn round_trip() {
+        let schema = Schema::from_artifact(ARTIFACT).unwrap();
+        let scene = Hash16::from([1; 16]);
+        let first = Hash16::from([2; 16]);
+        let unknown = Hash16::from([3; 16]);
+        let mut state = State::default();
+        state
+            .perform_with_schema(
+                vec![
+                    PatchAtom::Insert {
+                        oid: scene,
+                        data: vec![1u32.into(), "a3b2c1d0e9f8a7b6c5d4e3f2a1b0c9d8".into()],
+                        version: None,
+                    },
+                    PatchAtom::Insert {
+                        oid: first,
+                        data: vec![
+                            2u32.into(),
+                            scene.into(),
+                            "1.25".parse::<Decimal>().unwrap().into(),
+                        ],
+                        version: None,
+                    },
+                    PatchAtom::Insert {
+                        oid: unknown,
+                        data: vec![9u32.into(), true.into()],
+                        version: None,
+                    },
+                ],
+                &schema,
+            )
+            .unwrap();
+
+        let document = state.to_json(&schema);
+        let objects = document["objects"].as_array().unwrap();
+        assert_eq!(objects.len(), 2);
+        assert_eq!(objects[0]["struct"], "Scene");
+        let member = &objects[0]["members"]["boxes"][0];
+        assert_eq!(member["id"], json!(first));
+        assert_eq!(member["fields"], json!({ "size": { "decimal": "1.25" } }));
+        assert_eq!(objects[1]["data"], json!([9, true]));
+
+        let copy = State::from_json(&document, &schema, &state).unwrap();
+        assert!(state.diff(&copy).is_empty());
+        assert_eq!(copy.to_json(&schema), document);
+    }
+
+    #[test]
+    fn cycles() {
+        let schema = Schema::from_artifact(ARTIFACT).unwrap();
+        let boxed = |oid: u8, owner: u8| PatchAtom::Insert {
+            oid: Hash16::from([oid; 16]),
+            data: vec![
+                2u32.into(),
+                Hash16::from([owner; 16]).into(),
+                PrimitiveValue::Null,
+            ],
+            version: None,
+        };
+        let mut state = State::default();
+        state
+            .perform_with_schema(vec![boxed(1, 1), boxed(2, 3), boxed(3, 2)], &schema)
+            .unwrap();
+
+        let document = state.to_json(&schema);
+        let objects = document["objects"].as_array().unwrap();
+        assert_eq!(objects.len(), 2);
+        assert_eq!(objects[0]["id"], json!(Hash16::from([1; 16])));
+        assert_eq!(objects[0]["fields"]["owner"], json!(Hash16::from([1; 16])));
+        assert!(objects[0].get("members").is_none());
+        assert_eq!(objects[1]["id"], json!(Hash16::from([2; 16])));
+        let member = &objects[1]["members"]["boxes"][0];
+        assert_eq!(member["id"], json!(Hash16::from([3; 16])));
+        assert!(member.get("members").is_none());
+
+        let copy = State::from_json(&document, &schema, &state).unwrap();
+        assert!(state.diff(&copy).is_empty());
+    }
+
+    #[test]
+    fn invalid() {
+        let schema = Schema::from_artifact(ARTIFACT).unwrap();
+        let scene = Hash16::from([1; 16]);
+        let read = |objects: Value| {
+            let document = json!({ "version": 1, "objects": objects });
+            State::from_json(&document, &schema, &State::default())
+        };
+
+        assert!(read(json!([{ "id": scene, "struct": "Scene" }])).is_ok());
+        assert!(read(json!([{ "id": scene, "struct": "Circle" }])).is_err());
+        assert!(
+            read(json!([{ "id": scene, "struct": "Scene", "fields": { "title": 1 } }])).is_err()
+        );
+        assert!(read(json!([{
+            "id": scene,
+            "struct": "Scene",
+            "members": { "circles": [{ "id": Hash16::from([2; 16]), "struct": "Box" }] }
+        }]))
+        .is_err());
+        assert!(read(json!([
+            { "id": scene, "struct": "Scene" },
+            { "id": scene, "struct": "Scene" }
+        ]))
+        .is_err());
+    }
+}
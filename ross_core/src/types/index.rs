@@ -0,0 +1,320 @@
+use super::{Delta, DeltaEntry, FieldIndex, ObjectId, PrimitiveValue, Schema, State};
+use crate::utils::hash::Hash16;
+use std::borrow::Cow;
+use std::collections::{hash_map::Entry, HashMap, HashSet};
+
+/// Secondary indexes over the objects of a state, they are kept in sync with the
+/// state by feeding them the deltas that change it.
+#[derive(Debug, Default)]
+pub struct Indexes {
+    by_tag: HashMap<u32, HashSet<ObjectId>>,
+    by_owner: HashMap<ObjectId, HashSet<ObjectId>>,
+    /// The objects that have a reference to an object in any of their fields,
+    /// every `Hash16` value is considered a reference.
+    references: HashMap<ObjectId, HashSet<ObjectId>>,
+    /// Values of the `@index` fields, by the struct tag and the field index.
+    by_value: HashMap<(u32, FieldIndex), HashMap<IndexKey, HashSet<ObjectId>>>,
+}
+
+/// A hashable form of a `PrimitiveValue`, the values that are equal have the
+/// same key. (e.g `1`, `1.0` and `1.00`)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum IndexKey {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(u64),
+    Decimal(i64, u8),
+    Hash(Hash16),
+    String(Box<str>),
+}
+
+impl Indexes {
+    /// Build the indexes of all of the objects in the state.
+    pub fn build(schema: &Schema, state: &State) -> Self {
+        let mut indexes = Indexes::default();
+        for (oid, obj) in state.iter() {
+            indexes.insert(schema, oid, &obj.data);
+        }
+        indexes
+    }
+
+    /// Update the indexes after a `State::perform`, the given delta is the one
+    /// returned by `perform` which turns the state back to what it was.
+    pub fn update(&mut self, schema: &Schema, state: &State, inverse: &Delta) {
+        for (oid, entry) in inverse {
+            let current = state.get(oid);
+            let previous = match entry {
+                DeltaEntry::Deleted => None,
+                DeltaEntry::Inserted { data, .. } => Some(Cow::Borrowed(&data[..])),
+                DeltaEntry::Updated { changes, .. } => {
+                    let mut data = current.map(|o| o.data.clone()).unwrap_or_default();
+                    for (field, value) in changes {
+                        let field = *field as usize;
+                        if field >= data.len() {
+                            data.resize(field + 1, PrimitiveValue::Null);
+                        }
+                        data[field] = value.clone();
+                    }
+                    Some(Cow::Owned(data))
+                }
+            };
+
+            if let Some(previous) = previous {
+                self.remove(schema, oid, &previous);
+            }
+            if let Some(obj) = current {
+                self.insert(schema, oid, &obj.data);
+            }
+        }
+    }
+
+    /// Apply a trusted delta to the state and update the indexes.
+    pub fn apply_delta(&mut self, schema: &Schema, state: &mut State, delta: Delta) {
+        let oids: Vec<ObjectId> = delta.keys().copied().collect();
+        for oid in &oids {
+            if let Some(obj) = state.get(oid) {
+                self.remove(schema, oid, &obj.data);
+            }
+        }
+        state.apply_delta_trusted(delta);
+        for oid in &oids {
+            if let Some(obj) = state.get(oid) {
+                self.insert(schema, oid, &obj.data);
+            }
+        }
+    }
+
+    /// The objects of the struct with the given tag.
+    pub fn of_tag(&self, tag: u32) -> impl Iterator<Item = &ObjectId> {
+        self.by_tag.get(&tag).into_iter().flatten()
+    }
+
+    /// The objects that are owned by the given object.
+    pub fn members(&self, owner: &ObjectId) -> impl Iterator<Item = &ObjectId> {
+        self.by_owner.get(owner).into_iter().flatten()
+    }
+
+    /// The objects that have a reference to the given object.
+    pub fn references(&self, oid: &ObjectId) -> impl Iterator<Item = &ObjectId> {
+        self.references.get(oid).into_iter().flatten()
+    }
+
+    /// The objects whose `@index` field has the given value, it is `None` if
+    /// the field is not indexed.
+    pub fn by_value(
+        &self,
+        tag: u32,
+        field: FieldIndex,
+        value: &PrimitiveValue,
+    ) -> Option<impl Iterator<Item = &ObjectId>> {
+        let values = self.by_value.get(&(tag, field))?;
+        Some(values.get(&IndexKey::from(value)).into_iter().flatten())
+    }
+
+    fn insert(&mut self, schema: &Schema, oid: &ObjectId, data: &[PrimitiveValue]) {
+        self.visit(schema, data, |set| {
+            set.insert(*oid);
+        });
+    }
+
+    fn remove(&mut self, schema: &Schema, oid: &ObjectId, data: &[PrimitiveValue]) {
+        self.visit(schema, data, |set| {
+            set.remove(oid);
+        });
+    }
+
+    /// Call the function with every set that the object belongs to, the empty
+    /// sets are dropped afterwards.
+    fn visit<F: FnMut(&mut HashSet<ObjectId>)>(
+        &mut self,
+        schema: &Schema,
+        data: &[PrimitiveValue],
+        mut f: F,
+    ) {
+        let tag = match data.first() {
+            Some(PrimitiveValue::U32(tag)) => *tag,
+            _ => return,
+        };
+        visit_entry(&mut self.by_tag, tag, &mut f);
+
+        let st = schema.get_struct(tag);
+        if let (Some(PrimitiveValue::Hash16(owner)), Some(true)) =
+            (data.get(1), st.map(|st| st.owner.is_some()))
+        {
+            visit_entry(&mut self.by_owner, *owner, &mut f);
+        }
+
+        for value in &data[1..] {
+            if let PrimitiveValue::Hash16(target) = value {
+                visit_entry(&mut self.references, *target, &mut f);
+            }
+        }
+
+        let fields = st.map(|st| &st.fields[..]).unwrap_or_default();
+        for (i, field) in fields.iter().enumerate() {
+            if field.index {
+                let index = (i + 1) as FieldIndex;
+                let value = data.get(i + 1).unwrap_or(&PrimitiveValue::Null);
+                let values = self.by_value.entry((tag, index)).or_default();
+                visit_entry(values, IndexKey::from(value), &mut f);
+            }
+        }
+    }
+}
+
+#[inline]
+fn visit_entry<K, F>(map: &mut HashMap<K, HashSet<ObjectId>>, key: K, f: &mut F)
+where
+    K: std::hash::Hash + Eq,
+    F: FnMut(&mut HashSet<ObjectId>),
+{
+    match map.entry(key) {
+        Entry::Occupied(mut entry) => {
+            f(entry.get_mut());
+            if entry.get().is_empty() {
+                entry.remove();
+            }
+        }
+        Entry::Vacant(entry) => {
+            let mut set = HashSet::new();
+            f(&mut set);
+            if !set.is_empty() {
+                entry.insert(set);
+            }
+        }
+    }
+}
+
+impl From<&PrimitiveValue> for IndexKey {
+    fn from(value: &PrimitiveValue) -> Self {
+        match value {
+            PrimitiveValue::Null => IndexKey::Null,
+            PrimitiveValue::True => IndexKey::Bool(true),
+            PrimitiveValue::False => IndexKey::Bool(false),
+            PrimitiveValue::U32(n) => IndexKey::Int(*n as i64),
+            PrimitiveValue::I64(n) => IndexKey::Int(*n),
+            PrimitiveValue::Float(n) if n.fract() == 0.0 && n.abs() < 9.0e15 => {
+                IndexKey::Int(*n as i64)
+            }
+            PrimitiveValue::Float(n) => IndexKey::Float(n.to_bits()),
+            PrimitiveValue::Decimal(d) => {
+                let (mut mantissa, mut scale) = (d.mantissa, d.scale);
+                while scale > 0 && mantissa % 10 == 0 {
+                    mantissa /= 10;
+                    scale -= 1;
+                }
+                if scale == 0 {
+                    IndexKey::Int(mantissa)
+                } else {
+                    IndexKey::Decimal(mantissa, scale)
+                }
+            }
+            PrimitiveValue::Hash16(hash) => IndexKey::Hash(*hash),
+            PrimitiveValue::String(s) => IndexKey::String(s.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::{PatchAtom, Query};
+
+    const ARTIFACT: &str = r#"{
+        "version": 1,
+        "structs": [
+            {
+                "name": "Scene",
+                "id": 0,
+                "types": ["Str"],
+                "layout": [{ "field": "title" }]
+            },
+            {
+                "name": "Box",
+                "id": 1,
+                "owner": { "type": "Scene", "field": "boxes" },
+                "types": ["Hash", { "Decimal": 2 }, "Hash"],
+                "layout": [
+                    { "field": "owner" },
+                    { "field": "size", "attributes": { "index": true } },
+                    { "field": "next" }
+                ]
+            }
+        ],
+        "actions": []
+    }"#;
+
+    fn id(n: u8) -> ObjectId {
+        let mut bytes = [0; 16];
+        bytes[15] = n;
+        Hash16::from(bytes)
+    }
+
+    fn sorted<'a>(iter: impl Iterator<Item = &'a ObjectId>) -> Vec<ObjectId> {
+        let mut result: Vec<ObjectId> = iter.copied().collect();
+        result.sort();
+        result
+    }
+
+    #[test]
+    fn indexes() {
+        let schema = Schema::from_artifact(ARTIFACT).unwrap();
+        let u = PrimitiveValue::U32;
+        let r = PrimitiveValue::Hash16;
+        let d = |s: &str| PrimitiveValue::Decimal(s.parse().unwrap());
+        let insert = |oid: ObjectId, data: Vec<PrimitiveValue>| PatchAtom::Insert {
+            oid,
+            data,
+            version: None,
+        };
+
+        let mut state = State::default();
+        state
+            .perform(vec![
+                insert(id(1), vec![u(0), "A".into()]),
+                insert(id(2), vec![u(0), "B".into()]),
+                insert(id(3), vec![u(1), r(id(1)), d("1.5")]),
+            ])
+            .unwrap();
+        let mut indexes = Indexes::build(&schema, &state);
+        assert_eq!(sorted(indexes.of_tag(0)), vec![id(1), id(2)]);
+        assert_eq!(sorted(indexes.members(&id(1))), vec![id(3)]);
+
+        let inverse = state
+            .perform(vec![
+                insert(id(4), vec![u(1), r(id(1)), d("1.50"), r(id(3))]),
+                PatchAtom::CAS {
+                    oid: id(3),
+                    field: 1,
+                    current: r(id(1)),
+                    target: r(id(2)),
+                },
+            ])
+            .unwrap();
+        indexes.update(&schema, &state, &inverse);
+        assert_eq!(sorted(indexes.of_tag(1)), vec![id(3), id(4)]);
+        assert_eq!(sorted(indexes.members(&id(1))), vec![id(4)]);
+        assert_eq!(sorted(indexes.members(&id(2))), vec![id(3)]);
+        assert_eq!(sorted(indexes.references(&id(3))), vec![id(4)]);
+        assert_eq!(
+            sorted(indexes.by_value(1, 2, &d("1.5")).unwrap()),
+            vec![id(3), id(4)]
+        );
+        assert!(indexes.by_value(1, 3, &PrimitiveValue::Null).is_none());
+
+        // Undo the patch with the inverse delta.
+        indexes.apply_delta(&schema, &mut state, inverse);
+        assert_eq!(sorted(indexes.of_tag(1)), vec![id(3)]);
+        assert_eq!(sorted(indexes.members(&id(1))), vec![id(3)]);
+        assert_eq!(indexes.members(&id(2)).count(), 0);
+        assert_eq!(indexes.references(&id(3)).count(), 0);
+
+        let query = Query::with_indexes(&state, &schema, &indexes);
+        assert_eq!(query.members(&id(1), "boxes").count(), 1);
+        assert_eq!(query.objects_of("Scene").count(), 2);
+        assert_eq!(query.find("Box", "size", &d("1.5")).count(), 1);
+        assert_eq!(query.find("Box", "size", &d("2")).count(), 0);
+        assert_eq!(query.referencing(&id(1)).count(), 1);
+    }
+}
@@ -1,44 +1,152 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use super::{BranchId, CommitHash, Timestamp, UserId};
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(tag = "type", rename_all = "camelCase")]
-pub enum LogEvent {
-    Init {
-        user: UserId,
-        time: Timestamp,
-    },
-    BranchCreated {
-        id: BranchId,
-        head: CommitHash,
-        user: UserId,
-        time: Timestamp,
-    },
-    BranchDeleted {
-        id: BranchId,
-        user: UserId,
-        time: Timestamp,
-    },
-    Committed {
-        branch: BranchId,
-        hash: CommitHash,
-        user: UserId,
-        time: Timestamp,
-    },
-    MergeRequestCreated {
-        source: BranchId,
-        target: Vec<BranchId>,
-        /// When merge request is created a tmp branch is created to generate preview,
-        /// and resolve conflicts if any.
-        merge_branch: BranchId,
-        user: UserId,
-        time: Timestamp,
-    },
-    Merged {
-        source: BranchId,
-        target: Vec<BranchId>,
-        user: UserId,
-        time: Timestamp,
-    },
+/// Declares `LogEvent` along with its two serde representations, the JSON form is
+/// internally tagged by `type` but bincode, which the `LOG` column family uses,
+/// can not deserialize such enums so it gets the externally tagged form.
+macro_rules! log_event {
+    (
+        $(#[$attr:meta])*
+        pub enum LogEvent {
+            $(
+                $(#[$variant_attr:meta])*
+                $variant:ident {
+                    $($(#[$field_attr:meta])* $field:ident: $ty:ty,)*
+                },
+            )*
+        }
+    ) => {
+        $(#[$attr])*
+        pub enum LogEvent {
+            $(
+                $(#[$variant_attr])*
+                $variant {
+                    $($(#[$field_attr])* $field: $ty,)*
+                },
+            )*
+        }
+
+        #[allow(dead_code)]
+        #[derive(Serialize, Deserialize)]
+        #[serde(remote = "LogEvent", tag = "type", rename_all = "camelCase")]
+        enum Tagged {
+            $($variant { $($field: $ty,)* },)*
+        }
+
+        #[allow(dead_code)]
+        #[derive(Serialize, Deserialize)]
+        #[serde(remote = "LogEvent", rename_all = "camelCase")]
+        enum Stored {
+            $($variant { $($field: $ty,)* },)*
+        }
+    };
+}
+
+log_event! {
+    /// An event in the history of a repository.
+    #[derive(Debug)]
+    pub enum LogEvent {
+        Init {
+            user: UserId,
+            time: Timestamp,
+        },
+        BranchCreated {
+            id: BranchId,
+            head: CommitHash,
+            user: UserId,
+            time: Timestamp,
+        },
+        BranchDeleted {
+            id: BranchId,
+            user: UserId,
+            time: Timestamp,
+        },
+        Committed {
+            branch: BranchId,
+            hash: CommitHash,
+            user: UserId,
+            time: Timestamp,
+        },
+        MergeRequestCreated {
+            source: BranchId,
+            target: Vec<BranchId>,
+            /// When merge request is created a tmp branch is created to generate preview,
+            /// and resolve conflicts if any.
+            merge_branch: BranchId,
+            user: UserId,
+            time: Timestamp,
+        },
+        Merged {
+            source: BranchId,
+            target: Vec<BranchId>,
+            user: UserId,
+            time: Timestamp,
+        },
+        /// The commits of a branch were replayed on the head of its parent branch.
+        Rebased {
+            branch: BranchId,
+            head: CommitHash,
+            user: UserId,
+            time: Timestamp,
+        },
+        TagCreated {
+            name: String,
+            commit: CommitHash,
+            user: UserId,
+            time: Timestamp,
+        },
+        TagDeleted {
+            name: String,
+            user: UserId,
+            time: Timestamp,
+        },
+    }
+}
+
+impl Serialize for LogEvent {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if serializer.is_human_readable() {
+            Tagged::serialize(self, serializer)
+        } else {
+            Stored::serialize(self, serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for LogEvent {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        if deserializer.is_human_readable() {
+            Tagged::deserialize(deserializer)
+        } else {
+            Stored::deserialize(deserializer)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::hash::Hash16;
+
+    #[test]
+    fn representations() {
+        let event = LogEvent::TagDeleted {
+            name: "v1".into(),
+            user: UserId(Hash16::MIN),
+            time: 7,
+        };
+        let json = serde_json::to_string(&event).unwrap();
+        assert!(json.starts_with(r#"{"type":"tagDeleted","name":"v1","#));
+        assert!(matches!(
+            bincode::deserialize(&bincode::serialize(&event).unwrap()).unwrap(),
+            LogEvent::TagDeleted { time: 7, .. }
+        ));
+    }
 }
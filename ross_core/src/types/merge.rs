@@ -0,0 +1,232 @@
+use super::{
+    Delta, DeltaEntry, FieldIndex, MergeConflict, MergePolicy, Object, PrimitiveValue, Schema,
+    State,
+};
+use std::collections::BTreeMap;
+
+impl State {
+    /// Replay a delta that was computed on the `base` state on this state, the
+    /// returned delta can be applied to this state using `apply_delta_trusted`.
+    /// A change conflicts when the object was changed in this state since the
+    /// `base`, unless the field has the `lww` merge policy.  
+    /// In the conflicts `origin` is the value in this state and `target` is the
+    /// value that the delta tried to write.
+    pub fn merge_delta(
+        &self,
+        base: &State,
+        delta: &Delta,
+        schema: &Schema,
+    ) -> Result<Delta, Vec<MergeConflict>> {
+        let mut result = Delta::with_capacity(delta.len());
+        let mut conflicts = Vec::new();
+
+        for (oid, entry) in delta {
+            match (entry, self.get(oid)) {
+                (DeltaEntry::Deleted, None) => {}
+                (DeltaEntry::Deleted, Some(obj)) => {
+                    if base.get(oid).map(|o| &o.data) == Some(&obj.data) {
+                        result.insert(*oid, DeltaEntry::Deleted);
+                    } else {
+                        conflicts.push(MergeConflict::WriteDelete {
+                            oid: *oid,
+                            origin: Object {
+                                version: obj.version,
+                                data: obj.data.clone(),
+                            },
+                        });
+                    }
+                }
+                (DeltaEntry::Inserted { data, version }, None) => {
+                    let entry = DeltaEntry::Inserted {
+                        data: data.clone(),
+                        version: *version,
+                    };
+                    result.insert(*oid, entry);
+                }
+                (DeltaEntry::Inserted { data, .. }, Some(obj)) => {
+                    // The object is already here, it's only a conflict if the
+                    // two objects are not the same.
+                    for field in 0..data.len().max(obj.data.len()) {
+                        let field = field as FieldIndex;
+                        let target = data.get(field as usize).unwrap_or(&PrimitiveValue::Null);
+                        if obj.get(field) != target {
+                            conflicts.push(MergeConflict::WriteWrite {
+                                oid: *oid,
+                                field,
+                                origin: obj.get(field).clone(),
+                                target: target.clone(),
+                            });
+                        }
+                    }
+                }
+                (DeltaEntry::Updated { changes, .. }, None) => {
+                    let original = base.get(oid);
+                    for (field, target) in changes {
+                        conflicts.push(MergeConflict::DeleteWrite {
+                            oid: *oid,
+                            field: *field,
+                            origin: original
+                                .map_or(PrimitiveValue::Null, |o| o.get(*field).clone()),
+                            target: target.clone(),
+                        });
+                    }
+                }
+                (DeltaEntry::Updated { changes, .. }, Some(obj)) => {
+                    let original = base.get(oid);
+                    let mut applied = BTreeMap::new();
+                    for (field, target) in changes {
+                        let current = obj.get(*field);
+                        if current == target {
+                            continue;
+                        }
+                        let origin = original.map(|o| o.get(*field));
+                        if Some(current) == origin
+                            || (Some(target) != origin
+                                && schema.merge_policy(&obj.data, *field) == MergePolicy::Lww)
+                        {
+                            applied.insert(*field, target.clone());
+                        } else {
+                            conflicts.push(MergeConflict::WriteWrite {
+                                oid: *oid,
+                                field: *field,
+                                origin: current.clone(),
+                                target: target.clone(),
+                            });
+                        }
+                    }
+                    if !applied.is_empty() {
+                        // Each applied field bumps the version once like a `CAS`
+                        // does, the version of the delta is negative on reverts.
+                        let entry = DeltaEntry::Updated {
+                            version: applied.len() as i16,
+                            changes: applied,
+                        };
+                        result.insert(*oid, entry);
+                    }
+                }
+            }
+        }
+
+        if conflicts.is_empty() {
+            Ok(result)
+        } else {
+            Err(conflicts)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::{ObjectId, PatchAtom, PatchConflict};
+    use crate::utils::hash::Hash16;
+
+    const ARTIFACT: &str = r#"{
+        "version": 1,
+        "structs": [
+            {
+                "name": "Box",
+                "id": 0,
+                "types": ["U32", "U32"],
+                "layout": [
+                    { "field": "size" },
+                    { "field": "color", "attributes": { "merge": "lww" } }
+                ]
+            }
+        ],
+        "actions": []
+    }"#;
+
+    fn id(n: u8) -> ObjectId {
+        let mut bytes = [0; 16];
+        bytes[15] = n;
+        Hash16::from(bytes)
+    }
+
+    fn cas(oid: ObjectId, field: u8, current: u32, target: u32) -> PatchAtom {
+        PatchAtom::CAS {
+            oid,
+            field,
+            current: PrimitiveValue::U32(current),
+            target: PrimitiveValue::U32(target),
+        }
+    }
+
+    fn state(patch: Vec<PatchAtom>) -> State {
+        let u = PrimitiveValue::U32;
+        let mut state = State::default();
+        state
+            .perform(vec![
+                PatchAtom::Insert {
+                    oid: id(1),
+                    data: vec![u(0), u(1), u(1)],
+                    version: None,
+                },
+                PatchAtom::Insert {
+                    oid: id(2),
+                    data: vec![u(0), u(2), u(2)],
+                    version: None,
+                },
+            ])
+            .unwrap();
+        state.perform(patch).unwrap();
+        state
+    }
+
+    #[test]
+    fn merge_delta() {
+        let schema = Schema::from_artifact(ARTIFACT).unwrap();
+        let base = state(vec![]);
+        let change = state(vec![cas(id(1), 1, 1, 5), cas(id(1), 2, 1, 5)]);
+        let delta = base.diff(&change);
+
+        // Nothing has changed on the target, so the delta is applied as is.
+        let mut target = state(vec![cas(id(2), 1, 2, 3)]);
+        let result = target.merge_delta(&base, &delta, &schema).unwrap();
+        target.apply_delta_trusted(result);
+        assert_eq!(target.get(&id(1)).unwrap().get(1), &PrimitiveValue::U32(5));
+
+        // The `size` was changed, but `color` is a last-writer-wins field.
+        let target = state(vec![cas(id(1), 1, 1, 3), cas(id(1), 2, 1, 3)]);
+        let conflicts = target.merge_delta(&base, &delta, &schema).unwrap_err();
+        assert_eq!(conflicts.len(), 1);
+        assert!(matches!(
+            conflicts[0],
+            MergeConflict::WriteWrite { field: 1, .. }
+        ));
+
+        // A requirement on a last-writer-wins field still conflicts.
+        let mut target = state(vec![cas(id(1), 2, 1, 3)]);
+        let conflicts = target
+            .perform_with_schema(vec![cas(id(1), 2, 1, 1)], &schema)
+            .unwrap_err();
+        assert!(matches!(
+            conflicts[0],
+            PatchConflict::WriteWrite { field: 2, .. }
+        ));
+        assert_eq!(target.get(&id(1)).unwrap().get(2), &PrimitiveValue::U32(3));
+        target
+            .perform_with_schema(vec![cas(id(1), 2, 1, 4)], &schema)
+            .unwrap();
+        assert_eq!(target.get(&id(1)).unwrap().get(2), &PrimitiveValue::U32(4));
+
+        // Reverting the change.
+        let inverse = change.diff(&base);
+        let mut target = state(vec![cas(id(1), 1, 1, 5), cas(id(2), 1, 2, 3)]);
+        let result = target.merge_delta(&change, &inverse, &schema).unwrap();
+        target.apply_delta_trusted(result);
+        assert_eq!(target.get(&id(1)).unwrap().get(1), &PrimitiveValue::U32(1));
+        assert_eq!(target.get(&id(1)).unwrap().get(2), &PrimitiveValue::U32(1));
+        assert_eq!(target.get(&id(2)).unwrap().get(1), &PrimitiveValue::U32(3));
+
+        // The deleted object was changed on the target.
+        let deleted = state(vec![PatchAtom::Delete {
+            oid: id(2),
+            version: 0,
+        }]);
+        let delta = base.diff(&deleted);
+        let target = state(vec![cas(id(2), 1, 2, 3)]);
+        let conflicts = target.merge_delta(&base, &delta, &schema).unwrap_err();
+        assert!(matches!(conflicts[0], MergeConflict::WriteDelete { .. }));
+    }
+}
@@ -1,16 +1,29 @@
+mod bundle;
 mod conflict;
 mod delta;
+mod diff;
+mod document;
+mod index;
 mod log;
+mod merge;
 mod patch;
+mod query;
+mod schema;
 mod snapshot;
 mod state;
 mod value;
 mod vcs;
 
+pub use bundle::*;
 pub use conflict::*;
 pub use delta::*;
+pub use diff::*;
+pub use document::*;
+pub use index::*;
 pub use log::*;
 pub use patch::*;
+pub use query::*;
+pub use schema::*;
 pub use snapshot::*;
 pub use state::*;
 pub use value::*;
@@ -0,0 +1,292 @@
+use super::{
+    FieldIndex, Indexes, Object, ObjectId, PrimitiveType, PrimitiveValue, Schema, State,
+    StructSchema,
+};
+
+/// Read access to a state by the names of the structs and fields in the schema,
+/// the indexes are used when they are provided and otherwise the state is
+/// scanned.
+#[derive(Clone, Copy)]
+pub struct Query<'a> {
+    state: &'a State,
+    schema: &'a Schema,
+    indexes: Option<&'a Indexes>,
+}
+
+type Ids<'a> = Box<dyn Iterator<Item = &'a ObjectId> + 'a>;
+
+/// An object along with the schema of its struct.
+#[derive(Debug, Clone, Copy)]
+pub struct TypedObject<'a> {
+    pub id: ObjectId,
+    pub object: &'a Object,
+    pub schema: &'a StructSchema,
+}
+
+impl<'a> Query<'a> {
+    #[inline]
+    pub fn new(state: &'a State, schema: &'a Schema) -> Self {
+        Query {
+            state,
+            schema,
+            indexes: None,
+        }
+    }
+
+    /// Query the state using the indexes, they must be in sync with the state.
+    #[inline]
+    pub fn with_indexes(state: &'a State, schema: &'a Schema, indexes: &'a Indexes) -> Self {
+        Query {
+            state,
+            schema,
+            indexes: Some(indexes),
+        }
+    }
+
+    #[inline]
+    pub fn state(&self) -> &'a State {
+        self.state
+    }
+
+    /// Returns the object with the given id, objects of the structs that are
+    /// not in the schema are not returned.
+    pub fn get(&self, oid: &ObjectId) -> Option<TypedObject<'a>> {
+        self.typed(oid, self.state.get(oid)?)
+    }
+
+    /// Iterate over the objects of a struct by its qualified name.
+    pub fn objects_of(&self, name: &str) -> impl Iterator<Item = TypedObject<'a>> + 'a {
+        let state = self.state;
+        let ids: Ids<'a> = match (self.schema.find(name), self.indexes) {
+            (None, _) => Box::new(std::iter::empty()),
+            (Some(st), Some(indexes)) => Box::new(indexes.of_tag(st.tag)),
+            (Some(st), None) => Box::new(state.objects_of(st.tag).map(|(id, _)| id)),
+        };
+        self.typed_iter(ids)
+    }
+
+    /// Iterate over the members of an object. (e.g the boxes of a scene)
+    pub fn members(
+        &self,
+        owner: &ObjectId,
+        field: &str,
+    ) -> impl Iterator<Item = TypedObject<'a>> + 'a {
+        let state = self.state;
+        let member = self
+            .get(owner)
+            .and_then(|o| self.schema.find_member(&o.schema.name, field));
+        let ids: Ids<'a> = match (member, self.indexes) {
+            (None, _) => Box::new(std::iter::empty()),
+            (Some(st), Some(indexes)) => Box::new(
+                indexes
+                    .members(owner)
+                    .filter(move |id| state.get(id).and_then(Object::tag) == Some(st.tag)),
+            ),
+            (Some(st), None) => Box::new(state.members(owner, st.tag).map(|(id, _)| id)),
+        };
+        self.typed_iter(ids)
+    }
+
+    /// Iterate over the objects that have a reference to the given object.
+    pub fn referencing(&self, oid: &ObjectId) -> impl Iterator<Item = TypedObject<'a>> + 'a {
+        let target = PrimitiveValue::Hash16(*oid);
+        let ids: Ids<'a> = match self.indexes {
+            Some(indexes) => Box::new(indexes.references(oid)),
+            None => Box::new(
+                self.state
+                    .iter()
+                    .filter(move |(_, obj)| obj.data[1..].contains(&target))
+                    .map(|(id, _)| id),
+            ),
+        };
+        self.typed_iter(ids)
+    }
+
+    /// Iterate over the objects of a struct whose field has the given value,
+    /// the index of the field is used if it has the `@index` attribute.
+    pub fn find(
+        &self,
+        name: &str,
+        field: &str,
+        value: &PrimitiveValue,
+    ) -> impl Iterator<Item = TypedObject<'a>> + 'a {
+        let state = self.state;
+        let st = self.schema.find(name);
+        let index = st.and_then(|st| Some((st.tag, st.field_index(field)?)));
+        let indexed = match (index, self.indexes) {
+            (Some((tag, field)), Some(indexes)) => indexes.by_value(tag, field, value),
+            _ => None,
+        };
+        let ids: Ids<'a> = match (index, indexed) {
+            (None, _) => Box::new(std::iter::empty()),
+            (Some(_), Some(ids)) => Box::new(ids),
+            (Some((tag, field)), None) => {
+                let value = value.clone();
+                Box::new(
+                    state
+                        .objects_of(tag)
+                        .filter(move |(_, obj)| obj.get(field) == &value)
+                        .map(|(id, _)| id),
+                )
+            }
+        };
+        self.typed_iter(ids)
+    }
+
+    /// Returns the object that a reference field of the given object points to.
+    pub fn resolve(&self, object: &TypedObject<'a>, field: &str) -> Option<TypedObject<'a>> {
+        let index = object.schema.field_index(field)?;
+        let (id, target) = self.state.resolve(object.object, index)?;
+        self.typed(id, target)
+    }
+
+    #[inline]
+    fn typed_iter(&self, ids: Ids<'a>) -> impl Iterator<Item = TypedObject<'a>> + 'a {
+        let query = *self;
+        ids.filter_map(move |id| query.get(id))
+    }
+
+    #[inline]
+    fn typed(&self, oid: &ObjectId, object: &'a Object) -> Option<TypedObject<'a>> {
+        Some(TypedObject {
+            id: *oid,
+            object,
+            schema: self.schema.get_struct(object.tag()?)?,
+        })
+    }
+}
+
+impl<'a> TypedObject<'a> {
+    /// Qualified name of the struct of this object.
+    #[inline]
+    pub fn name(&self) -> &'a str {
+        &self.schema.name
+    }
+
+    /// Returns the value of a field by its name, fields of the inlined structs
+    /// are separated by dots. (e.g `color.r`)
+    pub fn get(&self, field: &str) -> Option<&'a PrimitiveValue> {
+        self.schema.field_index(field).map(|i| self.object.get(i))
+    }
+
+    /// Returns the type of a field by its name.
+    pub fn field_type(&self, field: &str) -> Option<PrimitiveType> {
+        let index = self.schema.field_index(field)?;
+        Some(self.schema.fields[index as usize - 1].ty)
+    }
+
+    /// The owner of this object, if its struct is owned.
+    pub fn owner(&self) -> Option<ObjectId> {
+        match (&self.schema.owner, self.object.get(1)) {
+            (Some(_), PrimitiveValue::Hash16(oid)) => Some(*oid),
+            _ => None,
+        }
+    }
+
+    /// Iterate over the name and the value of every field.
+    pub fn fields(&self) -> impl Iterator<Item = (&'a str, &'a PrimitiveValue)> + 'a {
+        let object = self.object;
+        self.schema
+            .fields
+            .iter()
+            .enumerate()
+            .map(move |(i, f)| (&f.name[..], object.get((i + 1) as FieldIndex)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::PatchAtom;
+    use crate::utils::hash::Hash16;
+
+    const ARTIFACT: &str = r#"{
+        "version": 1,
+        "structs": [
+            {
+                "name": "Scene",
+                "id": 0,
+                "types": ["Str"],
+                "layout": [{ "field": "title" }]
+            },
+            {
+                "name": "Box",
+                "id": 1,
+                "owner": { "type": "Scene", "field": "boxes" },
+                "types": ["Hash", "U32", "U32", "Hash"],
+                "layout": [
+                    { "field": "owner" },
+                    { "field": "size.w" },
+                    { "field": "size.h" },
+                    { "field": "next" }
+                ]
+            }
+        ],
+        "actions": []
+    }"#;
+
+    fn id(n: u8) -> ObjectId {
+        let mut bytes = [0; 16];
+        bytes[15] = n;
+        Hash16::from(bytes)
+    }
+
+    fn insert(oid: ObjectId, data: Vec<PrimitiveValue>) -> PatchAtom {
+        PatchAtom::Insert {
+            oid,
+            data,
+            version: None,
+        }
+    }
+
+    #[test]
+    fn query() {
+        let schema = Schema::from_artifact(ARTIFACT).unwrap();
+        let mut state = State::default();
+        let u = PrimitiveValue::U32;
+        let r = PrimitiveValue::Hash16;
+        state
+            .perform(vec![
+                insert(id(1), vec![u(0), "A".into()]),
+                insert(id(2), vec![u(0), "B".into()]),
+                insert(id(3), vec![u(1), r(id(1)), u(2), u(3), r(id(4))]),
+                insert(id(4), vec![u(1), r(id(1)), u(4), u(5)]),
+                insert(id(5), vec![u(1), r(id(2)), u(6), u(7)]),
+            ])
+            .unwrap();
+
+        assert_eq!(state.len(), 5);
+        assert_eq!(state.objects_of(1).count(), 3);
+        assert_eq!(state.members(&id(1), 1).count(), 2);
+        assert_eq!(state.get(&id(3)).unwrap().get(4), &r(id(4)));
+        assert_eq!(state.get(&id(4)).unwrap().get(4), &PrimitiveValue::Null);
+
+        let query = Query::new(&state, &schema);
+        let b = query.get(&id(3)).unwrap();
+        assert_eq!(b.name(), "Box");
+        assert_eq!(b.get("size.h"), Some(&u(3)));
+        assert_eq!(b.get("size"), None);
+        assert_eq!(b.field_type("next"), Some(PrimitiveType::Hash));
+        assert_eq!(b.owner(), Some(id(1)));
+        assert_eq!(
+            b.fields().map(|(name, _)| name).collect::<Vec<_>>(),
+            vec!["owner", "size.w", "size.h", "next"]
+        );
+
+        let next = query.resolve(&b, "next").unwrap();
+        assert_eq!(next.id, id(4));
+        assert!(query.resolve(&next, "next").is_none());
+        assert_eq!(
+            query.resolve(&b, "owner").unwrap().get("title"),
+            Some(&"A".into())
+        );
+
+        let mut boxes: Vec<ObjectId> = query.members(&id(1), "boxes").map(|o| o.id).collect();
+        boxes.sort();
+        assert_eq!(boxes, vec![id(3), id(4)]);
+        assert_eq!(query.members(&id(1), "circles").count(), 0);
+        assert_eq!(query.members(&id(3), "boxes").count(), 0);
+        assert_eq!(query.objects_of("Scene").count(), 2);
+        assert_eq!(query.objects_of("Circle").count(), 0);
+    }
+}
@@ -0,0 +1,468 @@
+//! Constraints of the objects, they are loaded from the schema artifact that is
+//! generated by the compiler. (`ross_compiler gen --target schema`)
+use super::{Decimal, FieldIndex, PrimitiveType, PrimitiveValue};
+use crate::utils::hash::Hash16;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+
+/// The latest version of the artifact format that can be read.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Constraints of every struct, indexed by the struct tag. (the first value of
+/// the data-vector)
+#[derive(Debug, Default, Clone)]
+pub struct Schema {
+    structs: HashMap<u32, StructSchema>,
+}
+
+#[derive(Debug, Clone)]
+pub struct StructSchema {
+    pub tag: u32,
+    /// Qualified name of the struct. (e.g `shapes.Circle`)
+    pub name: String,
+    /// The owner struct and the name of the member field in the owner.
+    pub owner: Option<(String, String)>,
+    /// Fields of the flattened data-vector, without the tag, so the field at
+    /// index `i` is stored at `data[i + 1]`.
+    pub fields: Vec<FieldSchema>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FieldSchema {
+    /// Path of the field, fields of the inlined structs are separated by dots.
+    pub name: String,
+    pub ty: PrimitiveType,
+    pub default: Option<PrimitiveValue>,
+    pub min: Option<PrimitiveValue>,
+    pub max: Option<PrimitiveValue>,
+    pub max_len: Option<usize>,
+    pub merge: MergePolicy,
+    pub index: bool,
+}
+
+/// How concurrent writes to the same field are resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MergePolicy {
+    /// A write conflicts with any other write that it did not see.
+    Cas,
+    /// The last write wins.
+    Lww,
+}
+
+// The `#[default]` variant attribute needs Rust 1.62, the crate builds on older
+// toolchains.
+#[allow(clippy::derivable_impls)]
+impl Default for MergePolicy {
+    fn default() -> Self {
+        MergePolicy::Cas
+    }
+}
+
+#[derive(Debug)]
+pub enum SchemaError {
+    InvalidFormat(String),
+    UnsupportedVersion(u32),
+    /// A default value or a bound does not match the type of its field.
+    InvalidValue(String),
+}
+
+impl std::error::Error for SchemaError {}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaError::InvalidFormat(e) => write!(f, "Invalid schema artifact: {}", e),
+            SchemaError::UnsupportedVersion(v) => {
+                write!(f, "Unsupported schema artifact version {}.", v)
+            }
+            SchemaError::InvalidValue(field) => {
+                write!(f, "Invalid attribute value for the field '{}'.", field)
+            }
+        }
+    }
+}
+
+/// The parts of the artifact that are needed to build the constraints.
+#[derive(Deserialize)]
+struct Artifact {
+    version: u32,
+    structs: Vec<ArtifactStruct>,
+}
+
+#[derive(Deserialize)]
+struct ArtifactStruct {
+    name: String,
+    id: u32,
+    owner: Option<ArtifactOwner>,
+    types: Vec<PrimitiveType>,
+    layout: Vec<ArtifactField>,
+}
+
+#[derive(Deserialize)]
+struct ArtifactOwner {
+    #[serde(rename = "type")]
+    ty: String,
+    field: String,
+}
+
+#[derive(Deserialize)]
+struct ArtifactField {
+    field: String,
+    #[serde(default)]
+    attributes: ArtifactAttributes,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct ArtifactAttributes {
+    default: Option<serde_json::Value>,
+    min: Option<String>,
+    max: Option<String>,
+    maxlen: Option<usize>,
+    merge: Option<MergePolicy>,
+    index: bool,
+}
+
+impl Schema {
+    /// Parse the JSON schema artifact.
+    pub fn from_artifact(source: &str) -> Result<Self, SchemaError> {
+        let artifact: Artifact =
+            serde_json::from_str(source).map_err(|e| SchemaError::InvalidFormat(e.to_string()))?;
+        if artifact.version > SCHEMA_VERSION {
+            return Err(SchemaError::UnsupportedVersion(artifact.version));
+        }
+
+        let mut structs = HashMap::with_capacity(artifact.structs.len());
+        for st in artifact.structs {
+            if st.types.len() != st.layout.len() {
+                return Err(SchemaError::InvalidFormat(format!(
+                    "Layout of '{}' does not match its types.",
+                    st.name
+                )));
+            }
+
+            let mut fields = Vec::with_capacity(st.types.len());
+            for (ty, field) in st.types.into_iter().zip(st.layout) {
+                fields.push(FieldSchema::new(field, ty)?);
+            }
+            structs.insert(
+                st.id,
+                StructSchema {
+                    tag: st.id,
+                    name: st.name,
+                    owner: st.owner.map(|o| (o.ty, o.field)),
+                    fields,
+                },
+            );
+        }
+
+        Ok(Schema { structs })
+    }
+
+    /// Returns the schema of the struct that the data-vector belongs to.
+    #[inline]
+    pub fn get(&self, data: &[PrimitiveValue]) -> Option<&StructSchema> {
+        match data.first() {
+            Some(PrimitiveValue::U32(tag)) => self.structs.get(tag),
+            _ => None,
+        }
+    }
+
+    /// Returns the schema of the struct with the given tag.
+    #[inline]
+    pub fn get_struct(&self, tag: u32) -> Option<&StructSchema> {
+        self.structs.get(&tag)
+    }
+
+    /// Find a struct by its qualified name.
+    pub fn find(&self, name: &str) -> Option<&StructSchema> {
+        self.structs.values().find(|st| st.name == name)
+    }
+
+    /// Find the struct whose objects are the members of the given field of the
+    /// owner struct. (e.g `Box` for `Scene.boxes`)
+    pub fn find_member(&self, owner: &str, field: &str) -> Option<&StructSchema> {
+        self.structs.values().find(|st| match &st.owner {
+            Some((o, f)) => o == owner && f == field,
+            None => false,
+        })
+    }
+
+    #[inline]
+    fn field(&self, data: &[PrimitiveValue], field: FieldIndex) -> Option<&FieldSchema> {
+        let index = (field as usize).checked_sub(1)?;
+        self.get(data)?.fields.get(index)
+    }
+
+    /// Fill the missing fields of a new object with their default values, and
+    /// check the constraints of the rest. On failure the index of the first
+    /// invalid field is returned.
+    ///
+    /// Objects of the structs that are not in the schema are not checked.
+    pub fn prepare_insert(&self, data: &mut Vec<PrimitiveValue>) -> Result<(), FieldIndex> {
+        let st = match self.get(data) {
+            Some(st) => st,
+            None => return Ok(()),
+        };
+
+        for (i, field) in st.fields.iter().enumerate() {
+            let index = i + 1;
+            if index >= data.len() {
+                data.push(PrimitiveValue::Null);
+            }
+            if let (PrimitiveValue::Null, Some(default)) = (&data[index], &field.default) {
+                data[index] = default.clone();
+            }
+            if !field.accepts(&data[index]) {
+                return Err(index as FieldIndex);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether the value can be written to the given field of the object.
+    #[inline]
+    pub fn accepts(
+        &self,
+        data: &[PrimitiveValue],
+        field: FieldIndex,
+        value: &PrimitiveValue,
+    ) -> bool {
+        match self.field(data, field) {
+            Some(f) => f.accepts(value),
+            None => true,
+        }
+    }
+
+    #[inline]
+    pub fn merge_policy(&self, data: &[PrimitiveValue], field: FieldIndex) -> MergePolicy {
+        self.field(data, field)
+            .map_or(MergePolicy::Cas, |f| f.merge)
+    }
+}
+
+impl StructSchema {
+    /// Index of a field in the data-vector, fields of the inlined structs are
+    /// separated by dots. (e.g `color.r`)
+    pub fn field_index(&self, name: &str) -> Option<FieldIndex> {
+        let index = self.fields.iter().position(|f| f.name == name)?;
+        Some((index + 1) as FieldIndex)
+    }
+}
+
+impl FieldSchema {
+    fn new(field: ArtifactField, ty: PrimitiveType) -> Result<Self, SchemaError> {
+        let invalid = || SchemaError::InvalidValue(field.field.clone());
+        let attributes = &field.attributes;
+        let default = match &attributes.default {
+            None | Some(serde_json::Value::Null) => None,
+            Some(serde_json::Value::Bool(b)) => Some(PrimitiveValue::from(*b)),
+            Some(serde_json::Value::String(s)) => Some(parse_value(ty, s).ok_or_else(invalid)?),
+            Some(_) => return Err(invalid()),
+        };
+        let bound = |bound: &Option<String>| match bound {
+            Some(n) => parse_value(ty, n).map(Some).ok_or_else(invalid),
+            None => Ok(None),
+        };
+
+        Ok(FieldSchema {
+            ty,
+            default,
+            min: bound(&attributes.min)?,
+            max: bound(&attributes.max)?,
+            max_len: attributes.maxlen,
+            merge: attributes.merge.unwrap_or_default(),
+            index: attributes.index,
+            name: field.field,
+        })
+    }
+
+    /// Whether the value is of the type of this field and satisfies its
+    /// constraints, null is always accepted since it means the field is not set.
+    pub fn accepts(&self, value: &PrimitiveValue) -> bool {
+        let typed = match (self.ty, value) {
+            (_, PrimitiveValue::Null) => return true,
+            // A string of 32 hex digits is read as a hash.
+            (PrimitiveType::Str, PrimitiveValue::Hash16(_)) => true,
+            (ty, value) => value.is_of(&ty),
+        };
+        if !typed {
+            return false;
+        }
+
+        let in_bound = |bound: &Option<PrimitiveValue>, rejected: Ordering| match bound {
+            Some(bound) => matches!(value.cmp_number(bound), Some(o) if o != rejected),
+            None => true,
+        };
+        let fits = match (self.max_len, value) {
+            (Some(len), PrimitiveValue::String(s)) => s.chars().count() <= len,
+            (Some(len), PrimitiveValue::Hash16(_)) => 32 <= len,
+            (Some(_), _) => false,
+            (None, _) => true,
+        };
+
+        in_bound(&self.min, Ordering::Less) && in_bound(&self.max, Ordering::Greater) && fits
+    }
+}
+
+/// Parse a value of the given type from the way it is written in the artifact.
+fn parse_value(ty: PrimitiveType, value: &str) -> Option<PrimitiveValue> {
+    Some(match ty {
+        PrimitiveType::Str => value.into(),
+        PrimitiveType::Hash => value.parse::<Hash16>().ok()?.into(),
+        PrimitiveType::U32 => value.parse::<u32>().ok()?.into(),
+        PrimitiveType::I64 => value.parse::<i64>().ok()?.into(),
+        PrimitiveType::F64 => value.parse::<f64>().ok()?.into(),
+        PrimitiveType::Num => match value.parse::<u32>() {
+            Ok(n) => n.into(),
+            Err(_) => value.parse::<f64>().ok()?.into(),
+        },
+        PrimitiveType::Decimal(scale) => {
+            let d = value.parse::<Decimal>().ok()?;
+            if d.scale > scale {
+                return None;
+            }
+            d.into()
+        }
+        PrimitiveType::Null | PrimitiveType::Bool => return None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::{PatchAtom, PatchConflict, State};
+
+    const ARTIFACT: &str = r#"{
+        "version": 1,
+        "structs": [{
+            "name": "Item",
+            "id": 7,
+            "fields": [],
+            "members": [],
+            "types": ["Str", { "Decimal": 2 }, "U32"],
+            "layout": [
+                { "field": "title", "attributes": { "default": "none", "maxlen": 5 } },
+                { "field": "price", "attributes": { "min": "0", "max": "99.99" } },
+                { "field": "count", "attributes": { "merge": "lww", "index": true } }
+            ]
+        }],
+        "actions": []
+    }"#;
+
+    fn schema() -> Schema {
+        Schema::from_artifact(ARTIFACT).unwrap()
+    }
+
+    fn u(n: u32) -> PrimitiveValue {
+        PrimitiveValue::U32(n)
+    }
+
+    fn d(s: &str) -> PrimitiveValue {
+        PrimitiveValue::Decimal(s.parse().unwrap())
+    }
+
+    #[test]
+    fn constraints() {
+        let schema = schema();
+        let st = schema.get(&[PrimitiveValue::U32(7)]).unwrap();
+        assert_eq!(st.name, "Item");
+        assert_eq!(st.fields[2].merge, MergePolicy::Lww);
+        assert!(st.fields[2].index);
+
+        let mut data = vec![PrimitiveValue::U32(7), PrimitiveValue::Null, d("1.5")];
+        assert_eq!(schema.prepare_insert(&mut data), Ok(()));
+        assert_eq!(
+            data,
+            vec![
+                PrimitiveValue::U32(7),
+                PrimitiveValue::String("none".into()),
+                d("1.5"),
+                PrimitiveValue::Null
+            ]
+        );
+
+        assert!(schema.accepts(&data, 1, &"short".into()));
+        assert!(!schema.accepts(&data, 1, &"too long".into()));
+        assert!(!schema.accepts(&data, 1, &PrimitiveValue::U32(1)));
+        assert!(schema.accepts(&data, 2, &d("99.99")));
+        assert!(!schema.accepts(&data, 2, &d("100")));
+        assert!(!schema.accepts(&data, 2, &d("-0.01")));
+        assert!(schema.accepts(&data, 2, &PrimitiveValue::Null));
+        assert!(!schema.accepts(&data, 2, &"1.5".into()));
+        assert!(!schema.accepts(&data, 2, &d("1.555")));
+        assert!(schema.accepts(&data, 3, &u(5)));
+        assert!(!schema.accepts(&data, 3, &"5".into()));
+        assert!(!schema.accepts(&data, 3, &PrimitiveValue::I64(-5)));
+
+        let mut data = vec![PrimitiveValue::U32(7), PrimitiveValue::Null, d("100")];
+        assert_eq!(schema.prepare_insert(&mut data), Err(2));
+
+        // Unknown structs are not checked.
+        let mut data = vec![PrimitiveValue::U32(8), d("-1")];
+        assert_eq!(schema.prepare_insert(&mut data), Ok(()));
+
+        assert!(matches!(
+            Schema::from_artifact(&ARTIFACT.replace("\"version\": 1", "\"version\": 9")),
+            Err(SchemaError::UnsupportedVersion(9))
+        ));
+        assert!(matches!(
+            Schema::from_artifact(&ARTIFACT.replace("\"99.99\"", "\"0.001\"")),
+            Err(SchemaError::InvalidValue(_))
+        ));
+    }
+
+    #[test]
+    fn perform() {
+        let schema = schema();
+        let oid = Hash16::MAX;
+        let mut state = State::default();
+        let insert = |data: Vec<PrimitiveValue>| PatchAtom::Insert {
+            oid,
+            data,
+            version: None,
+        };
+        let cas =
+            |field: FieldIndex, current: PrimitiveValue, target: PrimitiveValue| PatchAtom::CAS {
+                oid,
+                field,
+                current,
+                target,
+            };
+
+        let conflicts = state
+            .perform_with_schema(vec![insert(vec![u(7), "x".into(), d("-1")])], &schema)
+            .unwrap_err();
+        assert!(matches!(
+            conflicts[..],
+            [PatchConflict::InvalidValue { field: 2, .. }]
+        ));
+
+        state
+            .perform_with_schema(vec![insert(vec![u(7), "x".into(), d("1")])], &schema)
+            .unwrap();
+        assert!(state
+            .perform_with_schema(vec![cas(2, d("1"), d("100"))], &schema)
+            .is_err());
+
+        // `count` is a last-write-wins field, so the stale write is accepted.
+        state
+            .perform_with_schema(vec![cas(3, u(0), u(5))], &schema)
+            .unwrap();
+        state
+            .perform_with_schema(vec![cas(3, u(0), u(6))], &schema)
+            .unwrap();
+        let conflicts = state
+            .perform_with_schema(vec![cas(1, "y".into(), "z".into())], &schema)
+            .unwrap_err();
+        assert!(matches!(
+            conflicts[..],
+            [PatchConflict::WriteWrite { field: 1, .. }]
+        ));
+        assert!(state.perform(vec![cas(3, u(0), u(7))]).is_err());
+    }
+}
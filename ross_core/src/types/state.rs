@@ -1,4 +1,4 @@
-use super::{Delta, DeltaEntry, PatchAtom, PatchConflict, PrimitiveValue};
+use super::{Delta, DeltaEntry, MergePolicy, PatchAtom, PatchConflict, PrimitiveValue, Schema};
 use crate::utils::hash::Hash16;
 use serde::{Deserialize, Serialize};
 use std::collections::{hash_map::Entry, BTreeMap, HashMap};
@@ -31,7 +31,73 @@ impl Default for State {
     }
 }
 
+impl Object {
+    /// The struct tag of the object, it is the first value of the data-vector.
+    #[inline]
+    pub fn tag(&self) -> Option<u32> {
+        match self.data.first() {
+            Some(PrimitiveValue::U32(tag)) => Some(*tag),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of a field, the fields that are not stored are null.
+    #[inline]
+    pub fn get(&self, field: FieldIndex) -> &PrimitiveValue {
+        get_field(&self.data, field)
+    }
+}
+
 impl State {
+    /// Returns the object with the given id.
+    #[inline]
+    pub fn get(&self, oid: &ObjectId) -> Option<&Object> {
+        self.objects.get(oid)
+    }
+
+    /// Number of the objects in this state.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    /// Iterate over all of the objects in an arbitrary order.
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = (&ObjectId, &Object)> {
+        self.objects.iter()
+    }
+
+    /// Iterate over the objects of the struct with the given tag.
+    pub fn objects_of(&self, tag: u32) -> impl Iterator<Item = (&ObjectId, &Object)> {
+        self.iter().filter(move |(_, obj)| obj.tag() == Some(tag))
+    }
+
+    /// Iterate over the objects of an owned struct that belong to the given
+    /// owner, the owner of an object is always the first field after the tag.
+    pub fn members<'a>(
+        &'a self,
+        owner: &ObjectId,
+        tag: u32,
+    ) -> impl Iterator<Item = (&'a ObjectId, &'a Object)> {
+        let owner = PrimitiveValue::Hash16(*owner);
+        self.objects_of(tag)
+            .filter(move |(_, obj)| obj.get(1) == &owner)
+    }
+
+    /// Returns the object that a reference field points to.
+    #[inline]
+    pub fn resolve(&self, object: &Object, field: FieldIndex) -> Option<(&ObjectId, &Object)> {
+        match object.get(field) {
+            PrimitiveValue::Hash16(oid) => self.objects.get_key_value(oid),
+            _ => None,
+        }
+    }
+
     /// Apply a trusted diff to turn this state into the next.
     ///
     /// # Panics
@@ -60,6 +126,41 @@ impl State {
         }
     }
 
+    /// Compute the delta that turns this state into the target state.
+    pub fn diff(&self, target: &State) -> Delta {
+        let mut delta = Delta::new();
+        for oid in self.objects.keys() {
+            if !target.objects.contains_key(oid) {
+                delta.insert(*oid, DeltaEntry::Deleted);
+            }
+        }
+        for (oid, obj) in &target.objects {
+            let entry = match self.objects.get(oid) {
+                None => DeltaEntry::Inserted {
+                    data: obj.data.clone(),
+                    version: obj.version,
+                },
+                Some(current) => {
+                    let len = current.data.len().max(obj.data.len());
+                    let changes: BTreeMap<FieldIndex, PrimitiveValue> = (0..len)
+                        .map(|i| i as FieldIndex)
+                        .filter(|i| current.get(*i) != obj.get(*i))
+                        .map(|i| (i, obj.get(i).clone()))
+                        .collect();
+                    if changes.is_empty() && current.version == obj.version {
+                        continue;
+                    }
+                    DeltaEntry::Updated {
+                        version: (obj.version as i32 - current.version as i32) as i16,
+                        changes,
+                    }
+                }
+            };
+            delta.insert(*oid, entry);
+        }
+        delta
+    }
+
     /// Performs a `Patch`, this is an atomic method, after the call either all of the
     /// purposed changes are applied or none of them. On success this method will return
     /// a trusted `Delta` which can later be used to revert the changes.  
@@ -77,6 +178,18 @@ impl State {
     pub fn perform<P: IntoIterator<Item = PatchAtom>>(
         &mut self,
         patch: P,
+    ) -> Result<Delta, Vec<PatchConflict>> {
+        self.perform_with_schema(patch, &Schema::default())
+    }
+
+    /// Performs a `Patch` just like `perform`, but also enforces the constraints of
+    /// the schema: the default values are filled in for the inserted objects, the
+    /// invalid values are reported as `PatchConflict::InvalidValue` and the writes
+    /// to the last-write-wins fields never conflict, unlike the requirements.
+    pub fn perform_with_schema<P: IntoIterator<Item = PatchAtom>>(
+        &mut self,
+        patch: P,
+        schema: &Schema,
     ) -> Result<Delta, Vec<PatchConflict>> {
         let iter = patch.into_iter();
         let mut revert_builder = RevertDeltaBuilder::with_capacity(iter.size_hint().0 / 2);
@@ -100,7 +213,11 @@ impl State {
                         }
                     }
                 }
-                PatchAtom::Insert { oid, data, version } => {
+                PatchAtom::Insert {
+                    oid,
+                    mut data,
+                    version,
+                } => {
                     match self.objects.entry(oid) {
                         Entry::Occupied(_) => {
                             // ID-conflict.
@@ -111,10 +228,13 @@ impl State {
                             if let Some(obj) = revert_builder.delete(oid) {
                                 // don't trust the user in this case.
                                 entry.insert(obj);
+                            } else if let Err(field) = schema.prepare_insert(&mut data) {
+                                perform = false;
+                                conflicts.push(PatchConflict::InvalidValue { oid, field });
                             } else {
                                 entry.insert(Object {
                                     version: version.unwrap_or(0),
-                                    data: data.clone(),
+                                    data,
                                 });
                             }
                         }
@@ -144,7 +264,17 @@ impl State {
                         Some(obj) => {
                             match get_field(&obj.data, field) {
                                 v if v == &target => { /* Already there */ }
-                                v if v == &current => {
+                                _ if !schema.accepts(&obj.data, field, &target) => {
+                                    perform = false;
+                                    conflicts.push(PatchConflict::InvalidValue { oid, field });
+                                }
+                                // A `require` is a CAS whose target is the current
+                                // value, it is never overridden.
+                                v if v == &current
+                                    || (current != target
+                                        && schema.merge_policy(&obj.data, field)
+                                            == MergePolicy::Lww) =>
+                                {
                                     if perform {
                                         let prev = set_field(&mut obj.data, field, target.clone());
                                         if revert_builder.set(oid, field, prev) {
@@ -337,3 +467,54 @@ impl RevertDeltaBuilder {
         true
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn id(n: u8) -> ObjectId {
+        let mut bytes = [0; 16];
+        bytes[15] = n;
+        Hash16::from(bytes)
+    }
+
+    fn insert(oid: ObjectId, data: Vec<PrimitiveValue>) -> PatchAtom {
+        PatchAtom::Insert {
+            oid,
+            data,
+            version: None,
+        }
+    }
+
+    #[test]
+    fn diff() {
+        let u = PrimitiveValue::U32;
+        let mut state = State::default();
+        state
+            .perform(vec![
+                insert(id(1), vec![u(0), "A".into()]),
+                insert(id(2), vec![u(0), "B".into()]),
+                insert(id(3), vec![u(0), "C".into()]),
+            ])
+            .unwrap();
+        let mut target = State::default();
+        target
+            .perform(vec![
+                insert(id(1), vec![u(0), "A".into()]),
+                insert(id(2), vec![u(0), "B".into(), u(7)]),
+                insert(id(4), vec![u(0), "D".into()]),
+            ])
+            .unwrap();
+
+        let delta = state.diff(&target);
+        assert_eq!(delta.len(), 3);
+        assert!(matches!(delta[&id(3)], DeltaEntry::Deleted));
+        assert!(matches!(delta[&id(4)], DeltaEntry::Inserted { .. }));
+        assert!(delta[&id(2)].touches(Some(2)));
+        assert!(!delta[&id(2)].touches(Some(1)));
+
+        state.apply_delta_trusted(delta);
+        assert!(state.diff(&target).is_empty());
+        assert_eq!(state.get(&id(2)).unwrap().get(2), &u(7));
+    }
+}
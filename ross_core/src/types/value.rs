@@ -1,8 +1,10 @@
 use crate::utils::hash::Hash16;
 use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::cmp::Ordering;
 use std::fmt;
 use std::fmt::Formatter;
 use std::marker::PhantomData;
+use std::str::FromStr;
 
 #[derive(Debug, Clone)]
 pub enum PrimitiveValue {
@@ -13,6 +15,33 @@ pub enum PrimitiveValue {
     Float(f64),
     Hash16(Hash16),
     String(Box<str>),
+    I64(i64),
+    Decimal(Decimal),
+}
+
+/// A fixed-point decimal number which is equal to `mantissa * 10^-scale`, it is
+/// used for values that can not tolerate float rounding. (e.g prices)
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Decimal {
+    pub mantissa: i64,
+    pub scale: u8,
+}
+
+/// Type of a primitive value as declared in the schema, this is the server-side
+/// counterpart of the compiler's `ast::PrimitiveType`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PrimitiveType {
+    Null,
+    Bool,
+    Str,
+    /// The loosely typed number, it accepts both `U32` and `Float`.
+    Num,
+    Hash,
+    U32,
+    I64,
+    F64,
+    /// A decimal with at most the given number of fractional digits.
+    Decimal(u8),
 }
 
 impl PartialEq<PrimitiveValue> for PrimitiveValue {
@@ -25,6 +54,8 @@ impl PartialEq<PrimitiveValue> for PrimitiveValue {
             (PrimitiveValue::Float(a), PrimitiveValue::Float(b)) => a == b,
             (PrimitiveValue::Hash16(a), PrimitiveValue::Hash16(b)) => a == b,
             (PrimitiveValue::String(a), PrimitiveValue::String(b)) => a == b,
+            (PrimitiveValue::I64(a), PrimitiveValue::I64(b)) => a == b,
+            (PrimitiveValue::Decimal(a), PrimitiveValue::Decimal(b)) => a == b,
             (PrimitiveValue::U32(a), PrimitiveValue::Float(b)) => (*a as f64) == *b,
             (PrimitiveValue::Float(a), PrimitiveValue::U32(b)) => *a == (*b as f64),
             _ => false,
@@ -34,6 +65,157 @@ impl PartialEq<PrimitiveValue> for PrimitiveValue {
 
 impl Eq for PrimitiveValue {}
 
+impl PrimitiveValue {
+    /// Returns true if this value can be stored in a field of the given type,
+    /// `U32` is accepted for all of the numeric types since it is what a small
+    /// integer is decoded to in the JSON form.
+    pub fn is_of(&self, ty: &PrimitiveType) -> bool {
+        match (ty, self) {
+            (PrimitiveType::Null, PrimitiveValue::Null) => true,
+            (PrimitiveType::Bool, PrimitiveValue::True) => true,
+            (PrimitiveType::Bool, PrimitiveValue::False) => true,
+            (PrimitiveType::Str, PrimitiveValue::String(_)) => true,
+            (PrimitiveType::Hash, PrimitiveValue::Hash16(_)) => true,
+            (PrimitiveType::Num, PrimitiveValue::U32(_)) => true,
+            (PrimitiveType::Num, PrimitiveValue::Float(_)) => true,
+            (PrimitiveType::U32, PrimitiveValue::U32(_)) => true,
+            (PrimitiveType::I64, PrimitiveValue::I64(_)) => true,
+            (PrimitiveType::I64, PrimitiveValue::U32(_)) => true,
+            (PrimitiveType::F64, PrimitiveValue::Float(_)) => true,
+            (PrimitiveType::F64, PrimitiveValue::U32(_)) => true,
+            (PrimitiveType::Decimal(scale), PrimitiveValue::Decimal(d)) => d.scale <= *scale,
+            (PrimitiveType::Decimal(_), PrimitiveValue::U32(_)) => true,
+            _ => false,
+        }
+    }
+
+    /// Compare two numbers of any of the numeric types, returns `None` if any
+    /// of the values is not a number.
+    pub fn cmp_number(&self, other: &PrimitiveValue) -> Option<Ordering> {
+        match (self.as_decimal(), other.as_decimal()) {
+            (Some(a), Some(b)) => {
+                let scale = a.scale.max(b.scale);
+                match (a.rescale(scale), b.rescale(scale)) {
+                    (Some(a), Some(b)) => Some(a.cmp(&b)),
+                    _ => a.as_f64().partial_cmp(&b.as_f64()),
+                }
+            }
+            _ => self.as_f64()?.partial_cmp(&other.as_f64()?),
+        }
+    }
+
+    /// The exact value of an integer or a decimal.
+    fn as_decimal(&self) -> Option<Decimal> {
+        match self {
+            PrimitiveValue::U32(n) => Some(Decimal {
+                mantissa: *n as i64,
+                scale: 0,
+            }),
+            PrimitiveValue::I64(n) => Some(Decimal {
+                mantissa: *n,
+                scale: 0,
+            }),
+            PrimitiveValue::Decimal(d) => Some(*d),
+            _ => None,
+        }
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        match self {
+            PrimitiveValue::Float(n) => Some(*n),
+            value => value.as_decimal().map(|d| d.as_f64()),
+        }
+    }
+}
+
+impl Decimal {
+    /// Returns the mantissa of this number when it is rescaled to the given
+    /// scale, or `None` if it does not fit in the scale without losing digits
+    /// or overflows.
+    pub fn rescale(&self, scale: u8) -> Option<i64> {
+        if scale >= self.scale {
+            10i64
+                .checked_pow((scale - self.scale) as u32)
+                .and_then(|m| self.mantissa.checked_mul(m))
+        } else {
+            let d = 10i64.checked_pow((self.scale - scale) as u32)?;
+            if self.mantissa % d == 0 {
+                Some(self.mantissa / d)
+            } else {
+                None
+            }
+        }
+    }
+
+    /// The nearest float to this number.
+    pub fn as_f64(&self) -> f64 {
+        self.mantissa as f64 / 10f64.powi(self.scale as i32)
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Decimal) -> bool {
+        // 1.50 and 1.5 are the same number.
+        let scale = self.scale.max(other.scale);
+        match (self.rescale(scale), other.rescale(scale)) {
+            (Some(a), Some(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Decimal {}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.scale == 0 {
+            return write!(f, "{}", self.mantissa);
+        }
+        let digits = format!(
+            "{:0>width$}",
+            (self.mantissa as i128).abs(),
+            width = self.scale as usize + 1
+        );
+        let (int, frac) = digits.split_at(digits.len() - self.scale as usize);
+        let sign = if self.mantissa < 0 { "-" } else { "" };
+        write!(f, "{}{}.{}", sign, int, frac)
+    }
+}
+
+#[derive(Debug)]
+pub struct DecimalParseError;
+
+impl fmt::Display for DecimalParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid decimal number.")
+    }
+}
+
+impl std::error::Error for DecimalParseError {}
+
+impl FromStr for Decimal {
+    type Err = DecimalParseError;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let (int, frac) = match string.find('.') {
+            Some(i) => (&string[..i], &string[i + 1..]),
+            None => (string, ""),
+        };
+        let digits = |s: &str| s.bytes().all(|c| c.is_ascii_digit());
+        let unsigned = int.strip_prefix('-').unwrap_or(int);
+        if unsigned.is_empty() || !digits(unsigned) || !digits(frac) || frac.len() > 18 {
+            return Err(DecimalParseError);
+        }
+        let mantissa = format!("{}{}", int, frac)
+            .parse::<i64>()
+            .map_err(|_| DecimalParseError)?;
+        Ok(Decimal {
+            mantissa,
+            scale: frac.len() as u8,
+        })
+    }
+}
+
 impl From<bool> for PrimitiveValue {
     #[inline]
     fn from(value: bool) -> Self {
@@ -84,6 +266,20 @@ impl From<Hash16> for PrimitiveValue {
     }
 }
 
+impl From<i64> for PrimitiveValue {
+    #[inline]
+    fn from(value: i64) -> Self {
+        PrimitiveValue::I64(value)
+    }
+}
+
+impl From<Decimal> for PrimitiveValue {
+    #[inline]
+    fn from(value: Decimal) -> Self {
+        PrimitiveValue::Decimal(value)
+    }
+}
+
 impl<T: Into<PrimitiveValue>> From<Option<T>> for PrimitiveValue {
     #[inline]
     fn from(value: Option<T>) -> Self {
@@ -109,6 +305,17 @@ impl Serialize for PrimitiveValue {
                 PrimitiveValue::Float(n) => serializer.serialize_f64(*n),
                 PrimitiveValue::Hash16(h) => h.serialize(serializer),
                 PrimitiveValue::String(s) => serializer.serialize_str(&s),
+                // JSON numbers can not hold all of the 64-bit integers and decimals
+                // without rounding, so these are sent as tagged strings.
+                PrimitiveValue::I64(n) => {
+                    serializer.serialize_newtype_variant("PrimitiveValue", 7, "i64", &n.to_string())
+                }
+                PrimitiveValue::Decimal(d) => serializer.serialize_newtype_variant(
+                    "PrimitiveValue",
+                    8,
+                    "decimal",
+                    &d.to_string(),
+                ),
             }
         } else {
             match &self {
@@ -133,6 +340,12 @@ impl Serialize for PrimitiveValue {
                 PrimitiveValue::String(value) => {
                     serializer.serialize_newtype_variant("PrimitiveValue", 6, "String", value)
                 }
+                PrimitiveValue::I64(value) => {
+                    serializer.serialize_newtype_variant("PrimitiveValue", 7, "I64", value)
+                }
+                PrimitiveValue::Decimal(value) => {
+                    serializer.serialize_newtype_variant("PrimitiveValue", 8, "Decimal", value)
+                }
             }
         }
     }
@@ -204,6 +417,31 @@ impl<'de> Deserialize<'de> for PrimitiveValue {
                 return Ok(PrimitiveValue::Null);
             }
 
+            #[derive(Deserialize)]
+            enum Tagged {
+                #[serde(rename = "i64")]
+                I64(String),
+                #[serde(rename = "decimal")]
+                Decimal(String),
+            }
+
+            if let Ok(tagged) =
+                <Tagged as Deserialize>::deserialize(serde::private::de::ContentRefDeserializer::<
+                    D::Error,
+                >::new(&content))
+            {
+                return match tagged {
+                    Tagged::I64(s) => s
+                        .parse::<i64>()
+                        .map(PrimitiveValue::I64)
+                        .map_err(de::Error::custom),
+                    Tagged::Decimal(s) => s
+                        .parse::<Decimal>()
+                        .map(PrimitiveValue::Decimal)
+                        .map_err(de::Error::custom),
+                };
+            }
+
             Err(de::Error::custom(
                 "data did not match any variant of PrimitiveValue",
             ))
@@ -217,6 +455,8 @@ impl<'de> Deserialize<'de> for PrimitiveValue {
                 field4,
                 field5,
                 field6,
+                field7,
+                field8,
             }
 
             struct FieldVisitor;
@@ -237,9 +477,11 @@ impl<'de> Deserialize<'de> for PrimitiveValue {
                         4u64 => Ok(Field::field4),
                         5u64 => Ok(Field::field5),
                         6u64 => Ok(Field::field6),
+                        7u64 => Ok(Field::field7),
+                        8u64 => Ok(Field::field8),
                         _ => Err(serde::de::Error::invalid_value(
                             serde::de::Unexpected::Unsigned(value),
-                            &"variant index 0 <= i < 7",
+                            &"variant index 0 <= i < 9",
                         )),
                     }
                 }
@@ -315,11 +557,20 @@ impl<'de> Deserialize<'de> for PrimitiveValue {
                             serde::de::VariantAccess::newtype_variant::<Box<str>>(variant),
                             PrimitiveValue::String,
                         ),
+                        (Field::field7, variant) => Result::map(
+                            serde::de::VariantAccess::newtype_variant::<i64>(variant),
+                            PrimitiveValue::I64,
+                        ),
+                        (Field::field8, variant) => Result::map(
+                            serde::de::VariantAccess::newtype_variant::<Decimal>(variant),
+                            PrimitiveValue::Decimal,
+                        ),
                     }
                 }
             }
-            const VARIANTS: &'static [&'static str] =
-                &["Null", "True", "False", "U32", "Float", "Hash16", "String"];
+            const VARIANTS: &'static [&'static str] = &[
+                "Null", "True", "False", "U32", "Float", "Hash16", "String", "I64", "Decimal",
+            ];
             serde::Deserializer::deserialize_enum(
                 deserializer,
                 "PrimitiveValue",
@@ -335,8 +586,9 @@ impl<'de> Deserialize<'de> for PrimitiveValue {
 
 #[cfg(test)]
 mod test {
-    use super::{Hash16, PrimitiveValue};
+    use super::{Decimal, Hash16, PrimitiveType, PrimitiveValue};
     use bincode::Options;
+    use std::cmp::Ordering;
 
     macro_rules! json {
         ($value:expr) => {{
@@ -423,5 +675,77 @@ mod test {
             "\"ffffffffffffffffffffffffffffffff\""
         );
         same!(7, PrimitiveValue::String("Hello".into()), "\"Hello\"");
+        same!(2, PrimitiveValue::I64(-1), "{\"i64\":\"-1\"}");
+        same!(
+            10,
+            PrimitiveValue::I64(std::i64::MAX),
+            format!("{{\"i64\":\"{}\"}}", std::i64::MAX)
+        );
+        same!(
+            5,
+            PrimitiveValue::Decimal(Decimal {
+                mantissa: 1250,
+                scale: 2
+            }),
+            "{\"decimal\":\"12.50\"}"
+        );
+        same!(
+            3,
+            PrimitiveValue::Decimal(Decimal {
+                mantissa: -5,
+                scale: 3
+            }),
+            "{\"decimal\":\"-0.005\"}"
+        );
+    }
+
+    #[test]
+    fn decimal() {
+        let d = |s: &str| s.parse::<Decimal>().unwrap();
+        assert_eq!(d("1.50"), d("1.5"));
+        assert_eq!(d("-0.25").mantissa, -25);
+        assert_eq!(d("7").scale, 0);
+        assert_ne!(d("0.1"), d("0.01"));
+        assert_eq!(d("100.10").to_string(), "100.10");
+        assert!("1.".parse::<Decimal>().is_ok());
+        assert!(".5".parse::<Decimal>().is_err());
+        assert!("1.2.3".parse::<Decimal>().is_err());
+        assert!("99999999999999999999".parse::<Decimal>().is_err());
+    }
+
+    #[test]
+    fn is_of() {
+        let d = |s: &str| PrimitiveValue::Decimal(s.parse().unwrap());
+        assert!(PrimitiveValue::U32(5).is_of(&PrimitiveType::Num));
+        assert!(PrimitiveValue::Float(0.5).is_of(&PrimitiveType::Num));
+        assert!(!PrimitiveValue::Float(0.5).is_of(&PrimitiveType::U32));
+        assert!(PrimitiveValue::I64(-5).is_of(&PrimitiveType::I64));
+        assert!(!PrimitiveValue::I64(-5).is_of(&PrimitiveType::U32));
+        assert!(d("1.25").is_of(&PrimitiveType::Decimal(2)));
+        assert!(!d("1.255").is_of(&PrimitiveType::Decimal(2)));
+        assert!(!d("1.25").is_of(&PrimitiveType::F64));
+        assert!(!PrimitiveValue::Null.is_of(&PrimitiveType::Str));
+    }
+
+    #[test]
+    fn cmp_number() {
+        let d = |s: &str| PrimitiveValue::Decimal(s.parse().unwrap());
+        assert_eq!(d("1.5").cmp_number(&d("1.50")), Some(Ordering::Equal));
+        assert_eq!(
+            d("-0.01").cmp_number(&PrimitiveValue::U32(0)),
+            Some(Ordering::Less)
+        );
+        assert_eq!(
+            PrimitiveValue::I64(std::i64::MAX).cmp_number(&PrimitiveValue::I64(std::i64::MAX - 1)),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(
+            PrimitiveValue::Float(2.5).cmp_number(&d("2.25")),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(
+            PrimitiveValue::Null.cmp_number(&PrimitiveValue::U32(0)),
+            None
+        );
     }
 }
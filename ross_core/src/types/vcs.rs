@@ -2,6 +2,7 @@
 use super::Timestamp;
 use crate::utils::hash::*;
 use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
 
 /// An opaque type that represents a User UUID.
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
@@ -33,6 +34,35 @@ pub struct CommitIdentifier {
     pub hash: CommitHash,
 }
 
+/// A tag name, prefixed by the repository id.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
+pub struct TagIdentifier {
+    pub repository: RepositoryId,
+    pub name: String,
+}
+
+/// A stable name for a commit, a lightweight tag has no annotation.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TagInfo {
+    pub commit: CommitIdentifier,
+    pub annotation: Option<TagAnnotation>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TagAnnotation {
+    pub tagger: UserId,
+    pub message: String,
+    pub time: Timestamp,
+}
+
+/// A point in the history that can be checked out, the state of a branch also
+/// includes its live changes.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Revision {
+    Commit(CommitIdentifier),
+    Branch(BranchIdentifier),
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RepositoryInfo {
     pub owner: UserId,
@@ -109,3 +139,12 @@ pub struct CommitInfo {
     pub authors: Vec<UserId>,
     pub message: String,
 }
+
+impl CommitInfo {
+    /// The hash of a commit is the SHA-1 of its information.
+    pub fn hash(&self) -> CommitHash {
+        let mut hash = [0; 20];
+        hash.copy_from_slice(&Sha1::digest(&bincode::serialize(self).unwrap()));
+        CommitHash(Hash20::from(hash))
+    }
+}
@@ -1,52 +1,61 @@
-use bincode::{deserialize, serialize_into};
+use bincode::Options;
 
 /// Push number of new items that are already serialized into an optional already-serialized vector
 /// of elements (`existing`), if the first value is not provided a new vector with `len=0` is
 /// created and used instead.
-/// This function is used in `DB` as our RocksDB's merge operator for values of type `Vec`.
+/// This function is used in `DB` as our RocksDB's merge operator for values of type `Vec`, so the
+/// length prefix is encoded the same way as `db::bincode` does. (varint)
 #[inline]
 pub fn merge_push<'a>(existing: Option<&[u8]>, items: impl Iterator<Item = &'a [u8]>) -> Vec<u8> {
-    let mut first = true;
-    let est_count = items.size_hint().0;
+    let options = || {
+        bincode::DefaultOptions::new()
+            .with_varint_encoding()
+            .allow_trailing_bytes()
+    };
 
-    let (mut count, mut result): (u64, Vec<u8>) = match existing {
+    let (mut count, existing_items): (u64, &[u8]) = match existing {
         Some(bytes) => {
-            let c = deserialize::<u64>(bytes).unwrap();
-            (c, bytes.into())
+            let c = options().deserialize::<u64>(bytes).unwrap();
+            let len = options().serialized_size(&c).unwrap() as usize;
+            (c, &bytes[len..])
         }
-        None => (0, [0; 8].into()),
+        None => (0, &[]),
     };
 
+    let mut new_items = Vec::new();
     for buf in items {
-        if first {
-            first = false;
-            let size = est_count * buf.len();
-            result.reserve_exact(size);
-        }
-
         count += 1;
-        result.extend_from_slice(buf);
+        new_items.extend_from_slice(buf);
     }
 
-    serialize_into(&mut result[0..8], &count).unwrap();
-
+    let mut result = options().serialize(&count).unwrap();
+    result.reserve_exact(existing_items.len() + new_items.len());
+    result.extend_from_slice(existing_items);
+    result.extend_from_slice(&new_items);
     result
 }
 
 #[cfg(test)]
 mod test {
     use super::merge_push;
-    use bincode::{deserialize, serialize};
+    use bincode::Options;
     use serde::{Deserialize, Serialize};
 
     #[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
     struct Item(i32, i32);
 
+    fn serialize<T: Serialize>(value: &T) -> Vec<u8> {
+        bincode::DefaultOptions::new()
+            .with_varint_encoding()
+            .serialize(value)
+            .unwrap()
+    }
+
     fn run_test(existing: Option<Vec<Item>>, mut items: Vec<Item>) {
         let items_vec = {
             let mut result = Vec::with_capacity(items.len());
             for item in &items {
-                let v = serialize(item).unwrap();
+                let v = serialize(item);
                 result.push(v);
             }
             result
@@ -60,10 +69,13 @@ mod test {
             result
         };
 
-        let existing_serialized = existing.clone().map(|v| serialize(&v).unwrap());
+        let existing_serialized = existing.clone().map(|v| serialize(&v));
         let result_serialized =
             merge_push(existing_serialized.as_deref(), items_serialized.into_iter());
-        let result_decoded = deserialize::<Vec<Item>>(&result_serialized).unwrap();
+        let result_decoded = bincode::DefaultOptions::new()
+            .with_varint_encoding()
+            .deserialize::<Vec<Item>>(&result_serialized)
+            .unwrap();
 
         let mut result = Vec::new();
         if let Some(mut e) = existing {
@@ -85,5 +97,7 @@ mod test {
             Some(vec![Item(17, 9), Item(5, 27)]),
             vec![Item(12, 13), Item(8, 7)],
         );
+        // The length prefix grows from one byte to three bytes.
+        run_test(Some(vec![Item(1, 2); 250]), vec![Item(3, 4); 10]);
     }
 }
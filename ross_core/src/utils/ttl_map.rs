@@ -43,6 +43,12 @@ impl<K: Copy + Hash + Eq, V> TTLMap<K, V> {
         self.data.contains_key(key)
     }
 
+    /// Returns the element with the given key without changing its expiration.
+    #[inline]
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.data.get(key).map(|entry| &entry.value)
+    }
+
     /// Return the element from the map with the given key or insert the one
     /// returned by the provided closure, the closure may fail in that case
     /// the error returned by the closure will be returned.
@@ -79,12 +85,7 @@ impl<K: Copy + Hash + Eq, V> TTLMap<K, V> {
     #[inline]
     pub fn drop_item(&mut self, key: K, now: clock::Timestamp) {
         if self.ttl == 0 {
-            if let Some(entry) = self.data.remove(&key) {
-                if let Some(e) = entry.expiration {
-                    cancel_drop(&mut self.drop_queue, &key, e);
-                    self.to_drop_count -= 1;
-                }
-            }
+            self.remove(&key);
         } else {
             let expiration = now + self.ttl;
             if let Some(data) = self.data.get_mut(&key) {
@@ -105,6 +106,23 @@ impl<K: Copy + Hash + Eq, V> TTLMap<K, V> {
         }
     }
 
+    /// Remove the element with the given key right away.
+    #[inline]
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let entry = self.data.remove(key)?;
+        if let Some(e) = entry.expiration {
+            cancel_drop(&mut self.drop_queue, key, e);
+            self.to_drop_count -= 1;
+        }
+        Some(entry.value)
+    }
+
+    /// Iterate over the keys and the values in an arbitrary order.
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.data.iter().map(|(key, entry)| (key, &entry.value))
+    }
+
     /// Force run the garbage collector.
     #[inline]
     pub fn gc(&mut self, now: clock::Timestamp) {
@@ -230,4 +248,19 @@ mod test {
         assert_eq!(map.len(), 1);
         assert_eq!(map.to_drop_count, 0);
     }
+
+    #[test]
+    fn remove() {
+        let mut map = TTLMap::<i32, i32>::new(4, 10);
+        map.get_or_maybe_insert_with(0, || -> Result<i32, MapError> { Ok(1) })
+            .unwrap();
+        map.get_or_maybe_insert_with(1, || -> Result<i32, MapError> { Ok(2) })
+            .unwrap();
+        map.drop_item(0, 1);
+        assert_eq!(map.to_drop_count, 1);
+        assert_eq!(map.remove(&0), Some(1));
+        assert_eq!(map.remove(&0), None);
+        assert_eq!(map.to_drop_count, 0);
+        assert_eq!(map.iter().collect::<Vec<_>>(), vec![(&1, &2)]);
+    }
 }